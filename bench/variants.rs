@@ -1,9 +1,11 @@
 use criterion::{
   black_box, criterion_group, criterion_main, measurement::Measurement, BenchmarkGroup,
-  Criterion, Throughput,
+  BenchmarkId, Criterion, Throughput,
 };
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
-use simd_adler32::imp::{avx2, avx512, neon, scalar, sse2, ssse3, wasm, Adler32Imp};
+use simd_adler32::imp::{
+  avx2, avx512, neon, portable, scalar, sse2, ssse3, wasm, Adler32Imp, ScanImp, MOD,
+};
 
 pub fn bench(c: &mut Criterion) {
   let mut data = [0; 100_000];
@@ -11,31 +13,95 @@ pub fn bench(c: &mut Criterion) {
 
   SmallRng::from_entropy().fill_bytes(&mut data[..]);
 
+  for (name, update) in variants() {
+    bench_variant(&mut group, name, &data, update);
+  }
+}
+
+/// Compares the variants with `scalar::update_short` on short inputs, used to pick
+/// `imp::SHORT_LEN`. Lengths between one and two blocks measure the tail handling.
+pub fn bench_short(c: &mut Criterion) {
+  let mut data = [0; 256];
+  let mut group = c.benchmark_group("short");
+
+  SmallRng::from_entropy().fill_bytes(&mut data[..]);
+
+  for &len in &[
+    4, 8, 16, 24, 32, 33, 36, 40, 44, 48, 52, 56, 60, 63, 64, 96, 128, 256,
+  ] {
+    let data = &data[..len];
+
+    group.throughput(Throughput::Bytes(len as u64));
+    group.bench_with_input(BenchmarkId::new("short", len), data, |b, data| {
+      b.iter(|| black_box(scalar::update_short(1, 0, data, MOD)))
+    });
+
+    for (name, update) in variants() {
+      group.bench_with_input(BenchmarkId::new(name, len), data, |b, data| {
+        b.iter(|| black_box(update(1, 0, data)))
+      });
+    }
+  }
+}
+
+/// Compares the window scan implementations used by the chunker, with a mask no window
+/// meets so every window of the input is rolled.
+pub fn bench_scan(c: &mut Criterion) {
+  let mut data = [0; 100_000];
+  let mut group = c.benchmark_group("scan");
+
+  SmallRng::from_entropy().fill_bytes(&mut data[..]);
+
+  let mut variants = vec![("scalar", scalar::scan as ScanImp)];
+  let imps = [
+    ("avx2", avx2::get_scan_imp()),
+    ("sse2", sse2::get_scan_imp()),
+    ("wasm", wasm::get_scan_imp()),
+    ("neon", neon::get_scan_imp()),
+  ];
+  variants.extend(imps.iter().filter_map(|&(name, scan)| scan.map(|scan| (name, scan))));
+
+  group.throughput(Throughput::Bytes(data.len() as u64));
+  for (name, scan) in variants {
+    group.bench_with_input(BenchmarkId::new(name, data.len()), &data[..], |b, data| {
+      b.iter(|| black_box(scan(0, 0, data, 64, 31, 0xffff_ffff)))
+    });
+  }
+}
+
+fn variants() -> Vec<(&'static str, Adler32Imp)> {
+  let mut variants = Vec::new();
+
   if let Some(update) = avx512::get_imp() {
-    bench_variant(&mut group, "avx512", &data, update);
+    variants.push(("avx512", update));
   }
 
   if let Some(update) = avx2::get_imp() {
-    bench_variant(&mut group, "avx2", &data, update);
+    variants.push(("avx2", update));
   }
 
   if let Some(update) = ssse3::get_imp() {
-    bench_variant(&mut group, "ssse3", &data, update);
+    variants.push(("ssse3", update));
   }
 
   if let Some(update) = sse2::get_imp() {
-    bench_variant(&mut group, "sse2", &data, update);
+    variants.push(("sse2", update));
   }
 
   if let Some(update) = wasm::get_imp() {
-    bench_variant(&mut group, "wasm", &data, update);
+    variants.push(("wasm", update));
   }
 
   if let Some(update) = neon::get_imp() {
-    bench_variant(&mut group, "neon", &data, update);
+    variants.push(("neon", update));
+  }
+
+  if let Some(update) = portable::get_imp() {
+    variants.push(("portable", update));
   }
 
-  bench_variant(&mut group, "scalar", &data, scalar::update);
+  variants.push(("scalar", scalar::update as Adler32Imp));
+  variants
 }
 
 fn bench_variant<M>(g: &mut BenchmarkGroup<M>, name: &str, data: &[u8], imp: Adler32Imp)
@@ -61,5 +127,5 @@ where
   );
 }
 
-criterion_group!(benches, bench);
+criterion_group!(benches, bench, bench_short, bench_scan);
 criterion_main!(benches);
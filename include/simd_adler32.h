@@ -0,0 +1,62 @@
+/*
+ * simd-adler32 C API
+ *
+ * zlib compatible Adler-32 functions backed by the SIMD implementations of the
+ * simd-adler32 crate, built with its `capi` feature. Define SIMD_ADLER32_ZLIB_NAMES
+ * before including this header to call them by their zlib names.
+ *
+ * Generated from src/capi.rs by cbindgen, see cbindgen.toml.
+ */
+
+#ifndef SIMD_ADLER32_H
+#define SIMD_ADLER32_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef SIMD_ADLER32_ZLIB_NAMES
+#define adler32 simd_adler32
+#define adler32_z simd_adler32_z
+#define adler32_combine simd_adler32_combine
+#define adler32_combine64 simd_adler32_combine64
+#endif
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Updates the running Adler-32 `adler` with `len` bytes of `buf`, returns `1` if `buf`
+ * is null.
+ *
+ * # Safety
+ * Unless null, `buf` must be valid for reads of `len` bytes.
+ */
+unsigned long simd_adler32(unsigned long adler, const unsigned char *buf, unsigned int len);
+
+/**
+ * Updates the running Adler-32 `adler` with `len` bytes of `buf`, returns `1` if `buf`
+ * is null.
+ *
+ * # Safety
+ * Unless null, `buf` must be valid for reads of `len` bytes.
+ */
+unsigned long simd_adler32_z(unsigned long adler, const unsigned char *buf, size_t len);
+
+/**
+ * Combines the Adler-32 of two sequences, `len2` is the length of the second sequence.
+ * Returns `0xffffffff` if `len2` is negative.
+ */
+unsigned long simd_adler32_combine(unsigned long adler1, unsigned long adler2, long len2);
+
+/**
+ * Combines the Adler-32 of two sequences, `len2` is the length of the second sequence.
+ * Returns `0xffffffff` if `len2` is negative.
+ */
+unsigned long simd_adler32_combine64(unsigned long adler1, unsigned long adler2, int64_t len2);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SIMD_ADLER32_H */
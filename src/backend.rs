@@ -0,0 +1,334 @@
+//! Statically dispatched Adler-32.
+//!
+//! [`Adler32`](crate::Adler32) resolves its implementation at runtime and calls it
+//! through a function pointer. [`Adler32With`] is generic over a [`Backend`] and calls the
+//! implementation directly instead, so it can be inlined into code built with
+//! `-C target-cpu` or into functions enabling the same `#[target_feature]`.
+//!
+//! Backend types only exist on targets their implementation is built for, [`Scalar`]
+//! exists on every target.
+//!
+//! # Examples
+//! ```rust
+//! use simd_adler32::backend::{Adler32With, Scalar};
+//!
+//! let mut adler = Adler32With::new(Scalar::new());
+//! adler.write(b"rust is pretty cool, man");
+//!
+//! assert_eq!(adler.finish(), 1921255656);
+//! ```
+//!
+//! Multiversioning with `#[target_feature]`:
+//! ```rust
+//! # #[cfg(all(
+//! #   any(target_arch = "x86", target_arch = "x86_64"),
+//! #   any(feature = "std", target_feature = "avx2")
+//! # ))]
+//! # {
+//! use simd_adler32::adler32;
+//! use simd_adler32::backend::{Adler32With, Avx2, Backend};
+//!
+//! #[target_feature(enable = "avx2")]
+//! unsafe fn checksums(avx2: Avx2, blocks: &[&[u8]]) -> Vec<u32> {
+//!   blocks
+//!     .iter()
+//!     .map(|block| {
+//!       let mut adler = Adler32With::new(avx2);
+//!       adler.write(block);
+//!       adler.finish()
+//!     })
+//!     .collect()
+//! }
+//!
+//! if let Some(avx2) = Avx2::detect() {
+//!   let sums = unsafe { checksums(avx2, &[b"Wiki", b"pedia"]) };
+//!   assert_eq!(sums, [adler32(&&b"Wiki"[..]), adler32(&&b"pedia"[..])]);
+//! }
+//! # }
+//! ```
+use crate::imp::{scalar, MOD, SHORT_LEN};
+
+pub use crate::imp::scalar::Scalar;
+
+#[cfg(all(
+  feature = "nightly",
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(
+    feature = "std",
+    all(target_feature = "avx512f", target_feature = "avx512bw")
+  )
+))]
+pub use crate::imp::avx512::Avx512;
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "avx2")
+))]
+pub use crate::imp::avx2::Avx2;
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "ssse3")
+))]
+pub use crate::imp::ssse3::Ssse3;
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "sse2")
+))]
+pub use crate::imp::sse2::Sse2;
+
+#[cfg(all(
+  target_feature = "neon",
+  any(target_arch = "aarch64", feature = "nightly")
+))]
+pub use crate::imp::neon::Neon;
+
+#[cfg(all(
+  target_feature = "simd128",
+  any(
+    target_arch = "wasm32",
+    all(feature = "nightly", target_arch = "wasm64")
+  )
+))]
+pub use crate::imp::wasm::Wasm;
+
+#[cfg(all(
+  target_arch = "riscv64",
+  any(all(feature = "std", target_os = "linux"), target_feature = "v")
+))]
+pub use crate::imp::rvv::Rvv;
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(feature = "std", target_feature = "lasx")
+))]
+pub use crate::imp::loongarch::Lasx;
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(
+    feature = "std",
+    all(target_feature = "lsx", not(target_feature = "lasx"))
+  )
+))]
+pub use crate::imp::loongarch::Lsx;
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "powerpc64",
+  any(feature = "std", target_feature = "vsx")
+))]
+pub use crate::imp::vsx::Vsx;
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "s390x",
+  any(feature = "std", target_feature = "vector")
+))]
+pub use crate::imp::s390x::S390x;
+
+#[cfg(feature = "nightly")]
+pub use crate::imp::portable::Portable;
+
+/// An Adler-32 implementation for a single instruction set.
+///
+/// Values of the SIMD backends are only handed out by [`Backend::detect`], holding one
+/// proves the CPU supports its instructions.
+pub trait Backend: Copy {
+  /// Returns the backend if the CPU supports it. Without `std` only instruction sets
+  /// enabled at compile time are detected.
+  fn detect() -> Option<Self>;
+
+  /// Updates the running sums `a` and `b` with `data`.
+  fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16);
+}
+
+/// An adler32 hash generator type calling the implementation of `B` directly.
+#[derive(Clone)]
+pub struct Adler32With<B> {
+  a: u16,
+  b: u16,
+  backend: B,
+}
+
+impl<B: Backend> Adler32With<B> {
+  /// Constructs a new `Adler32With` using `backend`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::backend::{Adler32With, Scalar};
+  ///
+  /// let mut adler = Adler32With::new(Scalar::new());
+  /// ```
+  pub fn new(backend: B) -> Self {
+    Self::from_checksum(backend, 1)
+  }
+
+  /// Constructs a new `Adler32With` using `backend` and existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::backend::{Adler32With, Scalar};
+  ///
+  /// let mut adler = Adler32With::from_checksum(Scalar::new(), 0xdeadbeaf);
+  /// ```
+  pub fn from_checksum(backend: B, checksum: u32) -> Self {
+    Self {
+      a: checksum as u16,
+      b: (checksum >> 16) as u16,
+      backend,
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  #[inline(always)]
+  pub fn write(&mut self, data: &[u8]) {
+    let (a, b) = if data.len() < SHORT_LEN {
+      scalar::update_short(self.a, self.b, data, MOD)
+    } else {
+      self.backend.update(self.a, self.b, data)
+    };
+
+    self.a = a;
+    self.b = b;
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> u32 {
+    (u32::from(self.b) << 16) | u32::from(self.a)
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.a = 1;
+    self.b = 0;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Adler32With, Backend};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn scalar() {
+    assert_backend_eq::<super::Scalar>();
+  }
+
+  #[test]
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+  fn x86() {
+    #[cfg(all(
+      feature = "nightly",
+      any(
+        feature = "std",
+        all(target_feature = "avx512f", target_feature = "avx512bw")
+      )
+    ))]
+    assert_backend_eq::<super::Avx512>();
+    #[cfg(any(feature = "std", target_feature = "avx2"))]
+    assert_backend_eq::<super::Avx2>();
+    #[cfg(any(feature = "std", target_feature = "ssse3"))]
+    assert_backend_eq::<super::Ssse3>();
+    #[cfg(any(feature = "std", target_feature = "sse2"))]
+    assert_backend_eq::<super::Sse2>();
+  }
+
+  #[test]
+  #[cfg(all(
+    target_feature = "neon",
+    any(target_arch = "aarch64", feature = "nightly")
+  ))]
+  fn neon() {
+    assert_backend_eq::<super::Neon>();
+  }
+
+  #[test]
+  #[cfg(all(
+    target_feature = "simd128",
+    any(
+      target_arch = "wasm32",
+      all(feature = "nightly", target_arch = "wasm64")
+    )
+  ))]
+  fn wasm() {
+    assert_backend_eq::<super::Wasm>();
+  }
+
+  #[test]
+  #[cfg(all(
+    target_arch = "riscv64",
+    any(all(feature = "std", target_os = "linux"), target_feature = "v")
+  ))]
+  fn rvv() {
+    assert_backend_eq::<super::Rvv>();
+  }
+
+  #[test]
+  #[cfg(all(feature = "nightly", target_arch = "loongarch64"))]
+  fn loongarch() {
+    #[cfg(any(feature = "std", target_feature = "lasx"))]
+    assert_backend_eq::<super::Lasx>();
+    #[cfg(any(
+      feature = "std",
+      all(target_feature = "lsx", not(target_feature = "lasx"))
+    ))]
+    assert_backend_eq::<super::Lsx>();
+  }
+
+  #[test]
+  #[cfg(all(
+    feature = "nightly",
+    target_arch = "powerpc64",
+    any(feature = "std", target_feature = "vsx")
+  ))]
+  fn vsx() {
+    assert_backend_eq::<super::Vsx>();
+  }
+
+  #[test]
+  #[cfg(all(
+    feature = "nightly",
+    target_arch = "s390x",
+    any(feature = "std", target_feature = "vector")
+  ))]
+  fn s390x() {
+    assert_backend_eq::<super::S390x>();
+  }
+
+  #[test]
+  #[cfg(feature = "nightly")]
+  fn portable() {
+    assert_backend_eq::<super::Portable>();
+  }
+
+  fn assert_backend_eq<B: Backend>() {
+    if let Some(backend) = B::detect() {
+      let mut random = [0; 1024 * 20];
+      SmallRng::from_entropy().fill(&mut random[..]);
+
+      let mut adler = Adler32With::new(backend);
+      let mut expected = adler2::Adler32::new();
+      for chunk in random.chunks(1000).chain(random.chunks(7).take(10)) {
+        adler.write(chunk);
+        expected.write_slice(chunk);
+
+        assert_eq!(adler.finish(), expected.checksum(), "len({})", chunk.len());
+      }
+
+      let mut adler = Adler32With::from_checksum(backend, 0xdeadbeaf);
+      let mut expected = adler2::Adler32::from_checksum(0xdeadbeaf);
+      adler.write(&random);
+      expected.write_slice(&random);
+
+      assert_eq!(adler.finish(), expected.checksum());
+    }
+  }
+}
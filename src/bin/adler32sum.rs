@@ -0,0 +1,590 @@
+//! Print or check Adler-32 checksums, like `sha256sum`.
+use simd_adler32::bufread;
+use simd_adler32::imp::{self, Adler32Imp};
+use simd_adler32::manifest::{escape_name, path_bytes, Entry, Style};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::{env, process, thread};
+
+const USAGE: &str = "\
+Usage: adler32sum [OPTION]... [FILE]...
+Print or check Adler-32 checksums. With no FILE, or when FILE is -, read standard input.
+
+  -c, --check            read checksums from the FILEs and check them
+      --tag              create a BSD-style checksum
+  -z, --zero             end each output line with NUL, not newline
+  -j, --jobs N           hash up to N files in parallel
+      --backend NAME     use implementation NAME: auto, scalar, sse2, ssse3, avx2,
+                         avx512, neon, rvv, loongarch, vsx, s390x, wasm or
+                         portable
+  -q, --quiet            don't print OK for each successfully verified file
+      --status           don't output anything, status code shows success
+  -h, --help             display this help and exit
+  -V, --version          output version information and exit
+
+Short options can be combined, -cz is -c -z and -j4 is -j 4.
+";
+
+/// Buffer size of file readers.
+const BUF_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+struct Options {
+  check: bool,
+  tag: bool,
+  zero: bool,
+  quiet: bool,
+  status: bool,
+  jobs: usize,
+  backend: Option<Adler32Imp>,
+  files: Vec<OsString>,
+}
+
+/// What the command line asks for.
+enum Command {
+  Run(Options),
+  Help,
+  Version,
+}
+
+fn main() {
+  let stdout = io::stdout();
+  let stderr = io::stderr();
+
+  let code = run(
+    env::args_os().skip(1),
+    &mut stdout.lock(),
+    &mut stderr.lock(),
+  );
+
+  process::exit(code);
+}
+
+/// Runs the tool with `args` and returns the exit code.
+fn run<I: Iterator<Item = OsString>>(
+  args: I,
+  out: &mut dyn Write,
+  err: &mut dyn Write,
+) -> i32 {
+  let result = match parse_args(args) {
+    Ok(Command::Run(options)) if options.check => check(&options, out, err),
+    Ok(Command::Run(options)) => print(&options, out, err),
+    Ok(Command::Help) => out.write_all(USAGE.as_bytes()).map(|_| 0),
+    Ok(Command::Version) => {
+      writeln!(out, "adler32sum {}", env!("CARGO_PKG_VERSION")).map(|_| 0)
+    }
+    Err(message) => {
+      let _ = writeln!(err, "adler32sum: {}", message);
+      let _ = writeln!(err, "Try 'adler32sum --help' for more information.");
+      return 1;
+    }
+  };
+
+  match result.and_then(|code| out.flush().map(|_| code)) {
+    Ok(code) => code,
+    Err(error) => {
+      let _ = writeln!(err, "adler32sum: {}", error);
+      1
+    }
+  }
+}
+
+/// Parses the command line.
+fn parse_args<I: Iterator<Item = OsString>>(mut args: I) -> Result<Command, String> {
+  let mut options = Options {
+    jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+    ..Options::default()
+  };
+
+  // Rest of a group of short options, `-cz` is handled as `-c` followed by `-z`.
+  let mut group = None;
+
+  loop {
+    let arg = match group.take() {
+      Some(arg) => arg,
+      None => match args.next() {
+        Some(arg) => match arg.to_str() {
+          Some(arg) if arg.starts_with('-') && arg != "-" => arg.to_string(),
+          _ => {
+            options.files.push(arg);
+            continue;
+          }
+        },
+        None => break,
+      },
+    };
+
+    let (name, inline) = if arg.starts_with("--") {
+      match arg.find('=') {
+        Some(i) => (&arg[..i], Some(arg[i + 1..].to_string())),
+        None => (&arg[..], None),
+      }
+    } else {
+      let split = 1 + arg[1..].chars().next().map_or(0, char::len_utf8);
+      let (name, rest) = arg.split_at(split);
+
+      match rest {
+        "" => (name, None),
+        // The rest of the group is the value, `-j4` is `-j 4`.
+        _ if name == "-j" => (name, Some(rest.to_string())),
+        _ => {
+          group = Some(format!("-{}", rest));
+          (name, None)
+        }
+      }
+    };
+
+    let mut value = || {
+      inline
+        .clone()
+        .or_else(|| args.next().and_then(|value| value.into_string().ok()))
+        .ok_or_else(|| format!("option '{}' requires a value", name))
+    };
+
+    match name {
+      "-c" | "--check" => options.check = true,
+      "--tag" => options.tag = true,
+      "-z" | "--zero" => options.zero = true,
+      "-q" | "--quiet" => options.quiet = true,
+      "--status" => options.status = true,
+      "-j" | "--jobs" => {
+        let jobs = value()?;
+        options.jobs = match jobs.parse() {
+          Ok(jobs) if jobs > 0 => jobs,
+          _ => return Err(format!("invalid number of jobs '{}'", jobs)),
+        };
+      }
+      "--backend" => options.backend = backend(&value()?)?,
+      "-h" | "--help" => return Ok(Command::Help),
+      "-V" | "--version" => return Ok(Command::Version),
+      "--" => {
+        options.files.extend(args);
+        break;
+      }
+      _ => return Err(format!("unrecognized option '{}'", name)),
+    }
+  }
+
+  if options.files.is_empty() {
+    options.files.push(OsString::from("-"));
+  }
+
+  Ok(Command::Run(options))
+}
+
+/// Returns the update function of backend `name`, `None` for runtime detection.
+fn backend(name: &str) -> Result<Option<Adler32Imp>, String> {
+  let update = match name {
+    "auto" => return Ok(None),
+    "scalar" => Some(imp::scalar::update as Adler32Imp),
+    "sse2" => imp::sse2::get_imp(),
+    "ssse3" => imp::ssse3::get_imp(),
+    "avx2" => imp::avx2::get_imp(),
+    "avx512" => imp::avx512::get_imp(),
+    "neon" => imp::neon::get_imp(),
+    "rvv" => imp::rvv::get_imp(),
+    "loongarch" => imp::loongarch::get_imp(),
+    "vsx" => imp::vsx::get_imp(),
+    "s390x" => imp::s390x::get_imp(),
+    "wasm" => imp::wasm::get_imp(),
+    "portable" => imp::portable::get_imp(),
+    _ => return Err(format!("unknown backend '{}'", name)),
+  };
+
+  match update {
+    Some(update) => Ok(Some(update)),
+    None => Err(format!("backend '{}' is not supported on this CPU", name)),
+  }
+}
+
+/// Prints the checksums of the input files.
+fn print(options: &Options, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+  let mut code = 0;
+  let end = if options.zero { b'\0' } else { b'\n' };
+  let style = if options.tag { Style::Bsd } else { Style::Gnu };
+
+  hash_files(&options.files, options, |i, result| {
+    let path = Path::new(&options.files[i]);
+
+    match result {
+      Ok(checksum) => {
+        let mut line = Entry::new(path, checksum).to_line(style, !options.zero);
+        line.push(end);
+
+        out.write_all(&line)
+      }
+      Err(error) => {
+        code = 1;
+        report(err, path, &error)
+      }
+    }
+  })?;
+
+  Ok(code)
+}
+
+/// Checks the checksums listed in the input files.
+fn check(options: &Options, out: &mut dyn Write, err: &mut dyn Write) -> io::Result<i32> {
+  let mut code = 0;
+  let separator = if options.zero { b'\0' } else { b'\n' };
+
+  for list in &options.files {
+    let mut data = Vec::new();
+    if let Err(error) = open(list).and_then(|mut reader| reader.read_to_end(&mut data)) {
+      report(err, Path::new(list), &error)?;
+      code = 1;
+      continue;
+    }
+
+    let mut entries = Vec::new();
+    let mut invalid = 0;
+    for line in data.split(|&byte| byte == separator) {
+      match Entry::parse(line) {
+        Some(entry) => entries.push(entry),
+        None if line.is_empty() || line == b"\r" => {}
+        None => invalid += 1,
+      }
+    }
+
+    if entries.is_empty() {
+      err.write_all(b"adler32sum: ")?;
+      err.write_all(&path_bytes(Path::new(list)))?;
+      err.write_all(b": no properly formatted checksum lines found\n")?;
+      code = 1;
+      continue;
+    }
+
+    let paths: Vec<_> = entries
+      .iter()
+      .map(|entry| entry.path.clone().into_os_string())
+      .collect();
+    let (mut unreadable, mut mismatched) = (0, 0);
+
+    hash_files(&paths, options, |i, result| {
+      let Entry { checksum, ref path } = entries[i];
+      let status: &[u8] = match result {
+        Ok(actual) if actual == checksum => b"OK",
+        Ok(_) => {
+          mismatched += 1;
+          b"FAILED"
+        }
+        Err(error) => {
+          unreadable += 1;
+          if !options.status {
+            report(err, path, &error)?;
+          }
+          b"FAILED open or read"
+        }
+      };
+
+      if options.status || (options.quiet && status == b"OK") {
+        return Ok(());
+      }
+
+      let name = path_bytes(path);
+      let mut line = Vec::new();
+      match escape_name(&name).filter(|_| !options.zero) {
+        Some(escaped) => {
+          line.push(b'\\');
+          line.extend_from_slice(&escaped);
+        }
+        None => line.extend_from_slice(&name),
+      }
+      line.extend_from_slice(b": ");
+      line.extend_from_slice(status);
+      line.push(b'\n');
+
+      out.write_all(&line)
+    })?;
+
+    if !options.status {
+      warn(err, invalid, "line is", "lines are", "improperly formatted")?;
+      warn(
+        err,
+        unreadable,
+        "listed file",
+        "listed files",
+        "could not be read",
+      )?;
+      warn(
+        err,
+        mismatched,
+        "computed checksum",
+        "computed checksums",
+        "did NOT match",
+      )?;
+    }
+
+    if unreadable + mismatched > 0 {
+      code = 1;
+    }
+  }
+
+  Ok(code)
+}
+
+/// Hashes `paths` on up to `options.jobs` threads and passes the results to `report` in
+/// order.
+fn hash_files<F>(paths: &[OsString], options: &Options, mut report: F) -> io::Result<()>
+where
+  F: FnMut(usize, io::Result<u32>) -> io::Result<()>,
+{
+  let next = AtomicUsize::new(0);
+  let (sender, receiver) = mpsc::channel();
+  let backend = options.backend;
+
+  thread::scope(|scope| {
+    for _ in 0..options.jobs.min(paths.len()) {
+      let (sender, next) = (sender.clone(), &next);
+
+      scope.spawn(move || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        if i >= paths.len() {
+          break;
+        }
+
+        let result = open(&paths[i]).and_then(|mut reader| hash(&mut reader, backend));
+        if sender.send((i, result)).is_err() {
+          break;
+        }
+      });
+    }
+    drop(sender);
+
+    let mut pending = BTreeMap::new();
+    let mut reported = 0;
+    for (i, result) in receiver {
+      pending.insert(i, result);
+
+      while let Some(result) = pending.remove(&reported) {
+        if let Err(error) = report(reported, result) {
+          // Stop the workers, the remaining results are dropped.
+          next.store(paths.len(), Ordering::Relaxed);
+          return Err(error);
+        }
+        reported += 1;
+      }
+    }
+
+    Ok(())
+  })
+}
+
+/// Opens `path` for reading, `-` is standard input.
+fn open(path: &OsStr) -> io::Result<Box<dyn BufRead + Send>> {
+  if path == "-" {
+    Ok(Box::new(BufReader::with_capacity(BUF_SIZE, io::stdin())))
+  } else {
+    let file = File::open(path)?;
+    Ok(Box::new(BufReader::with_capacity(BUF_SIZE, file)))
+  }
+}
+
+/// Hashes `reader` until EOF with `backend`, or the detected backend if `None`.
+fn hash<R: BufRead>(reader: &mut R, backend: Option<Adler32Imp>) -> io::Result<u32> {
+  let update = match backend {
+    Some(update) => update,
+    None => return bufread::adler32(reader),
+  };
+
+  let (mut a, mut b) = (1, 0);
+  loop {
+    let len = match reader.fill_buf() {
+      Ok([]) => return Ok((u32::from(b) << 16) | u32::from(a)),
+      Ok(buf) => {
+        let (next_a, next_b) = update(a, b, buf);
+        a = next_a;
+        b = next_b;
+        buf.len()
+      }
+      Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+      Err(error) => return Err(error),
+    };
+
+    reader.consume(len);
+  }
+}
+
+fn report(err: &mut dyn Write, path: &Path, error: &io::Error) -> io::Result<()> {
+  err.write_all(b"adler32sum: ")?;
+  err.write_all(&path_bytes(path))?;
+  writeln!(err, ": {}", error)
+}
+
+fn warn(
+  err: &mut dyn Write,
+  count: usize,
+  one: &str,
+  many: &str,
+  what: &str,
+) -> io::Result<()> {
+  match count {
+    0 => Ok(()),
+    1 => writeln!(err, "adler32sum: WARNING: 1 {} {}", one, what),
+    _ => writeln!(err, "adler32sum: WARNING: {} {} {}", count, many, what),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{hash, run};
+  use std::ffi::OsString;
+  use std::fs;
+  use std::path::PathBuf;
+
+  /// Temporary directory removed on drop.
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let dir =
+        std::env::temp_dir().join(format!("adler32sum-{}-{}", std::process::id(), name));
+      fs::create_dir_all(&dir).unwrap();
+      TempDir(dir)
+    }
+
+    fn file(&self, name: &str, data: &[u8]) -> String {
+      let path = self.0.join(name);
+      fs::write(&path, data).unwrap();
+      path.to_str().unwrap().to_string()
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn sum(args: &[&str]) -> (i32, String, String) {
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let code = run(args.iter().map(OsString::from), &mut out, &mut err);
+
+    (
+      code,
+      String::from_utf8(out).unwrap(),
+      String::from_utf8(err).unwrap(),
+    )
+  }
+
+  #[test]
+  fn formats() {
+    let dir = TempDir::new("formats");
+    let a = dir.file("a", b"Wikipedia");
+    let b = dir.file("b", b"");
+
+    let (code, out, _) = sum(&[&a, &b]);
+    assert_eq!(code, 0);
+    assert_eq!(out, format!("11e60398  {}\n00000001  {}\n", a, b));
+
+    let (_, out, _) = sum(&["--tag", &a]);
+    assert_eq!(out, format!("ADLER32 ({}) = 11e60398\n", a));
+
+    let (_, out, _) = sum(&["-z", "-j", "1", &a, &b]);
+    assert_eq!(out, format!("11e60398  {}\000000001  {}\0", a, b));
+  }
+
+  #[test]
+  fn backends() {
+    let dir = TempDir::new("backends");
+    let data: Vec<u8> = (0..1024 * 300).map(|i| (i * 7 % 251) as u8).collect();
+    let file = dir.file("data", &data);
+    let expected = format!("{:08x}  {}\n", adler2::adler32_slice(&data), file);
+
+    for &name in &["auto", "scalar", "sse2", "ssse3", "avx2", "avx512"] {
+      if super::backend(name).is_err() {
+        continue;
+      }
+
+      let (code, out, _) = sum(&["--backend", name, &file]);
+      assert_eq!((code, out), (0, expected.clone()), "backend({})", name);
+    }
+
+    let (code, _, err) = sum(&["--backend=bogus", &file]);
+    assert_eq!(code, 1);
+    assert!(err.contains("unknown backend"));
+
+    let mut reader = &data[..];
+    let scalar = super::backend("scalar").unwrap();
+    assert_eq!(
+      hash(&mut reader, scalar).unwrap(),
+      adler2::adler32_slice(&data)
+    );
+  }
+
+  #[test]
+  fn check() {
+    let dir = TempDir::new("check");
+    let a = dir.file("a", b"Wikipedia");
+    let b = dir.file("b", b"data");
+    let c = dir.file("c", b"more data");
+
+    let (_, gnu, _) = sum(&[&a, &b]);
+    let (_, bsd, _) = sum(&["--tag", &c]);
+    let list = dir.file("list", format!("{}{}", gnu, bsd).as_bytes());
+
+    let (code, out, err) = sum(&["-c", &list]);
+    assert_eq!((code, err.as_str()), (0, ""));
+    assert_eq!(out, format!("{}: OK\n{}: OK\n{}: OK\n", a, b, c));
+
+    fs::write(&b, b"changed").unwrap();
+    fs::remove_file(&c).unwrap();
+
+    let (code, out, err) = sum(&["--check", "--quiet", &list]);
+    assert_eq!(code, 1);
+    assert_eq!(out, format!("{}: FAILED\n{}: FAILED open or read\n", b, c));
+    assert!(err.contains("WARNING: 1 listed file could not be read"));
+    assert!(err.contains("WARNING: 1 computed checksum did NOT match"));
+
+    let (code, out, err) = sum(&["-c", "--status", &list]);
+    assert_eq!((code, out.as_str(), err.as_str()), (1, "", ""));
+
+    let garbage = dir.file("garbage", b"not a checksum\n");
+    let (code, _, err) = sum(&["-c", &garbage]);
+    assert_eq!(code, 1);
+    assert!(err.contains("no properly formatted checksum lines found"));
+  }
+
+  #[test]
+  fn options() {
+    let dir = TempDir::new("options");
+    let a = dir.file("a", b"Wikipedia");
+
+    let (_, out, _) = sum(&["-z", &a]);
+    let list = dir.file("list", out.as_bytes());
+
+    let (code, out, _) = sum(&["-cz", "-qj2", &list]);
+    assert_eq!((code, out.as_str()), (0, ""));
+    assert_eq!(sum(&["-zcq", "-j", "2", &list]).1, "");
+
+    let (code, _, err) = sum(&["-cx", &list]);
+    assert_eq!(code, 1);
+    assert!(err.contains("unrecognized option '-x'"));
+
+    let (code, out, err) = sum(&["--help"]);
+    assert_eq!((code, out.as_str(), err.as_str()), (0, super::USAGE, ""));
+
+    let (code, out, _) = sum(&["-V"]);
+    assert_eq!(code, 0);
+    assert_eq!(out, format!("adler32sum {}\n", env!("CARGO_PKG_VERSION")));
+  }
+
+  #[test]
+  fn escaped_names() {
+    let dir = TempDir::new("escaped");
+    let file = dir.file("new\nline", b"data");
+
+    let (_, out, _) = sum(&[&file]);
+    assert_eq!(out, format!("\\0400019b  {}\n", file.replace('\n', "\\n")));
+
+    let list = dir.file("list", out.as_bytes());
+    let (code, out, _) = sum(&["-c", &list]);
+    assert_eq!(code, 0);
+    assert_eq!(out, format!("\\{}: OK\n", file.replace('\n', "\\n")));
+  }
+}
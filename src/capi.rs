@@ -0,0 +1,217 @@
+//! zlib compatible C API.
+//!
+//! The functions follow the semantics of `adler32`, `adler32_z`, `adler32_combine` and
+//! `adler32_combine64` from zlib, prefixed with `simd_` so they can be linked next to
+//! zlib. They are declared in `include/simd_adler32.h`, which also maps the zlib names
+//! when `SIMD_ADLER32_ZLIB_NAMES` is defined. The header is generated from this module
+//! with `cbindgen --config cbindgen.toml --output include/simd_adler32.h src/capi.rs`.
+//!
+//! Build a C library with
+//! `cargo rustc --release --features capi --crate-type cdylib` or `staticlib`.
+use crate::imp::{cached_imp, MOD};
+use core::ffi::{c_long, c_uchar, c_uint, c_ulong};
+use core::slice;
+
+/// Updates the running Adler-32 `adler` with `len` bytes of `buf`, returns `1` if `buf`
+/// is null.
+///
+/// # Safety
+/// Unless null, `buf` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn simd_adler32(
+  adler: c_ulong,
+  buf: *const c_uchar,
+  len: c_uint,
+) -> c_ulong {
+  simd_adler32_z(adler, buf, len as usize)
+}
+
+/// Updates the running Adler-32 `adler` with `len` bytes of `buf`, returns `1` if `buf`
+/// is null.
+///
+/// # Safety
+/// Unless null, `buf` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn simd_adler32_z(
+  adler: c_ulong,
+  buf: *const c_uchar,
+  len: usize,
+) -> c_ulong {
+  if buf.is_null() {
+    return 1;
+  }
+
+  let adler = adler as u32;
+  let a = ((adler & 0xffff) % MOD) as u16;
+  let b = ((adler >> 16) % MOD) as u16;
+
+  let (a, b) = cached_imp()(a, b, slice::from_raw_parts(buf, len));
+
+  c_ulong::from((u32::from(b) << 16) | u32::from(a))
+}
+
+/// Combines the Adler-32 of two sequences, `len2` is the length of the second sequence.
+/// Returns `0xffffffff` if `len2` is negative.
+#[no_mangle]
+pub extern "C" fn simd_adler32_combine(
+  adler1: c_ulong,
+  adler2: c_ulong,
+  len2: c_long,
+) -> c_ulong {
+  // `long` is 32-bit on Windows.
+  #[allow(clippy::useless_conversion)]
+  let len2 = i64::from(len2);
+
+  simd_adler32_combine64(adler1, adler2, len2)
+}
+
+/// Combines the Adler-32 of two sequences, `len2` is the length of the second sequence.
+/// Returns `0xffffffff` if `len2` is negative.
+#[no_mangle]
+pub extern "C" fn simd_adler32_combine64(
+  adler1: c_ulong,
+  adler2: c_ulong,
+  len2: i64,
+) -> c_ulong {
+  if len2 < 0 {
+    return 0xffff_ffff;
+  }
+
+  c_ulong::from(crate::adler32_combine(
+    adler1 as u32,
+    adler2 as u32,
+    len2 as u64,
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    simd_adler32, simd_adler32_combine, simd_adler32_combine64, simd_adler32_z,
+  };
+  use core::ffi::c_ulong;
+  use core::ptr;
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn known_values() {
+    for &(data, expected) in &[
+      (&b""[..], 0x0000_0001),
+      (b"a", 0x0062_0062),
+      (b"abc", 0x024d_0127),
+      (b"message digest", 0x2975_0586),
+      (b"abcdefghijklmnopqrstuvwxyz", 0x9086_0b20),
+      (b"Wikipedia", 0x11e6_0398),
+    ] {
+      let adler = unsafe { simd_adler32(1, data.as_ptr(), data.len() as _) };
+      assert_eq!(adler, expected, "data({:?})", data);
+    }
+  }
+
+  #[test]
+  fn null() {
+    unsafe {
+      assert_eq!(simd_adler32(0, ptr::null(), 0), 1);
+      assert_eq!(simd_adler32(0x1234_5678, ptr::null(), 100), 1);
+      assert_eq!(simd_adler32_z(0x1234_5678, ptr::null(), 100), 1);
+    }
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut adler = unsafe { simd_adler32(0, ptr::null(), 0) };
+    for chunk in random.chunks(1000) {
+      adler = unsafe { simd_adler32_z(adler, chunk.as_ptr(), chunk.len()) };
+    }
+
+    assert_eq!(adler, adler2::adler32_slice(&random).into());
+  }
+
+  #[test]
+  fn combine() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let (first, second) = random.split_at(3000);
+    let adler1 = adler2::adler32_slice(first).into();
+    let adler2 = adler2::adler32_slice(second).into();
+    let expected = adler2::adler32_slice(&random).into();
+
+    assert_eq!(
+      simd_adler32_combine(adler1, adler2, second.len() as _),
+      expected
+    );
+    assert_eq!(
+      simd_adler32_combine64(adler1, adler2, second.len() as _),
+      expected
+    );
+    assert_eq!(simd_adler32_combine(adler1, adler2, -1), 0xffff_ffff);
+    assert_eq!(simd_adler32_combine64(adler1, adler2, -1), 0xffff_ffff);
+
+    let combined = simd_adler32_combine64(0xfff0_fff0, 0xfff0_fff0, 1 << 40);
+    assert_eq!(
+      combined,
+      reference_combine(0xfff0_fff0, 0xfff0_fff0, 1 << 40)
+    );
+  }
+
+  /// The header declares exactly the exported functions, in order. CI checks the rest of
+  /// it against the output of cbindgen.
+  #[test]
+  fn header() {
+    let header = include_str!("../include/simd_adler32.h");
+    let source = include_str!("capi.rs").split("#[cfg(test)]").next().unwrap();
+
+    let declared: Vec<_> = header
+      .lines()
+      .filter(|line| line.ends_with(");"))
+      .filter_map(|line| line.split('(').next()?.rsplit(' ').next())
+      .collect();
+    let exported: Vec<_> = source
+      .split("#[no_mangle]")
+      .skip(1)
+      .filter_map(|item| item.split("fn ").nth(1)?.split('(').next())
+      .collect();
+
+    assert_eq!(
+      declared,
+      [
+        "simd_adler32",
+        "simd_adler32_z",
+        "simd_adler32_combine",
+        "simd_adler32_combine64"
+      ]
+    );
+    assert_eq!(declared, exported);
+  }
+
+  /// Port of `adler32_combine_` from zlib.
+  fn reference_combine(adler1: c_ulong, adler2: c_ulong, len2: u64) -> c_ulong {
+    const BASE: u64 = 65521;
+
+    let rem = len2 % BASE;
+    let mut sum1 = u64::from(adler1 as u32 & 0xffff);
+    let mut sum2 = rem * sum1 % BASE;
+
+    sum1 += u64::from(adler2 as u32 & 0xffff) + BASE - 1;
+    sum2 += u64::from(adler1 as u32 >> 16) + u64::from(adler2 as u32 >> 16) + BASE - rem;
+
+    if sum1 >= BASE {
+      sum1 -= BASE;
+    }
+    if sum1 >= BASE {
+      sum1 -= BASE;
+    }
+    if sum2 >= BASE << 1 {
+      sum2 -= BASE << 1;
+    }
+    if sum2 >= BASE {
+      sum2 -= BASE;
+    }
+
+    (sum1 | (sum2 << 16)) as c_ulong
+  }
+}
@@ -0,0 +1,455 @@
+//! Content-defined chunking.
+//!
+//! Chunk boundaries are placed after bytes where the librsync [`Rollsum`] over the last
+//! [`WINDOW`] bytes has its low bits set, similar to rsyncable gzip and bup. A boundary
+//! only depends on the bytes in the window so inserting or removing data only moves the
+//! boundaries near the edit.
+//!
+//! The digest is taken as `s1 << 16 | s2`, so the mask tests the better mixed `s2` first.
+//! Bytes below the minimum chunk size are never tested, the window is primed with the
+//! SIMD update implementations at the minimum. From there the SIMD scan implementations
+//! compute the sums of a vector of consecutive windows at once: prefix sums of the
+//! per-position changes of `s1` and `s2` give the sums of every window in the vector,
+//! which are tested against the mask lane by lane.
+//!
+//! [`Chunks`] and [`ReadChunks`] fill the window with bytes of the previous chunk when
+//! the minimum chunk size is below [`WINDOW`], so boundaries don't depend on where the
+//! previous chunk was cut.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::chunker::Chunker;
+//!
+//! let data = vec![0x2a; 100_000];
+//! let chunker = Chunker::new(2048, 8192, 32768);
+//!
+//! for chunk in chunker.chunks(&data) {
+//!   assert!(chunk.len() <= 32768);
+//! }
+//! ```
+use crate::imp::{get_scan_imp, ScanImp};
+use crate::rsync::RS_CHAR_OFFSET;
+use crate::Rollsum;
+
+/// Number of bytes the rolling checksum is computed over.
+pub const WINDOW: usize = 64;
+
+/// Content-defined chunker configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chunker {
+  min: usize,
+  mask: u32,
+  max: usize,
+}
+
+impl Chunker {
+  /// Constructs a new `Chunker` producing chunks of `min` to `max` bytes, `avg` bytes on
+  /// average.
+  ///
+  /// Past `min`, boundaries are on average a power of two bytes apart, the power of two
+  /// nearest to `avg - min`. Chunks cut at `max` lower the average below `avg` unless
+  /// `max` is well above it.
+  ///
+  /// # Panics
+  /// Panics unless `min < avg <= max` and `avg - min` does not exceed `2^31`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::chunker::Chunker;
+  ///
+  /// let chunker = Chunker::new(2048, 8192, 65536);
+  /// ```
+  pub fn new(min: usize, avg: usize, max: usize) -> Self {
+    assert!(
+      min < avg && avg <= max,
+      "Chunk sizes must satisfy min < avg <= max"
+    );
+    assert!(
+      (avg - min) as u64 <= 1 << 31,
+      "Average chunk size must not exceed the minimum by more than 2^31"
+    );
+
+    Self {
+      min,
+      mask: (nearest_power_of_two(avg - min) - 1) as u32,
+      max,
+    }
+  }
+
+  /// Returns the length of the first chunk of `data`, taking `data` as the start of a
+  /// stream.
+  ///
+  /// Returns `None` if `data` is shorter than the maximum chunk size and contains no
+  /// boundary, more data is needed to decide. At the end of a stream the remaining data
+  /// is the last chunk.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::chunker::Chunker;
+  ///
+  /// let chunker = Chunker::new(64, 256, 1024);
+  ///
+  /// assert_eq!(chunker.next_boundary(&[0; 2048]), Some(1024));
+  /// assert_eq!(chunker.next_boundary(&[0; 16]), None);
+  /// ```
+  pub fn next_boundary(&self, data: &[u8]) -> Option<usize> {
+    self.boundary(data, 0, get_scan_imp())
+  }
+
+  /// Splits `data` into chunks.
+  pub fn chunks<'a>(&self, data: &'a [u8]) -> Chunks<'a> {
+    Chunks {
+      chunker: *self,
+      data,
+      offset: 0,
+      scan: get_scan_imp(),
+    }
+  }
+
+  /// Splits the data read from `reader` into chunks.
+  ///
+  /// Up to one maximum sized chunk is buffered.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use std::io::Cursor;
+  /// use simd_adler32::chunker::Chunker;
+  ///
+  /// let chunker = Chunker::new(2048, 8192, 32768);
+  /// let mut reader = Cursor::new(vec![0x2a; 100_000]);
+  ///
+  /// for chunk in chunker.read_chunks(&mut reader) {
+  ///   let chunk = chunk.unwrap();
+  ///   assert!(chunk.len() <= 32768);
+  /// }
+  /// ```
+  #[cfg(feature = "std")]
+  pub fn read_chunks<R: std::io::Read>(&self, reader: R) -> ReadChunks<R> {
+    ReadChunks {
+      chunker: *self,
+      reader,
+      buf: Vec::new(),
+      history: 0,
+      eof: false,
+      scan: get_scan_imp(),
+    }
+  }
+
+  /// Returns the length of the first chunk of `data[history..]`, the `history` bytes
+  /// before it only fill the window.
+  fn boundary(&self, data: &[u8], history: usize, scan: ScanImp) -> Option<usize> {
+    let len = data.len() - history;
+    let start = self.min.max(1);
+    if len < start {
+      return None;
+    }
+
+    let end = history + len.min(self.max);
+    let start = history + start;
+
+    let mut sum = Rollsum::new();
+    sum.write(&data[start.saturating_sub(WINDOW)..start]);
+    if self.is_boundary(&sum) {
+      return Some(start - history);
+    }
+
+    // At the start of a stream the window fills up one byte at a time.
+    let mut pos = start;
+    while pos < end.min(WINDOW) {
+      sum.roll_in(data[pos]);
+      pos += 1;
+
+      if self.is_boundary(&sum) {
+        return Some(pos - history);
+      }
+    }
+
+    if pos < end {
+      let digest = sum.finish();
+      let (s1, s2) = (digest as u16, (digest >> 16) as u16);
+      let windows = &data[pos - WINDOW..end];
+
+      if let Some(n) = scan(s1, s2, windows, WINDOW, RS_CHAR_OFFSET, self.mask) {
+        return Some(pos + n - history);
+      }
+    }
+
+    if len >= self.max {
+      Some(self.max)
+    } else {
+      None
+    }
+  }
+
+  #[inline(always)]
+  fn is_boundary(&self, sum: &Rollsum) -> bool {
+    sum.finish().rotate_left(16) & self.mask == self.mask
+  }
+}
+
+/// Returns the power of two nearest to `n`, rounding ties up.
+fn nearest_power_of_two(n: usize) -> usize {
+  let high = n.next_power_of_two();
+  let low = high / 2;
+
+  if n - low < high - n {
+    low
+  } else {
+    high
+  }
+}
+
+/// Iterator over the chunks of a slice, see [`Chunker::chunks`].
+#[derive(Clone, Debug)]
+pub struct Chunks<'a> {
+  chunker: Chunker,
+  data: &'a [u8],
+  offset: usize,
+  scan: ScanImp,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+  type Item = &'a [u8];
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.offset == self.data.len() {
+      return None;
+    }
+
+    let history = self.offset.min(WINDOW);
+    let data = &self.data[self.offset - history..];
+    let len = self
+      .chunker
+      .boundary(data, history, self.scan)
+      .unwrap_or(data.len() - history);
+    let chunk = &self.data[self.offset..self.offset + len];
+
+    self.offset += len;
+
+    Some(chunk)
+  }
+}
+
+/// Iterator over the chunks read from a reader, see [`Chunker::read_chunks`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ReadChunks<R> {
+  chunker: Chunker,
+  reader: R,
+  /// The last `history` bytes of the previous chunk followed by the buffered data.
+  buf: Vec<u8>,
+  history: usize,
+  eof: bool,
+  scan: ScanImp,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> ReadChunks<R> {
+  /// Reads until a maximum sized chunk is buffered or the reader is exhausted.
+  fn fill(&mut self) -> std::io::Result<()> {
+    let mut buf = [0; 4096];
+
+    let max = self.history + self.chunker.max;
+
+    while !self.eof && self.buf.len() < max {
+      let want = buf.len().min(max - self.buf.len());
+
+      match self.reader.read(&mut buf[..want]) {
+        Ok(0) => self.eof = true,
+        Ok(n) => self.buf.extend_from_slice(&buf[..n]),
+        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+        Err(err) => return Err(err),
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> Iterator for ReadChunks<R> {
+  type Item = std::io::Result<Vec<u8>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if let Err(err) = self.fill() {
+      return Some(Err(err));
+    }
+
+    if self.buf.len() == self.history {
+      return None;
+    }
+
+    let len = self
+      .chunker
+      .boundary(&self.buf, self.history, self.scan)
+      .unwrap_or(self.buf.len() - self.history);
+    let end = self.history + len;
+    let chunk = self.buf[self.history..end].to_vec();
+
+    // Keep the tail of the chunk as the window history of the next one.
+    self.history = end.min(WINDOW);
+    self.buf.drain(..end - self.history);
+
+    Some(Ok(chunk))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Chunker;
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn sizes() {
+    let mut random = vec![0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let chunker = Chunker::new(1024, 4096, 16384);
+    let chunks: Vec<_> = chunker.chunks(&random).collect();
+    let (last, chunks) = chunks.split_last().unwrap();
+
+    assert!(last.len() <= 16384);
+    for chunk in chunks {
+      assert!(
+        chunk.len() >= 1024 && chunk.len() <= 16384,
+        "len({})",
+        chunk.len()
+      );
+    }
+
+    // Expect about `avg` bytes per chunk, well within `min` and `max`.
+    let count = chunks.len() + 1;
+    assert!(
+      count > random.len() / 16384 && count < random.len() / 2048,
+      "count({})",
+      count
+    );
+    assert_eq!(chunks.concat().len() + last.len(), random.len());
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn average() {
+    let mut random = vec![0; 1024 * 1024 * 4];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    // `max` is far enough above `avg` to rarely cut a chunk.
+    let chunker = Chunker::new(1024, 1024 + 4096, 65536);
+    let count = chunker.chunks(&random).count();
+    let expected = random.len() / (1024 + 4096);
+
+    assert!(
+      count > expected * 4 / 5 && count < expected * 5 / 4,
+      "count({}), expected({})",
+      count,
+      expected
+    );
+  }
+
+  #[test]
+  fn mask() {
+    assert_eq!(Chunker::new(16, 256, 4096).mask, 255);
+    assert_eq!(Chunker::new(2048, 8192, 65536).mask, 8191);
+    assert_eq!(Chunker::new(2048, 6000, 65536).mask, 4095);
+    assert_eq!(Chunker::new(0, 1, 1).mask, 0);
+    assert_eq!(Chunker::new(0, 1 << 31, 1 << 31).mask, (1 << 31) - 1);
+  }
+
+  #[test]
+  fn boundaries() {
+    let mut random = [0; 1024 * 64];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    // The window reaches into the previous chunk below a minimum of `WINDOW` bytes.
+    let chunker = Chunker::new(16, 256, 4096);
+    let mut offset = 0;
+
+    for chunk in chunker.chunks(&random) {
+      let start = offset;
+      offset += chunk.len();
+
+      for end in start + 16..offset {
+        assert!(!reference_boundary(&random[..end]), "end({})", end);
+      }
+      if chunk.len() < 4096 && offset < random.len() {
+        assert!(reference_boundary(&random[..offset]), "end({})", offset);
+      }
+    }
+
+    assert_eq!(offset, random.len());
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn insertion() {
+    let mut random = vec![0; 1024 * 256];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut edited = random.clone();
+    edited.splice(1000..1000, b"inserted".iter().cloned());
+
+    let chunker = Chunker::new(512, 2048, 8192);
+    let original: Vec<_> = chunker.chunks(&random).collect();
+    let edited: Vec<_> = chunker.chunks(&edited).collect();
+
+    // Chunks resynchronize shortly after the edit.
+    let shared = original.iter().rev().zip(edited.iter().rev());
+    let shared = shared.take_while(|(a, b)| a == b).count();
+
+    assert!(
+      shared + 4 >= original.len(),
+      "{} of {}",
+      shared,
+      original.len()
+    );
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn read_chunks() {
+    let mut random = vec![0; 1024 * 256];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for chunker in &[Chunker::new(512, 2048, 8192), Chunker::new(16, 256, 1024)] {
+      let expected: Vec<_> = chunker.chunks(&random).collect();
+      let chunks: Vec<_> = chunker
+        .read_chunks(Trickle(&random))
+        .map(Result::unwrap)
+        .collect();
+
+      assert_eq!(chunks, expected);
+      assert_eq!(chunker.read_chunks(&[][..]).count(), 0);
+    }
+  }
+
+  /// Reader returning at most 7 bytes per read.
+  #[cfg(feature = "std")]
+  struct Trickle<'a>(&'a [u8]);
+
+  #[cfg(feature = "std")]
+  impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      let n = buf.len().min(self.0.len()).min(7);
+
+      buf[..n].copy_from_slice(&self.0[..n]);
+      self.0 = &self.0[n..];
+
+      Ok(n)
+    }
+  }
+
+  /// Checks the bup style boundary condition with mask `255` for the window ending
+  /// `data`.
+  fn reference_boundary(data: &[u8]) -> bool {
+    let window = &data[data.len().saturating_sub(super::WINDOW)..];
+    let (mut s1, mut s2) = (0u32, 0u32);
+
+    for byte in window {
+      s1 = s1.wrapping_add(u32::from(*byte) + 31);
+      s2 = s2.wrapping_add(s1);
+    }
+
+    (((s1 & 0xffff) << 16) | (s2 & 0xffff)) & 255 == 255
+  }
+}
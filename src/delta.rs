@@ -0,0 +1,607 @@
+//! rsync-style signatures, deltas and patching.
+//!
+//! A [`Signature`] splits a basis file into blocks and records the Adler-32 of every
+//! block along with a strong hash supplied by the caller. [`Delta::generate`] rolls an
+//! Adler-32 over the new file to find blocks of the basis, confirming weak matches with
+//! the strong hash, and describes the new file as copies from the basis and literal
+//! data. [`patch`] applies a delta to the basis to reconstruct the new file.
+//!
+//! # Binary format
+//!
+//! All integers are big-endian.
+//!
+//! A signature is the magic `ADS\x01`, the block size as `u32`, the strong hash length
+//! as `u32` and the basis length as `u64`, followed by one entry per block holding the
+//! Adler-32 of the block as `u32` and the strong hash truncated to the strong hash
+//! length. Every block but the last is block size bytes long.
+//!
+//! A delta is the magic `ADD\x01` followed by instructions, each starting with a tag
+//! byte:
+//!
+//! | tag    | instruction | operands                                                |
+//! | ------ | ----------- | ------------------------------------------------------- |
+//! | `0x00` | end         | Adler-32 of the new file as `u32`                       |
+//! | `0x01` | copy        | basis offset as `u64`, length as `u64`                  |
+//! | `0x02` | literal     | length as `u64`, followed by that many bytes            |
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::delta::{patch, Delta, Signature};
+//! use simd_adler32::fletcher::fletcher64;
+//!
+//! // Use a cryptographic hash in practice.
+//! let strong = |block: &[u8]| fletcher64(block).to_be_bytes();
+//!
+//! let basis = b"The quick brown fox jumps over the lazy dog".repeat(100);
+//! let mut new = basis.clone();
+//! new.splice(1000..1000, b"cat".iter().cloned());
+//!
+//! let signature = Signature::generate(&basis, 64, 8, strong);
+//! let delta = Delta::generate(&signature, &new, strong);
+//!
+//! let mut patched = Vec::new();
+//! patch(&basis, &delta, &mut patched).unwrap();
+//!
+//! assert_eq!(patched, new);
+//! ```
+use crate::search::{BlockTable, Rolling};
+use crate::Adler32;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+const SIGNATURE_MAGIC: [u8; 4] = *b"ADS\x01";
+const DELTA_MAGIC: [u8; 4] = *b"ADD\x01";
+
+const TAG_END: u8 = 0x00;
+const TAG_COPY: u8 = 0x01;
+const TAG_LITERAL: u8 = 0x02;
+
+/// Block signature of a basis file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+  block_size: u32,
+  strong_len: u32,
+  len: u64,
+  blocks: Vec<BlockSignature>,
+}
+
+/// Checksums of a single basis block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockSignature {
+  /// Adler-32 of the block.
+  pub weak: u32,
+  /// Strong hash of the block truncated to the signature's strong hash length.
+  pub strong: Vec<u8>,
+}
+
+impl Signature {
+  /// Computes the signature of `basis` with blocks of `block_size` bytes.
+  ///
+  /// `strong` is called with every block and must return at least `strong_len` bytes,
+  /// the rest are discarded.
+  ///
+  /// # Panics
+  /// Panics if `block_size` is zero or `strong` returns less than `strong_len` bytes.
+  pub fn generate<F, H>(
+    basis: &[u8],
+    block_size: u32,
+    strong_len: u32,
+    mut strong: F,
+  ) -> Self
+  where
+    F: FnMut(&[u8]) -> H,
+    H: AsRef<[u8]>,
+  {
+    assert!(block_size > 0, "Block size must not be zero");
+
+    let blocks = basis
+      .chunks(block_size as usize)
+      .map(|block| BlockSignature {
+        weak: adler32(block),
+        strong: truncate(strong(block).as_ref(), strong_len),
+      })
+      .collect();
+
+    Self {
+      block_size,
+      strong_len,
+      len: basis.len() as u64,
+      blocks,
+    }
+  }
+
+  /// Returns the block size in bytes.
+  pub fn block_size(&self) -> u32 {
+    self.block_size
+  }
+
+  /// Returns the length of the strong hashes in bytes.
+  pub fn strong_len(&self) -> u32 {
+    self.strong_len
+  }
+
+  /// Returns the length of the basis in bytes.
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  /// Returns `true` if the basis is empty.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns the block signatures in basis order.
+  pub fn blocks(&self) -> &[BlockSignature] {
+    &self.blocks
+  }
+
+  /// Returns the length of block `i` in bytes.
+  fn block_len(&self, i: usize) -> usize {
+    let offset = i as u64 * u64::from(self.block_size);
+
+    (self.len - offset).min(u64::from(self.block_size)) as usize
+  }
+
+  /// Serializes the signature to `writer`.
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+    writer.write_all(&SIGNATURE_MAGIC)?;
+    writer.write_all(&self.block_size.to_be_bytes())?;
+    writer.write_all(&self.strong_len.to_be_bytes())?;
+    writer.write_all(&self.len.to_be_bytes())?;
+
+    for block in &self.blocks {
+      writer.write_all(&block.weak.to_be_bytes())?;
+      writer.write_all(&block.strong)?;
+    }
+
+    Ok(())
+  }
+
+  /// Deserializes a signature from `reader`.
+  pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+    if read_array::<_, [u8; 4]>(reader)? != SIGNATURE_MAGIC {
+      return Err(invalid_data("Invalid signature magic"));
+    }
+
+    let block_size = read_u32(reader)?;
+    let strong_len = read_u32(reader)?;
+    let len = read_u64(reader)?;
+
+    if block_size == 0 {
+      return Err(invalid_data("Invalid signature block size"));
+    }
+
+    let count = len / u64::from(block_size) + u64::from(len % u64::from(block_size) != 0);
+    let mut blocks = Vec::new();
+    for _ in 0..count {
+      let weak = read_u32(reader)?;
+      let strong = read_vec(reader, u64::from(strong_len))?;
+
+      blocks.push(BlockSignature { weak, strong });
+    }
+
+    Ok(Self {
+      block_size,
+      strong_len,
+      len,
+      blocks,
+    })
+  }
+}
+
+/// Delta instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+  /// Copies `len` bytes of the basis starting at `offset`.
+  Copy {
+    /// Offset in the basis.
+    offset: u64,
+    /// Number of bytes copied.
+    len: u64,
+  },
+  /// Inserts literal bytes.
+  Literal(Vec<u8>),
+}
+
+/// Instructions reconstructing a new file from a basis.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delta {
+  ops: Vec<Op>,
+  checksum: u32,
+}
+
+impl Delta {
+  /// Computes the delta of `new` against the basis described by `signature`.
+  ///
+  /// `strong` must be the function the signature was generated with.
+  pub fn generate<F, H>(signature: &Signature, new: &[u8], mut strong: F) -> Self
+  where
+    F: FnMut(&[u8]) -> H,
+    H: AsRef<[u8]>,
+  {
+    let block_size = signature.block_size as usize;
+    let strong_len = signature.strong_len;
+
+    let weaks: Vec<_> = signature.blocks.iter().map(|block| block.weak).collect();
+    let index = BlockTable::new(block_size, &weaks);
+
+    let mut find = |weak: u32, window: &[u8]| {
+      let mut candidates = index.get(weak).peekable();
+      candidates.peek()?;
+      let hash = truncate(strong(window).as_ref(), strong_len);
+
+      candidates.find(|&i| {
+        let block = &signature.blocks[i];
+        block.strong == hash && signature.block_len(i) == window.len()
+      })
+    };
+
+    let mut ops = Vec::new();
+    let mut literal = 0;
+    let mut pos = 0;
+    let mut rolling = None;
+
+    while pos + block_size <= new.len() {
+      let window = &new[pos..pos + block_size];
+      let weak = *rolling.get_or_insert_with(|| Rolling::new(window));
+
+      match find(weak.finish(), window) {
+        Some(i) => {
+          push_literal(&mut ops, &new[literal..pos]);
+          push_copy(&mut ops, i as u64 * block_size as u64, block_size as u64);
+
+          pos += block_size;
+          literal = pos;
+          rolling = None;
+        }
+        None => {
+          if let (Some(rolling), Some(&new_byte)) =
+            (rolling.as_mut(), new.get(pos + block_size))
+          {
+            rolling.rotate(new[pos], new_byte);
+          }
+
+          pos += 1;
+        }
+      }
+    }
+
+    // A short last block of the basis can only match the end of the new file.
+    let last = signature.blocks.len().wrapping_sub(1);
+    let tail = match signature.blocks.last() {
+      Some(block) if signature.block_len(last) < block_size => {
+        let tail_len = signature.block_len(last);
+        let tail = &new[new.len() - tail_len.min(new.len() - literal)..];
+
+        if tail.len() == tail_len && block.weak == adler32(tail) {
+          find(block.weak, tail).map(|i| (i, tail.len()))
+        } else {
+          None
+        }
+      }
+      _ => None,
+    };
+
+    match tail {
+      Some((i, len)) => {
+        push_literal(&mut ops, &new[literal..new.len() - len]);
+        push_copy(&mut ops, i as u64 * block_size as u64, len as u64);
+      }
+      None => push_literal(&mut ops, &new[literal..]),
+    }
+
+    Self {
+      ops,
+      checksum: adler32(new),
+    }
+  }
+
+  /// Returns the instructions in order.
+  pub fn ops(&self) -> &[Op] {
+    &self.ops
+  }
+
+  /// Returns the Adler-32 of the new file.
+  pub fn checksum(&self) -> u32 {
+    self.checksum
+  }
+
+  /// Serializes the delta to `writer`.
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+    writer.write_all(&DELTA_MAGIC)?;
+
+    for op in &self.ops {
+      match op {
+        Op::Copy { offset, len } => {
+          writer.write_all(&[TAG_COPY])?;
+          writer.write_all(&offset.to_be_bytes())?;
+          writer.write_all(&len.to_be_bytes())?;
+        }
+        Op::Literal(data) => {
+          writer.write_all(&[TAG_LITERAL])?;
+          writer.write_all(&(data.len() as u64).to_be_bytes())?;
+          writer.write_all(data)?;
+        }
+      }
+    }
+
+    writer.write_all(&[TAG_END])?;
+    writer.write_all(&self.checksum.to_be_bytes())
+  }
+
+  /// Deserializes a delta from `reader`.
+  pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+    if read_array::<_, [u8; 4]>(reader)? != DELTA_MAGIC {
+      return Err(invalid_data("Invalid delta magic"));
+    }
+
+    let mut ops = Vec::new();
+    loop {
+      let [tag] = read_array::<_, [u8; 1]>(reader)?;
+
+      match tag {
+        TAG_END => {
+          let checksum = read_u32(reader)?;
+
+          return Ok(Self { ops, checksum });
+        }
+        TAG_COPY => {
+          let offset = read_u64(reader)?;
+          let len = read_u64(reader)?;
+
+          ops.push(Op::Copy { offset, len });
+        }
+        TAG_LITERAL => {
+          let len = read_u64(reader)?;
+
+          ops.push(Op::Literal(read_vec(reader, len)?));
+        }
+        _ => return Err(invalid_data("Invalid delta instruction")),
+      }
+    }
+  }
+}
+
+/// Applies `delta` to `basis` and writes the new file to `writer`.
+///
+/// Fails with [`ErrorKind::InvalidData`] if a copy is out of the basis bounds or the
+/// result does not match the delta checksum, in which case `writer` has already
+/// received the mismatching data.
+pub fn patch<W: Write>(basis: &[u8], delta: &Delta, writer: &mut W) -> Result<()> {
+  let mut hash = Adler32::new();
+
+  for op in &delta.ops {
+    let data = match op {
+      Op::Copy { offset, len } => offset
+        .checked_add(*len)
+        .filter(|end| *end <= basis.len() as u64)
+        .map(|end| &basis[*offset as usize..end as usize])
+        .ok_or_else(|| invalid_data("Copy out of basis bounds"))?,
+      Op::Literal(data) => &data[..],
+    };
+
+    hash.write(data);
+    writer.write_all(data)?;
+  }
+
+  if hash.finish() != delta.checksum {
+    return Err(invalid_data("Checksum mismatch"));
+  }
+
+  Ok(())
+}
+
+fn adler32(data: &[u8]) -> u32 {
+  let mut hash = Adler32::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+fn truncate(hash: &[u8], len: u32) -> Vec<u8> {
+  assert!(
+    hash.len() >= len as usize,
+    "Strong hash is shorter than the strong hash length"
+  );
+
+  hash[..len as usize].to_vec()
+}
+
+fn push_literal(ops: &mut Vec<Op>, data: &[u8]) {
+  if data.is_empty() {
+    return;
+  }
+
+  if let Some(Op::Literal(literal)) = ops.last_mut() {
+    literal.extend_from_slice(data);
+  } else {
+    ops.push(Op::Literal(data.to_vec()));
+  }
+}
+
+fn push_copy(ops: &mut Vec<Op>, offset: u64, len: u64) {
+  if let Some(Op::Copy {
+    offset: last,
+    len: last_len,
+  }) = ops.last_mut()
+  {
+    if *last + *last_len == offset {
+      *last_len += len;
+      return;
+    }
+  }
+
+  ops.push(Op::Copy { offset, len });
+}
+
+pub(crate) fn invalid_data(message: &str) -> Error {
+  Error::new(ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn read_array<R: Read, A: Default + AsMut<[u8]>>(reader: &mut R) -> Result<A> {
+  let mut buf = A::default();
+
+  reader.read_exact(buf.as_mut())?;
+  Ok(buf)
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+  read_array(reader).map(u32::from_be_bytes)
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+  read_array(reader).map(u64::from_be_bytes)
+}
+
+/// Reads exactly `len` bytes without trusting `len` for the allocation size.
+fn read_vec<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+  let mut buf = Vec::new();
+
+  reader.take(len).read_to_end(&mut buf)?;
+  if (buf.len() as u64) < len {
+    return Err(ErrorKind::UnexpectedEof.into());
+  }
+
+  Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{adler32, patch, Delta, Op, Signature};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn edits() {
+    let mut random = vec![0; 1024 * 64];
+    SmallRng::from_entropy().fill(&mut random[..]);
+    let basis = &random[..1024 * 32 + 100];
+
+    let mut inserted = basis.to_vec();
+    inserted.splice(5000..5000, random[1024 * 40..1024 * 41].iter().cloned());
+
+    let mut removed = basis.to_vec();
+    removed.drain(3000..4500);
+
+    let mut modified = basis.to_vec();
+    modified[20000] ^= 0xff;
+
+    let mut appended = basis.to_vec();
+    appended.extend_from_slice(&random[1024 * 50..1024 * 51]);
+
+    let mut prepended = random[1024 * 60..1024 * 60 + 7].to_vec();
+    prepended.extend_from_slice(basis);
+
+    // Every edit costs at most the edited bytes plus the blocks it touches.
+    for (new, max_literal) in &[
+      (basis.to_vec(), 0),
+      (inserted, 1024 + 2 * 512),
+      (removed, 2 * 512),
+      (modified, 512),
+      (appended, 1024 + 100),
+      (prepended, 7),
+      (Vec::new(), 0),
+    ] {
+      let delta = assert_round_trip(basis, new, 512);
+
+      let literal: usize = delta
+        .ops()
+        .iter()
+        .map(|op| match op {
+          Op::Literal(data) => data.len(),
+          Op::Copy { .. } => 0,
+        })
+        .sum();
+      assert!(literal <= *max_literal, "literal({})", literal);
+    }
+
+    assert_round_trip(basis, &random[1024 * 40..], 512);
+  }
+
+  #[test]
+  fn identical() {
+    let mut random = vec![0; 1024 * 8 + 5];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let delta = assert_round_trip(&random, &random, 1024);
+
+    assert_eq!(
+      delta.ops(),
+      &[Op::Copy {
+        offset: 0,
+        len: random.len() as u64
+      }]
+    );
+  }
+
+  #[test]
+  fn small() {
+    assert_round_trip(&[], &[], 16);
+    assert_round_trip(&[], b"abc", 16);
+    assert_round_trip(b"abc", &[], 16);
+    assert_round_trip(b"abc", b"abc", 16);
+    assert_round_trip(b"abcdef", b"xxabcdef", 3);
+    assert_round_trip(b"abcdefgh", b"defghabc", 3);
+  }
+
+  #[test]
+  fn serialization() {
+    let mut random = vec![0; 1024 * 4 + 3];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut new = random.clone();
+    new.splice(100..100, b"inserted".iter().cloned());
+
+    let signature = Signature::generate(&random, 256, 6, fnv);
+    let delta = Delta::generate(&signature, &new, fnv);
+
+    let mut buf = Vec::new();
+    signature.write_to(&mut buf).unwrap();
+    assert_eq!(buf.len(), 4 + 4 + 4 + 8 + 17 * (4 + 6));
+    assert_eq!(Signature::read_from(&mut &buf[..]).unwrap(), signature);
+
+    let mut buf = Vec::new();
+    delta.write_to(&mut buf).unwrap();
+    assert_eq!(Delta::read_from(&mut &buf[..]).unwrap(), delta);
+
+    let truncated = &buf[..buf.len() - 1];
+    assert!(Delta::read_from(&mut &truncated[..]).is_err());
+    assert!(Signature::read_from(&mut &buf[..]).is_err());
+  }
+
+  #[test]
+  fn invalid() {
+    let basis = b"0123456789";
+    let delta = Delta {
+      ops: vec![Op::Copy { offset: 8, len: 4 }],
+      checksum: adler32(b"89"),
+    };
+
+    assert!(patch(basis, &delta, &mut Vec::new()).is_err());
+
+    let signature = Signature::generate(basis, 4, 8, fnv);
+    let delta = Delta::generate(&signature, basis, fnv);
+
+    assert!(patch(b"9876543210", &delta, &mut Vec::new()).is_err());
+  }
+
+  fn assert_round_trip(basis: &[u8], new: &[u8], block_size: u32) -> Delta {
+    let signature = Signature::generate(basis, block_size, 8, fnv);
+    let delta = Delta::generate(&signature, new, fnv);
+
+    let mut patched = Vec::new();
+    patch(basis, &delta, &mut patched).unwrap();
+    assert_eq!(patched, new);
+
+    delta
+  }
+
+  /// FNV-1a, strong enough for tests.
+  fn fnv(data: &[u8]) -> [u8; 8] {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+
+    for byte in data {
+      hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash.to_be_bytes()
+  }
+}
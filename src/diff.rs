@@ -0,0 +1,443 @@
+//! Locating differing ranges with hierarchical checksums.
+//!
+//! A [`ChecksumTree`] holds the Adler-32 checksums of fixed size leaf blocks and of
+//! every group of `fanout` blocks above them, up to a single root covering all data.
+//! Comparing two trees starts at the largest blocks and only descends into blocks whose
+//! checksums differ, so replicas can be compared by exchanging checksums instead of data.
+//!
+//! Equal checksums do not prove equal data, differences colliding at every level of the
+//! tree are missed.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::diff::{diff, ChecksumTree};
+//!
+//! let original = vec![0x2a; 100_000];
+//! let mut replica = original.clone();
+//! replica[50_000] = 0;
+//!
+//! assert_eq!(diff(&original, &replica, 4096, 16), vec![49152..53248]);
+//!
+//! let mut saved = Vec::new();
+//! ChecksumTree::new(&original, 4096, 16).write_to(&mut saved).unwrap();
+//!
+//! let tree = ChecksumTree::read_from(&mut &saved[..]).unwrap();
+//! assert_eq!(tree.diff_reader(&replica[..]).unwrap(), vec![49152..53248]);
+//! ```
+use crate::delta::{invalid_data, read_array, read_u32, read_u64};
+use crate::{adler32_combine, block_checksums};
+use std::io::{ErrorKind, Read, Result, Write};
+use std::ops::Range;
+
+const TREE_MAGIC: [u8; 4] = *b"ACT\x01";
+
+/// Bytes read at once when building a tree from a reader.
+const READ_SIZE: usize = 64 * 1024;
+
+/// Hierarchical Adler-32 checksums of some data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumTree {
+  leaf_size: u32,
+  fanout: u32,
+  len: u64,
+  levels: Vec<Vec<u32>>,
+}
+
+impl ChecksumTree {
+  /// Computes the checksum tree of `data` with leaf blocks of `leaf_size` bytes and
+  /// `fanout` blocks per parent.
+  ///
+  /// # Panics
+  /// Panics if `leaf_size` is zero or `fanout` is below two.
+  pub fn new(data: &[u8], leaf_size: u32, fanout: u32) -> Self {
+    check_shape(leaf_size, fanout);
+
+    let mut leaves = vec![0; data.chunks(leaf_size as usize).len()];
+    block_checksums(data, leaf_size as usize, &mut leaves);
+
+    Self::from_leaves(leaf_size, fanout, data.len() as u64, leaves)
+  }
+
+  /// Computes the checksum tree of the data read from `reader`.
+  ///
+  /// # Panics
+  /// Panics if `leaf_size` is zero or `fanout` is below two.
+  pub fn from_reader<R: Read>(
+    mut reader: R,
+    leaf_size: u32,
+    fanout: u32,
+  ) -> Result<Self> {
+    check_shape(leaf_size, fanout);
+
+    let leaf = leaf_size as usize;
+    let mut buf = vec![0; (READ_SIZE / leaf).max(1) * leaf];
+    let mut leaves = Vec::new();
+    let mut len = 0;
+
+    loop {
+      let filled = read_full(&mut reader, &mut buf)?;
+      let data = &buf[..filled];
+
+      let start = leaves.len();
+      leaves.resize(start + data.chunks(leaf).len(), 0);
+      block_checksums(data, leaf, &mut leaves[start..]);
+
+      len += filled as u64;
+      if filled < buf.len() {
+        break;
+      }
+    }
+
+    Ok(Self::from_leaves(leaf_size, fanout, len, leaves))
+  }
+
+  /// Returns the leaf block size in bytes.
+  pub fn leaf_size(&self) -> u32 {
+    self.leaf_size
+  }
+
+  /// Returns the number of blocks per parent block.
+  pub fn fanout(&self) -> u32 {
+    self.fanout
+  }
+
+  /// Returns the length of the data in bytes.
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  /// Returns `true` if the data is empty.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns the number of levels, including the leaves and the root.
+  pub fn height(&self) -> usize {
+    self.levels.len()
+  }
+
+  /// Returns the block checksums of `level`, level zero holds the leaves.
+  ///
+  /// # Panics
+  /// Panics if `level` is not below the height.
+  pub fn level(&self, level: usize) -> &[u32] {
+    &self.levels[level]
+  }
+
+  /// Returns the Adler-32 checksum of the whole data.
+  pub fn root(&self) -> u32 {
+    self.levels[self.levels.len() - 1]
+      .first()
+      .cloned()
+      .unwrap_or(1)
+  }
+
+  /// Returns the ranges of the data that differ from the data of `other`, ascending and
+  /// with adjacent ranges merged.
+  ///
+  /// Data past the end of the shorter side differs.
+  ///
+  /// # Panics
+  /// Panics if the trees do not have the same leaf size and fanout.
+  pub fn diff(&self, other: &ChecksumTree) -> Vec<Range<u64>> {
+    assert!(
+      self.leaf_size == other.leaf_size && self.fanout == other.fanout,
+      "Checksum trees must have the same leaf size and fanout"
+    );
+
+    let top = self.height().min(other.height()) - 1;
+    let count = self.levels[top].len().max(other.levels[top].len());
+
+    let mut ranges = Vec::new();
+    self.diff_blocks(other, top, 0..count, &mut ranges);
+
+    ranges
+  }
+
+  /// Returns the ranges of the data read from `reader` that differ from the data of the
+  /// tree, see [`diff`](Self::diff).
+  pub fn diff_reader<R: Read>(&self, reader: R) -> Result<Vec<Range<u64>>> {
+    let other = Self::from_reader(reader, self.leaf_size, self.fanout)?;
+
+    Ok(self.diff(&other))
+  }
+
+  /// Serializes the tree into `writer`.
+  ///
+  /// Only the leaves are stored, the upper levels are recomputed when reading.
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+    writer.write_all(&TREE_MAGIC)?;
+    writer.write_all(&self.leaf_size.to_be_bytes())?;
+    writer.write_all(&self.fanout.to_be_bytes())?;
+    writer.write_all(&self.len.to_be_bytes())?;
+
+    for checksum in &self.levels[0] {
+      writer.write_all(&checksum.to_be_bytes())?;
+    }
+
+    Ok(())
+  }
+
+  /// Deserializes a tree from `reader`.
+  pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+    if read_array::<_, [u8; 4]>(reader)? != TREE_MAGIC {
+      return Err(invalid_data("Invalid checksum tree magic"));
+    }
+
+    let leaf_size = read_u32(reader)?;
+    let fanout = read_u32(reader)?;
+    let len = read_u64(reader)?;
+
+    if leaf_size == 0 || fanout < 2 {
+      return Err(invalid_data("Invalid checksum tree shape"));
+    }
+
+    let count = len / u64::from(leaf_size) + u64::from(len % u64::from(leaf_size) != 0);
+    let mut leaves = Vec::new();
+    for _ in 0..count {
+      leaves.push(read_u32(reader)?);
+    }
+
+    Ok(Self::from_leaves(leaf_size, fanout, len, leaves))
+  }
+
+  fn from_leaves(leaf_size: u32, fanout: u32, len: u64, leaves: Vec<u32>) -> Self {
+    let mut tree = Self {
+      leaf_size,
+      fanout,
+      len,
+      levels: vec![leaves],
+    };
+
+    while tree.levels[tree.levels.len() - 1].len() > 1 {
+      let level = tree.levels.len() - 1;
+      let child_size = tree.block_size(level);
+
+      let parents = tree.levels[level]
+        .chunks(fanout as usize)
+        .enumerate()
+        .map(|(i, children)| {
+          let start = i as u64 * child_size * u64::from(fanout);
+
+          children
+            .iter()
+            .enumerate()
+            .fold(1, |checksum, (j, &child)| {
+              let offset = start + j as u64 * child_size;
+              adler32_combine(checksum, child, child_size.min(len - offset))
+            })
+        })
+        .collect();
+
+      tree.levels.push(parents);
+    }
+
+    tree
+  }
+
+  /// Returns the size of the full blocks of `level`.
+  fn block_size(&self, level: usize) -> u64 {
+    (0..level).fold(u64::from(self.leaf_size), |size, _| {
+      size.saturating_mul(u64::from(self.fanout))
+    })
+  }
+
+  /// Returns the checksum and length of block `i` of `level`.
+  fn block(&self, level: usize, i: usize) -> Option<(u32, u64)> {
+    let size = self.block_size(level);
+
+    self.levels[level]
+      .get(i)
+      .map(|&checksum| (checksum, size.min(self.len - i as u64 * size)))
+  }
+
+  fn diff_blocks(
+    &self,
+    other: &ChecksumTree,
+    level: usize,
+    blocks: Range<usize>,
+    ranges: &mut Vec<Range<u64>>,
+  ) {
+    let size = self.block_size(level);
+    let fanout = self.fanout as usize;
+
+    for i in blocks {
+      if self.block(level, i) == other.block(level, i) {
+        continue;
+      }
+
+      if level == 0 {
+        let start = i as u64 * size;
+        let end = (start + size).min(self.len.max(other.len));
+
+        match ranges.last_mut() {
+          Some(last) if last.end == start => last.end = end,
+          _ => ranges.push(start..end),
+        }
+      } else {
+        self.diff_blocks(other, level - 1, i * fanout..(i + 1) * fanout, ranges);
+      }
+    }
+  }
+}
+
+/// Returns the ranges where the data of `a` and `b` differ, see [`ChecksumTree::diff`].
+///
+/// # Panics
+/// Panics if `leaf_size` is zero or `fanout` is below two.
+pub fn diff(a: &[u8], b: &[u8], leaf_size: u32, fanout: u32) -> Vec<Range<u64>> {
+  ChecksumTree::new(a, leaf_size, fanout).diff(&ChecksumTree::new(b, leaf_size, fanout))
+}
+
+/// Returns the ranges where the data read from `a` and `b` differ, see
+/// [`ChecksumTree::diff`].
+///
+/// # Panics
+/// Panics if `leaf_size` is zero or `fanout` is below two.
+pub fn diff_readers<A: Read, B: Read>(
+  a: A,
+  b: B,
+  leaf_size: u32,
+  fanout: u32,
+) -> Result<Vec<Range<u64>>> {
+  let a = ChecksumTree::from_reader(a, leaf_size, fanout)?;
+  let b = ChecksumTree::from_reader(b, leaf_size, fanout)?;
+
+  Ok(a.diff(&b))
+}
+
+fn check_shape(leaf_size: u32, fanout: u32) {
+  assert!(leaf_size > 0, "Leaf size must not be zero");
+  assert!(fanout >= 2, "Fanout must be at least two");
+}
+
+/// Reads until `buf` is full or the reader is exhausted, returns the bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+  let mut filled = 0;
+
+  while filled < buf.len() {
+    match reader.read(&mut buf[filled..]) {
+      Ok(0) => break,
+      Ok(n) => filled += n,
+      Err(err) if err.kind() == ErrorKind::Interrupted => {}
+      Err(err) => return Err(err),
+    }
+  }
+
+  Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{diff, diff_readers, ChecksumTree};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn levels() {
+    let mut random = vec![0; 1024 * 100 + 7];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let tree = ChecksumTree::new(&random, 100, 4);
+    assert_eq!(tree.root(), adler2::adler32_slice(&random));
+    assert_eq!(tree.level(tree.height() - 1).len(), 1);
+
+    for level in 0..tree.height() {
+      let size = 100 * 4usize.pow(level as u32);
+
+      for (i, block) in random.chunks(size).enumerate() {
+        assert_eq!(
+          tree.level(level)[i],
+          adler2::adler32_slice(block),
+          "level({}) block({})",
+          level,
+          i
+        );
+      }
+    }
+
+    let empty = ChecksumTree::new(&[], 100, 4);
+    assert_eq!((empty.height(), empty.root()), (1, 1));
+  }
+
+  #[test]
+  fn from_reader() {
+    let mut random = vec![0; 1024 * 300 + 7];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &leaf_size in &[1, 100, 4096, 1024 * 100] {
+      let tree = ChecksumTree::from_reader(Trickle(&random), leaf_size, 8).unwrap();
+      assert_eq!(tree, ChecksumTree::new(&random, leaf_size, 8));
+    }
+  }
+
+  #[test]
+  fn ranges() {
+    let mut random = vec![0; 1024 * 256];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut modified = random.clone();
+    modified[10] ^= 1;
+    modified[5000] ^= 1;
+    modified[5100] ^= 1;
+    modified[200_000] ^= 1;
+
+    assert_eq!(diff(&random, &random, 1024, 4), vec![]);
+    assert_eq!(
+      diff(&random, &modified, 1024, 4),
+      vec![0..1024, 4096..5120, 199_680..200_704]
+    );
+
+    let truncated = &random[..100_000];
+    assert_eq!(diff(&random, truncated, 1024, 4), vec![99_328..262_144]);
+    assert_eq!(diff(truncated, &random, 1024, 4), vec![99_328..262_144]);
+    assert_eq!(diff(&random, &[], 1024, 4), vec![0..262_144]);
+  }
+
+  #[test]
+  fn readers() {
+    let mut random = vec![0; 1024 * 256];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut modified = random.clone();
+    modified[123_456] ^= 1;
+
+    let ranges = diff_readers(Trickle(&random), Trickle(&modified), 512, 16).unwrap();
+    assert_eq!(ranges, vec![123_392..123_904]);
+  }
+
+  #[test]
+  fn serialization() {
+    let mut random = vec![0; 1024 * 64 + 3];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let tree = ChecksumTree::new(&random, 1000, 3);
+    let mut buf = Vec::new();
+    tree.write_to(&mut buf).unwrap();
+
+    assert_eq!(buf.len(), 20 + 66 * 4);
+    assert_eq!(ChecksumTree::read_from(&mut &buf[..]).unwrap(), tree);
+
+    let mut corrupted = buf.clone();
+    corrupted[0] ^= 1;
+    assert!(ChecksumTree::read_from(&mut &corrupted[..]).is_err());
+    assert!(ChecksumTree::read_from(&mut &buf[..buf.len() - 1]).is_err());
+
+    let mut shape = buf.clone();
+    shape[8..12].copy_from_slice(&1u32.to_be_bytes());
+    assert!(ChecksumTree::read_from(&mut &shape[..]).is_err());
+  }
+
+  /// Reader returning at most 4099 bytes per read.
+  struct Trickle<'a>(&'a [u8]);
+
+  impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      let n = buf.len().min(self.0.len()).min(4099);
+
+      buf[..n].copy_from_slice(&self.0[..n]);
+      self.0 = &self.0[n..];
+
+      Ok(n)
+    }
+  }
+}
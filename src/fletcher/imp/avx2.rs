@@ -0,0 +1,286 @@
+use super::{Fletcher32Imp, Fletcher64Imp};
+
+/// Resolves Fletcher-32 update implementation if CPU supports avx2 instructions.
+pub fn get_imp32() -> Option<Fletcher32Imp> {
+  get_imp_inner().map(|(update32, _)| update32)
+}
+
+/// Resolves Fletcher-64 update implementation if CPU supports avx2 instructions.
+pub fn get_imp64() -> Option<Fletcher64Imp> {
+  get_imp_inner().map(|(_, update64)| update64)
+}
+
+#[inline]
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+fn get_imp_inner() -> Option<(Fletcher32Imp, Fletcher64Imp)> {
+  if std::is_x86_feature_detected!("avx2") {
+    Some((imp::update32, imp::update64))
+  } else {
+    None
+  }
+}
+
+#[inline]
+#[cfg(all(
+  target_feature = "avx2",
+  not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
+))]
+fn get_imp_inner() -> Option<(Fletcher32Imp, Fletcher64Imp)> {
+  Some((imp::update32, imp::update64))
+}
+
+#[inline]
+#[cfg(all(
+  not(target_feature = "avx2"),
+  not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
+))]
+fn get_imp_inner() -> Option<(Fletcher32Imp, Fletcher64Imp)> {
+  None
+}
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "avx2")
+))]
+mod imp {
+  const MOD32: u32 = 65535;
+  const NMAX32: usize = 360;
+  const BLOCK_SIZE32: usize = 32;
+  const CHUNK_SIZE32: usize = NMAX32 * 2 / BLOCK_SIZE32 * BLOCK_SIZE32;
+
+  const MOD64: u64 = 0xffff_ffff;
+  const NMAX64: usize = 92680;
+  const BLOCK_SIZE64: usize = 32;
+  const CHUNK_SIZE64: usize = NMAX64 * 4 / BLOCK_SIZE64 * BLOCK_SIZE64;
+
+  #[cfg(target_arch = "x86")]
+  use core::arch::x86::*;
+  #[cfg(target_arch = "x86_64")]
+  use core::arch::x86_64::*;
+
+  pub fn update32(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    unsafe { update32_imp(a, b, data) }
+  }
+
+  pub fn update64(a: u32, b: u32, data: &[u8]) -> (u32, u32) {
+    unsafe { update64_imp(a, b, data) }
+  }
+
+  #[inline]
+  #[target_feature(enable = "avx2")]
+  unsafe fn update32_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    debug_assert_eq!(data.len() % 2, 0, "Unexpected partial word");
+
+    let mut a = a as u32;
+    let mut b = b as u32;
+
+    for chunk in data.chunks(CHUNK_SIZE32) {
+      for word in reduce_add_blocks32(&mut a, &mut b, chunk).chunks_exact(2) {
+        a += u32::from(word[0]) | u32::from(word[1]) << 8;
+        b += a;
+      }
+
+      a %= MOD32;
+      b %= MOD32;
+    }
+
+    (a as u16, b as u16)
+  }
+
+  #[inline]
+  #[target_feature(enable = "avx2")]
+  unsafe fn update64_imp(a: u32, b: u32, data: &[u8]) -> (u32, u32) {
+    debug_assert_eq!(data.len() % 4, 0, "Unexpected partial word");
+
+    let mut a = a as u64;
+    let mut b = b as u64;
+
+    for chunk in data.chunks(CHUNK_SIZE64) {
+      for word in reduce_add_blocks64(&mut a, &mut b, chunk).chunks_exact(4) {
+        a += u64::from(word[0])
+          | u64::from(word[1]) << 8
+          | u64::from(word[2]) << 16
+          | u64::from(word[3]) << 24;
+        b += a;
+      }
+
+      a %= MOD64;
+      b %= MOD64;
+    }
+
+    (a as u32, b as u32)
+  }
+
+  /// Sums 16-bit words using signed multiply-adds.
+  ///
+  /// Words are biased by `-32768` to fit `_mm256_madd_epi16`, the bias is removed from the
+  /// reduced sums.  Lanes wrap, the reduced sums are exact as long as the chunk fits
+  /// within `NMAX32` words.
+  #[inline(always)]
+  unsafe fn reduce_add_blocks32<'a>(
+    a: &mut u32,
+    b: &mut u32,
+    chunk: &'a [u8],
+  ) -> &'a [u8] {
+    if chunk.len() < BLOCK_SIZE32 {
+      return chunk;
+    }
+
+    let blocks = chunk.chunks_exact(BLOCK_SIZE32);
+    let blocks_remainder = blocks.remainder();
+    let words = (blocks.len() * BLOCK_SIZE32 / 2) as u32;
+
+    let one_v = _mm256_set1_epi16(1);
+    let bias_v = _mm256_set1_epi16(-0x8000);
+    let weights = _mm256_set_epi16(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16);
+
+    let mut p_v = _mm256_setzero_si256();
+    let mut a_v = _mm256_setzero_si256();
+    let mut b_v = _mm256_setzero_si256();
+
+    for block in blocks {
+      let block = _mm256_loadu_si256(block.as_ptr() as *const _);
+      let block = _mm256_xor_si256(block, bias_v);
+
+      p_v = _mm256_add_epi32(p_v, a_v);
+
+      a_v = _mm256_add_epi32(a_v, _mm256_madd_epi16(block, one_v));
+      b_v = _mm256_add_epi32(b_v, _mm256_madd_epi16(block, weights));
+    }
+
+    b_v = _mm256_add_epi32(b_v, _mm256_slli_epi32(p_v, 4));
+
+    let sum_a = reduce_add32(a_v).wrapping_add(words << 15);
+    let sum_b = reduce_add32(b_v).wrapping_add(triangular(words) << 15);
+
+    *b = b.wrapping_add(a.wrapping_mul(words)).wrapping_add(sum_b);
+    *a = a.wrapping_add(sum_a);
+
+    blocks_remainder
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add_blocks64<'a>(
+    a: &mut u64,
+    b: &mut u64,
+    chunk: &'a [u8],
+  ) -> &'a [u8] {
+    if chunk.len() < BLOCK_SIZE64 {
+      return chunk;
+    }
+
+    let blocks = chunk.chunks_exact(BLOCK_SIZE64);
+    let blocks_remainder = blocks.remainder();
+    let words = (blocks.len() * BLOCK_SIZE64 / 4) as u64;
+
+    let weight_lo_v = _mm256_set_epi64x(5, 6, 7, 8);
+    let weight_hi_v = _mm256_set_epi64x(1, 2, 3, 4);
+
+    let mut p_v = _mm256_setzero_si256();
+    let mut a_v = _mm256_setzero_si256();
+    let mut b_v = _mm256_setzero_si256();
+
+    for block in blocks {
+      let block_ptr = block.as_ptr() as *const __m128i;
+      let lo_v = _mm256_cvtepu32_epi64(_mm_loadu_si128(block_ptr));
+      let hi_v = _mm256_cvtepu32_epi64(_mm_loadu_si128(block_ptr.add(1)));
+
+      p_v = _mm256_add_epi64(p_v, a_v);
+
+      a_v = _mm256_add_epi64(a_v, _mm256_add_epi64(lo_v, hi_v));
+      b_v = _mm256_add_epi64(b_v, _mm256_mul_epu32(lo_v, weight_lo_v));
+      b_v = _mm256_add_epi64(b_v, _mm256_mul_epu32(hi_v, weight_hi_v));
+    }
+
+    b_v = _mm256_add_epi64(b_v, _mm256_slli_epi64(p_v, 3));
+
+    *b += *a * words + reduce_add64(b_v);
+    *a += reduce_add64(a_v);
+
+    blocks_remainder
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add32(v: __m256i) -> u32 {
+    let sum = _mm_add_epi32(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
+    let hi = _mm_unpackhi_epi64(sum, sum);
+
+    let sum = _mm_add_epi32(hi, sum);
+    let hi = _mm_shuffle_epi32(sum, crate::imp::_MM_SHUFFLE(2, 3, 0, 1));
+
+    let sum = _mm_add_epi32(sum, hi);
+
+    _mm_cvtsi128_si32(sum) as _
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add64(v: __m256i) -> u64 {
+    let v: [u64; 4] = core::mem::transmute(v);
+
+    v[0] + v[1] + v[2] + v[3]
+  }
+
+  /// Returns `n * (n + 1) / 2`.
+  #[inline(always)]
+  fn triangular(n: u32) -> u32 {
+    n * (n + 1) / 2
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn zeroes() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0; 4]);
+    assert_sum_eq(&[0; 100]);
+    assert_sum_eq(&[0; 1024]);
+    assert_sum_eq(&[0; 1024 - 4]);
+    #[cfg(not(miri))]
+    assert_sum_eq(&[0; 1024 * 1024]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0xff; 4]);
+    assert_sum_eq(&[0xff; 100]);
+    assert_sum_eq(&[0xff; 1024]);
+    assert_sum_eq(&[0xff; 1024 - 4]); // non-power-of-2 to test remainder handling
+    #[cfg(not(miri))]
+    assert_sum_eq(&[0xff; 1024 * 1024]);
+  }
+
+  #[test]
+  fn random() {
+    if super::get_imp32().is_none() {
+      return;
+    } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sum_eq(&random[..4]);
+    assert_sum_eq(&random[..100]);
+    assert_sum_eq(&random[..1024]);
+    assert_sum_eq(&random[..1024 - 4]); // non-power-of-2 to test remainder handling
+    assert_sum_eq(&random[..1024 * 10]);
+  }
+
+  fn assert_sum_eq(data: &[u8]) {
+    if let Some(update) = super::get_imp32() {
+      let left = update(65534, 65534, data);
+      let right = crate::fletcher::imp::scalar::update32(65534, 65534, data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+
+    if let Some(update) = super::get_imp64() {
+      let left = update(0xffff_fffe, 0xffff_fffe, data);
+      let right = crate::fletcher::imp::scalar::update64(0xffff_fffe, 0xffff_fffe, data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+  }
+}
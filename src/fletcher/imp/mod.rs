@@ -0,0 +1,25 @@
+pub mod avx2;
+pub mod scalar;
+pub mod sse2;
+
+/// Fletcher-32 update function over little-endian 16-bit words.
+///
+/// The length of the supplied data must be a multiple of `2`.
+pub type Fletcher32Imp = fn(u16, u16, &[u8]) -> (u16, u16);
+
+/// Fletcher-64 update function over little-endian 32-bit words.
+///
+/// The length of the supplied data must be a multiple of `4`.
+pub type Fletcher64Imp = fn(u32, u32, &[u8]) -> (u32, u32);
+
+pub fn get_imp32() -> Fletcher32Imp {
+  avx2::get_imp32()
+    .or_else(sse2::get_imp32)
+    .unwrap_or(scalar::update32)
+}
+
+pub fn get_imp64() -> Fletcher64Imp {
+  avx2::get_imp64()
+    .or_else(sse2::get_imp64)
+    .unwrap_or(scalar::update64)
+}
@@ -0,0 +1,82 @@
+const MOD32: u32 = 65535;
+const NMAX32: usize = 360;
+const MOD64: u64 = 0xffff_ffff;
+const NMAX64: usize = 92680;
+
+pub fn update32(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+  debug_assert_eq!(data.len() % 2, 0, "Unexpected partial word");
+
+  let mut a = a as u32;
+  let mut b = b as u32;
+
+  for chunk in data.chunks(NMAX32 * 2) {
+    for word in chunk.chunks_exact(2) {
+      a += u32::from(word[0]) | u32::from(word[1]) << 8;
+      b += a;
+    }
+
+    a %= MOD32;
+    b %= MOD32;
+  }
+
+  (a as u16, b as u16)
+}
+
+pub fn update64(a: u32, b: u32, data: &[u8]) -> (u32, u32) {
+  debug_assert_eq!(data.len() % 4, 0, "Unexpected partial word");
+
+  let mut a = a as u64;
+  let mut b = b as u64;
+
+  for chunk in data.chunks(NMAX64 * 4) {
+    for word in chunk.chunks_exact(4) {
+      a += u64::from(word[0])
+        | u64::from(word[1]) << 8
+        | u64::from(word[2]) << 16
+        | u64::from(word[3]) << 24;
+      b += a;
+    }
+
+    a %= MOD64;
+    b %= MOD64;
+  }
+
+  (a as u32, b as u32)
+}
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn zeroes() {
+    assert_eq!(fletcher32(&[]), 0);
+    assert_eq!(fletcher32(&[0; 1024]), 0);
+    assert_eq!(fletcher64(&[]), 0);
+    assert_eq!(fletcher64(&[0; 1024]), 0);
+  }
+
+  #[test]
+  fn ones() {
+    assert_eq!(fletcher32(&[0xff; 1024]), 0);
+    assert_eq!(fletcher64(&[0xff; 1024]), 0);
+  }
+
+  /// Example calculations from https://en.wikipedia.org/wiki/Fletcher%27s_checksum.
+  #[test]
+  fn wiki() {
+    assert_eq!(fletcher32(b"abcdef"), 0x56502d2a);
+    assert_eq!(fletcher32(b"abcdefgh"), 0xebe19591);
+    assert_eq!(fletcher64(b"abcdefgh"), 0x312e2b28cccac8c6);
+  }
+
+  fn fletcher32(data: &[u8]) -> u32 {
+    let (a, b) = super::update32(0, 0, data);
+
+    u32::from(b) << 16 | u32::from(a)
+  }
+
+  fn fletcher64(data: &[u8]) -> u64 {
+    let (a, b) = super::update64(0, 0, data);
+
+    u64::from(b) << 32 | u64::from(a)
+  }
+}
@@ -0,0 +1,504 @@
+//! Fletcher checksums.
+//!
+//! Adler-32 is a modification of Fletcher's checksum, the same SIMD structure computes
+//! Fletcher-16 over bytes, Fletcher-32 over 16-bit words and Fletcher-64 over 32-bit
+//! words using ones' complement moduli (`255`, `65535` and `2^32 - 1`).
+//!
+//! Words are read as little-endian and a trailing partial word is padded with zeroes.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::fletcher::{fletcher32, Fletcher32};
+//!
+//! let mut hash = Fletcher32::new();
+//! hash.write(b"abcd");
+//! hash.write(b"ef");
+//!
+//! assert_eq!(hash.finish(), 0x56502d2a);
+//! assert_eq!(fletcher32(b"abcdef"), 0x56502d2a);
+//! ```
+use crate::imp::{get_mod_imp, AdlerModImp, Modulus};
+
+#[doc(hidden)]
+pub mod imp;
+
+use imp::{get_imp32, get_imp64, Fletcher32Imp, Fletcher64Imp};
+
+const MOD16: u32 = 255;
+const MODULUS16: Modulus = Modulus::new(MOD16);
+
+/// Compute Fletcher-16 checksum of `data`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::fletcher::fletcher16;
+///
+/// let hash = fletcher16(b"abcde");
+/// println!("{:#x}", hash); // 0xc8f0
+/// ```
+pub fn fletcher16(data: &[u8]) -> u16 {
+  let mut hash = Fletcher16::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// Compute Fletcher-32 checksum of `data`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::fletcher::fletcher32;
+///
+/// let hash = fletcher32(b"abcde");
+/// println!("{:#x}", hash); // 0xf04fc729
+/// ```
+pub fn fletcher32(data: &[u8]) -> u32 {
+  let mut hash = Fletcher32::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// Compute Fletcher-64 checksum of `data`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::fletcher::fletcher64;
+///
+/// let hash = fletcher64(b"abcde");
+/// println!("{:#x}", hash); // 0xc8c6c527646362c6
+/// ```
+pub fn fletcher64(data: &[u8]) -> u64 {
+  let mut hash = Fletcher64::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// A Fletcher-16 hash generator type.
+#[derive(Clone)]
+pub struct Fletcher16 {
+  a: u16,
+  b: u16,
+  update: AdlerModImp,
+}
+
+impl Fletcher16 {
+  /// Constructs a new `Fletcher16`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher::Fletcher16;
+  ///
+  /// let mut hash = Fletcher16::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Constructs a new `Fletcher16` using existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher::Fletcher16;
+  ///
+  /// let mut hash = Fletcher16::from_checksum(0xc8f0);
+  /// ```
+  pub fn from_checksum(checksum: u16) -> Self {
+    Self {
+      a: (checksum & 0xff) % MOD16 as u16,
+      b: (checksum >> 8) % MOD16 as u16,
+      update: get_mod_imp(),
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  pub fn write(&mut self, data: &[u8]) {
+    let (a, b) = (self.update)(self.a, self.b, data, MODULUS16);
+
+    self.a = a;
+    self.b = b;
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> u16 {
+    (self.b << 8) | self.a
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.a = 0;
+    self.b = 0;
+  }
+}
+
+impl Default for Fletcher16 {
+  fn default() -> Self {
+    Self {
+      a: 0,
+      b: 0,
+      update: get_mod_imp(),
+    }
+  }
+}
+
+/// A Fletcher-32 hash generator type.
+#[derive(Clone)]
+pub struct Fletcher32 {
+  a: u16,
+  b: u16,
+  tail: Tail,
+  update: Fletcher32Imp,
+}
+
+impl Fletcher32 {
+  /// Constructs a new `Fletcher32`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher::Fletcher32;
+  ///
+  /// let mut hash = Fletcher32::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Constructs a new `Fletcher32` using existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher::Fletcher32;
+  ///
+  /// let mut hash = Fletcher32::from_checksum(0xf04fc729);
+  /// ```
+  pub fn from_checksum(checksum: u32) -> Self {
+    Self {
+      a: (checksum as u16) % 0xffff,
+      b: ((checksum >> 16) as u16) % 0xffff,
+      tail: Tail::new(),
+      update: get_imp32(),
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  ///
+  /// Data does not need to be word aligned, a trailing partial word is kept until the
+  /// next write.
+  pub fn write(&mut self, data: &[u8]) {
+    let (a, b, update) = (&mut self.a, &mut self.b, self.update);
+
+    self.tail.write(data, 2, |words| {
+      let (a_, b_) = update(*a, *b, words);
+
+      *a = a_;
+      *b = b_;
+    });
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// A trailing partial word is padded with zeroes without modifying the internal state.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> u32 {
+    let (a, b) = match self.tail.padded() {
+      Some(word) => (self.update)(self.a, self.b, &word[..2]),
+      None => (self.a, self.b),
+    };
+
+    (u32::from(b) << 16) | u32::from(a)
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.a = 0;
+    self.b = 0;
+    self.tail = Tail::new();
+  }
+}
+
+impl Default for Fletcher32 {
+  fn default() -> Self {
+    Self {
+      a: 0,
+      b: 0,
+      tail: Tail::new(),
+      update: get_imp32(),
+    }
+  }
+}
+
+/// A Fletcher-64 hash generator type.
+#[derive(Clone)]
+pub struct Fletcher64 {
+  a: u32,
+  b: u32,
+  tail: Tail,
+  update: Fletcher64Imp,
+}
+
+impl Fletcher64 {
+  /// Constructs a new `Fletcher64`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher::Fletcher64;
+  ///
+  /// let mut hash = Fletcher64::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Constructs a new `Fletcher64` using existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher::Fletcher64;
+  ///
+  /// let mut hash = Fletcher64::from_checksum(0xc8c6c527646362c6);
+  /// ```
+  pub fn from_checksum(checksum: u64) -> Self {
+    Self {
+      a: (checksum as u32) % 0xffff_ffff,
+      b: ((checksum >> 32) as u32) % 0xffff_ffff,
+      tail: Tail::new(),
+      update: get_imp64(),
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  ///
+  /// Data does not need to be word aligned, a trailing partial word is kept until the
+  /// next write.
+  pub fn write(&mut self, data: &[u8]) {
+    let (a, b, update) = (&mut self.a, &mut self.b, self.update);
+
+    self.tail.write(data, 4, |words| {
+      let (a_, b_) = update(*a, *b, words);
+
+      *a = a_;
+      *b = b_;
+    });
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// A trailing partial word is padded with zeroes without modifying the internal state.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> u64 {
+    let (a, b) = match self.tail.padded() {
+      Some(word) => (self.update)(self.a, self.b, &word[..4]),
+      None => (self.a, self.b),
+    };
+
+    (u64::from(b) << 32) | u64::from(a)
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.a = 0;
+    self.b = 0;
+    self.tail = Tail::new();
+  }
+}
+
+impl Default for Fletcher64 {
+  fn default() -> Self {
+    Self {
+      a: 0,
+      b: 0,
+      tail: Tail::new(),
+      update: get_imp64(),
+    }
+  }
+}
+
+/// Partial word kept between writes.
+#[derive(Clone, Copy)]
+pub(crate) struct Tail {
+  buf: [u8; 4],
+  len: usize,
+}
+
+impl Tail {
+  pub(crate) fn new() -> Self {
+    Self {
+      buf: [0; 4],
+      len: 0,
+    }
+  }
+
+  /// Feeds whole `size` byte words to `update`, keeping any trailing partial word.
+  pub(crate) fn write<F: FnMut(&[u8])>(
+    &mut self,
+    mut data: &[u8],
+    size: usize,
+    mut update: F,
+  ) {
+    if self.len > 0 {
+      let n = (size - self.len).min(data.len());
+
+      self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+      self.len += n;
+      data = &data[n..];
+
+      if self.len < size {
+        return;
+      }
+
+      update(&self.buf[..size]);
+      self.len = 0;
+    }
+
+    let split = data.len() - data.len() % size;
+    if split > 0 {
+      update(&data[..split]);
+    }
+
+    let rest = &data[split..];
+    self.buf[..rest.len()].copy_from_slice(rest);
+    self.len = rest.len();
+  }
+
+  /// Returns the partial word padded with zeroes if there is one.
+  pub(crate) fn padded(&self) -> Option<[u8; 4]> {
+    if self.len == 0 {
+      return None;
+    }
+
+    let mut word = [0; 4];
+    word[..self.len].copy_from_slice(&self.buf[..self.len]);
+
+    Some(word)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{fletcher16, fletcher32, fletcher64, Fletcher16, Fletcher32, Fletcher64};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  /// Example calculations from https://en.wikipedia.org/wiki/Fletcher%27s_checksum.
+  #[test]
+  fn wiki() {
+    assert_eq!(fletcher16(b"abcde"), 0xc8f0);
+    assert_eq!(fletcher16(b"abcdef"), 0x2057);
+    assert_eq!(fletcher16(b"abcdefgh"), 0x0627);
+
+    assert_eq!(fletcher32(b"abcde"), 0xf04fc729);
+    assert_eq!(fletcher32(b"abcdef"), 0x56502d2a);
+    assert_eq!(fletcher32(b"abcdefgh"), 0xebe19591);
+
+    assert_eq!(fletcher64(b"abcde"), 0xc8c6c527646362c6);
+    assert_eq!(fletcher64(b"abcdef"), 0xc8c72b276463c8c6);
+    assert_eq!(fletcher64(b"abcdefgh"), 0x312e2b28cccac8c6);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &len in &[1, 3, 100, 1024 - 5, 5553, 1024 * 100] {
+      let data = &random[..len];
+
+      assert_eq!(fletcher16(data), reference16(data), "len({})", len);
+      assert_eq!(fletcher32(data), reference32(data), "len({})", len);
+      assert_eq!(fletcher64(data), reference64(data), "len({})", len);
+    }
+  }
+
+  #[test]
+  fn streaming() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut f16 = Fletcher16::new();
+    let mut f32 = Fletcher32::new();
+    let mut f64 = Fletcher64::new();
+
+    let mut offset = 0;
+    for size in (0..).map(|i| i % 7) {
+      let end = (offset + size).min(random.len());
+
+      f16.write(&random[offset..end]);
+      f32.write(&random[offset..end]);
+      f64.write(&random[offset..end]);
+
+      let data = &random[..end];
+      assert_eq!(f16.finish(), reference16(data), "len({})", end);
+      assert_eq!(f32.finish(), reference32(data), "len({})", end);
+      assert_eq!(f64.finish(), reference64(data), "len({})", end);
+
+      offset = end;
+      if offset == random.len() {
+        break;
+      }
+    }
+  }
+
+  #[test]
+  fn from_checksum() {
+    let mut f16 = Fletcher16::from_checksum(fletcher16(b"abcd"));
+    let mut f32 = Fletcher32::from_checksum(fletcher32(b"abcd"));
+    let mut f64 = Fletcher64::from_checksum(fletcher64(b"abcd"));
+
+    f16.write(b"efgh");
+    f32.write(b"efgh");
+    f64.write(b"efgh");
+
+    assert_eq!(f16.finish(), fletcher16(b"abcdefgh"));
+    assert_eq!(f32.finish(), fletcher32(b"abcdefgh"));
+    assert_eq!(f64.finish(), fletcher64(b"abcdefgh"));
+  }
+
+  fn reference16(data: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u32, 0u32);
+
+    for byte in data {
+      a = (a + u32::from(*byte)) % 255;
+      b = (b + a) % 255;
+    }
+
+    (b << 8 | a) as u16
+  }
+
+  fn reference32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (0u32, 0u32);
+
+    for word in data.chunks(2) {
+      let lo = u32::from(word[0]);
+      let hi = u32::from(*word.get(1).unwrap_or(&0));
+
+      a = (a + (hi << 8 | lo)) % 65535;
+      b = (b + a) % 65535;
+    }
+
+    b << 16 | a
+  }
+
+  fn reference64(data: &[u8]) -> u64 {
+    let (mut a, mut b) = (0u64, 0u64);
+
+    for word in data.chunks(4) {
+      let mut buf = [0; 4];
+      buf[..word.len()].copy_from_slice(word);
+
+      a = (a + u64::from(u32::from_le_bytes(buf))) % 0xffff_ffff;
+      b = (b + a) % 0xffff_ffff;
+    }
+
+    b << 32 | a
+  }
+}
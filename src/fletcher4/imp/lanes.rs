@@ -0,0 +1,124 @@
+/// Combines the accumulators of interleaved lanes into `sums`.
+///
+/// Lane `j` of `k` lanes holds the Fletcher-4 sums, starting from zero, of words
+/// `j, j + k, j + 2k, ...` for a total of `words` words. A word at lane position `u`,
+/// counted from the end of its lane, has weights `1`, `u`, `u(u + 1) / 2` and
+/// `u(u + 1)(u + 2) / 6` within the lane, and weights `1`, `ku - j`, `(ku - j + 1)` choose
+/// `2` and `(ku - j + 2)` choose `3` within the whole input. The latter are rewritten in
+/// terms of the former so lanes can be combined without revisiting the input.
+#[inline]
+pub fn combine(sums: [u64; 4], lanes: &[[u64; 4]], words: u64) -> [u64; 4] {
+  let [a, b, c, d] = sums;
+  let k = lanes.len() as i64;
+  let n2 = binomial2(words);
+  let n3 = binomial3(words);
+
+  let mut sums = [
+    a,
+    b.wrapping_add(words.wrapping_mul(a)),
+    c.wrapping_add(words.wrapping_mul(b))
+      .wrapping_add(n2.wrapping_mul(a)),
+    d.wrapping_add(words.wrapping_mul(c))
+      .wrapping_add(n2.wrapping_mul(b))
+      .wrapping_add(n3.wrapping_mul(a)),
+  ];
+
+  for (j, lane) in lanes.iter().enumerate() {
+    let j = j as i64;
+    let weights = [
+      [1, 0, 0, 0],
+      coefficients(|u| k * u - j),
+      coefficients(|u| (k * u - j + 1) * (k * u - j) / 2),
+      coefficients(|u| (k * u - j + 2) * (k * u - j + 1) * (k * u - j) / 6),
+    ];
+
+    for (sum, weights) in sums.iter_mut().zip(weights.iter()) {
+      for (value, weight) in lane.iter().zip(weights.iter()) {
+        *sum = sum.wrapping_add(value.wrapping_mul(*weight as u64));
+      }
+    }
+  }
+
+  sums
+}
+
+/// Expresses the polynomial `f` of degree `<= 3` in the basis `1`, `u`, `u(u + 1) / 2`
+/// and `u(u + 1)(u + 2) / 6` by evaluating it at `0`, `-1`, `-2` and `-3`.
+#[inline(always)]
+fn coefficients<F: Fn(i64) -> i64>(f: F) -> [i64; 4] {
+  let c0 = f(0);
+  let c1 = c0 - f(-1);
+  let c2 = f(-2) - c0 + 2 * c1;
+  let c3 = c0 - 3 * c1 + 3 * c2 - f(-3);
+
+  [c0, c1, c2, c3]
+}
+
+/// Returns `n(n + 1) / 2` modulo `2^64`.
+#[inline(always)]
+fn binomial2(n: u64) -> u64 {
+  if n & 1 == 0 {
+    (n / 2).wrapping_mul(n.wrapping_add(1))
+  } else {
+    n.wrapping_mul(n / 2 + 1)
+  }
+}
+
+/// Returns `n(n + 1)(n + 2) / 6` modulo `2^64`.
+#[inline(always)]
+fn binomial3(n: u64) -> u64 {
+  let mut factors = [n, n.wrapping_add(1), n.wrapping_add(2)];
+
+  for &divisor in &[3, 2] {
+    if let Some(factor) = factors.iter_mut().find(|factor| **factor % divisor == 0) {
+      *factor /= divisor;
+    }
+  }
+
+  factors[0].wrapping_mul(factors[1]).wrapping_mul(factors[2])
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::fletcher4::imp::scalar;
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn combine() {
+    let mut random = [0; 4 * 8 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let sums = [1, 2, 3, !0];
+
+    for &k in &[1, 2, 4, 8] {
+      let words = random.len() / 4;
+      let mut lanes = [[0; 4]; 8];
+
+      for (j, lane) in lanes[..k].iter_mut().enumerate() {
+        for word in (j..words).step_by(k) {
+          *lane = scalar::update_native(*lane, &random[word * 4..word * 4 + 4]);
+        }
+      }
+
+      assert_eq!(
+        super::combine(sums, &lanes[..k], words as u64),
+        scalar::update_native(sums, &random),
+        "lanes({})",
+        k
+      );
+    }
+  }
+
+  #[test]
+  fn binomial() {
+    for n in 0..100u64 {
+      assert_eq!(super::binomial2(n), n * (n + 1) / 2);
+      assert_eq!(super::binomial3(n), n * (n + 1) * (n + 2) / 6);
+    }
+
+    for &n in &[1u64 << 40, (1 << 40) + 1, (1 << 40) + 2] {
+      let expected = u128::from(n) * u128::from(n + 1) * u128::from(n + 2) / 6;
+      assert_eq!(super::binomial3(n), expected as u64);
+    }
+  }
+}
@@ -0,0 +1,42 @@
+pub mod avx2;
+pub mod avx512;
+// Only built with a lane kernel using it.
+#[cfg(any(
+  all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    any(feature = "std", target_feature = "sse2")
+  ),
+  all(
+    target_feature = "neon",
+    target_endian = "little",
+    any(target_arch = "aarch64", feature = "nightly")
+  )
+))]
+mod lanes;
+pub mod neon;
+pub mod scalar;
+pub mod sse2;
+pub mod ssse3;
+
+/// Fletcher-4 update function over 32-bit words.
+///
+/// A trailing partial word is ignored.
+pub type Fletcher4Imp = fn([u64; 4], &[u8]) -> [u64; 4];
+
+pub fn get_native_imp() -> Fletcher4Imp {
+  avx512::get_native_imp()
+    .or_else(neon::get_native_imp)
+    .or_else(avx2::get_native_imp)
+    .or_else(ssse3::get_native_imp)
+    .or_else(sse2::get_native_imp)
+    .unwrap_or(scalar::update_native)
+}
+
+pub fn get_byteswap_imp() -> Fletcher4Imp {
+  avx512::get_byteswap_imp()
+    .or_else(neon::get_byteswap_imp)
+    .or_else(avx2::get_byteswap_imp)
+    .or_else(ssse3::get_byteswap_imp)
+    .or_else(sse2::get_byteswap_imp)
+    .unwrap_or(scalar::update_byteswap)
+}
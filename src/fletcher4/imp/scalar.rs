@@ -0,0 +1,53 @@
+pub fn update_native(sums: [u64; 4], data: &[u8]) -> [u64; 4] {
+  update_words(sums, data, false)
+}
+
+pub fn update_byteswap(sums: [u64; 4], data: &[u8]) -> [u64; 4] {
+  update_words(sums, data, true)
+}
+
+#[inline(always)]
+fn update_words(sums: [u64; 4], data: &[u8], byteswap: bool) -> [u64; 4] {
+  let [mut a, mut b, mut c, mut d] = sums;
+
+  for word in data.chunks_exact(4) {
+    let word = u32::from_ne_bytes([word[0], word[1], word[2], word[3]]);
+    let word = if byteswap { word.swap_bytes() } else { word };
+
+    a = a.wrapping_add(u64::from(word));
+    b = b.wrapping_add(a);
+    c = c.wrapping_add(b);
+    d = d.wrapping_add(c);
+  }
+
+  [a, b, c, d]
+}
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn zeroes() {
+    assert_eq!(super::update_native([0; 4], &[]), [0; 4]);
+    assert_eq!(super::update_native([0; 4], &[0; 1024]), [0; 4]);
+    assert_eq!(super::update_byteswap([0; 4], &[0; 1024]), [0; 4]);
+  }
+
+  #[test]
+  fn words() {
+    let mut data = [0; 8];
+    data[..4].copy_from_slice(&1u32.to_ne_bytes());
+    data[4..].copy_from_slice(&2u32.to_ne_bytes());
+
+    assert_eq!(super::update_native([0; 4], &data), [3, 4, 5, 6]);
+
+    data[..4].copy_from_slice(&1u32.swap_bytes().to_ne_bytes());
+    data[4..].copy_from_slice(&2u32.swap_bytes().to_ne_bytes());
+
+    assert_eq!(super::update_byteswap([0; 4], &data), [3, 4, 5, 6]);
+  }
+
+  #[test]
+  fn partial_word() {
+    assert_eq!(super::update_native([0; 4], &[1, 2, 3]), [0; 4]);
+  }
+}
@@ -0,0 +1,195 @@
+use super::Fletcher4Imp;
+
+/// Resolves native update implementation if CPU supports sse2 instructions.
+pub fn get_native_imp() -> Option<Fletcher4Imp> {
+  get_imp_inner().map(|(native, _)| native)
+}
+
+/// Resolves byteswap update implementation if CPU supports sse2 instructions.
+pub fn get_byteswap_imp() -> Option<Fletcher4Imp> {
+  get_imp_inner().map(|(_, byteswap)| byteswap)
+}
+
+#[inline]
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+fn get_imp_inner() -> Option<(Fletcher4Imp, Fletcher4Imp)> {
+  if std::is_x86_feature_detected!("sse2") {
+    Some((imp::update_native, imp::update_byteswap))
+  } else {
+    None
+  }
+}
+
+#[inline]
+#[cfg(all(
+  target_feature = "sse2",
+  not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
+))]
+fn get_imp_inner() -> Option<(Fletcher4Imp, Fletcher4Imp)> {
+  Some((imp::update_native, imp::update_byteswap))
+}
+
+#[inline]
+#[cfg(all(
+  not(target_feature = "sse2"),
+  not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
+))]
+fn get_imp_inner() -> Option<(Fletcher4Imp, Fletcher4Imp)> {
+  None
+}
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "sse2")
+))]
+mod imp {
+  use crate::fletcher4::imp::{lanes::combine, scalar};
+
+  const BLOCK_SIZE: usize = 16;
+
+  #[cfg(target_arch = "x86")]
+  use core::arch::x86::*;
+  #[cfg(target_arch = "x86_64")]
+  use core::arch::x86_64::*;
+
+  pub fn update_native(sums: [u64; 4], data: &[u8]) -> [u64; 4] {
+    unsafe { update_native_imp(sums, data) }
+  }
+
+  pub fn update_byteswap(sums: [u64; 4], data: &[u8]) -> [u64; 4] {
+    unsafe { update_byteswap_imp(sums, data) }
+  }
+
+  #[inline]
+  #[target_feature(enable = "sse2")]
+  unsafe fn update_native_imp(sums: [u64; 4], data: &[u8]) -> [u64; 4] {
+    let (sums, remainder) = update_lanes(sums, data, false);
+
+    scalar::update_native(sums, remainder)
+  }
+
+  #[inline]
+  #[target_feature(enable = "sse2")]
+  unsafe fn update_byteswap_imp(sums: [u64; 4], data: &[u8]) -> [u64; 4] {
+    let (sums, remainder) = update_lanes(sums, data, true);
+
+    scalar::update_byteswap(sums, remainder)
+  }
+
+  /// Sums words in four interleaved lanes, two per vector.
+  #[inline(always)]
+  unsafe fn update_lanes(
+    sums: [u64; 4],
+    data: &[u8],
+    byteswap: bool,
+  ) -> ([u64; 4], &[u8]) {
+    let blocks = data.chunks_exact(BLOCK_SIZE);
+    let blocks_remainder = blocks.remainder();
+    let words = (blocks.len() * BLOCK_SIZE / 4) as u64;
+
+    let zero_v = _mm_setzero_si128();
+    let mut a_v = [zero_v; 2];
+    let mut b_v = [zero_v; 2];
+    let mut c_v = [zero_v; 2];
+    let mut d_v = [zero_v; 2];
+
+    for block in blocks {
+      let block = _mm_loadu_si128(block.as_ptr() as *const _);
+      let block = if byteswap { bswap(block) } else { block };
+      let block = [
+        _mm_unpacklo_epi32(block, zero_v),
+        _mm_unpackhi_epi32(block, zero_v),
+      ];
+
+      for i in 0..2 {
+        a_v[i] = _mm_add_epi64(a_v[i], block[i]);
+        b_v[i] = _mm_add_epi64(b_v[i], a_v[i]);
+        c_v[i] = _mm_add_epi64(c_v[i], b_v[i]);
+        d_v[i] = _mm_add_epi64(d_v[i], c_v[i]);
+      }
+    }
+
+    let a: [u64; 4] = core::mem::transmute(a_v);
+    let b: [u64; 4] = core::mem::transmute(b_v);
+    let c: [u64; 4] = core::mem::transmute(c_v);
+    let d: [u64; 4] = core::mem::transmute(d_v);
+
+    let lanes = [
+      [a[0], b[0], c[0], d[0]],
+      [a[1], b[1], c[1], d[1]],
+      [a[2], b[2], c[2], d[2]],
+      [a[3], b[3], c[3], d[3]],
+    ];
+
+    (combine(sums, &lanes, words), blocks_remainder)
+  }
+
+  /// Swaps the bytes of every 32-bit word.
+  #[inline(always)]
+  unsafe fn bswap(v: __m128i) -> __m128i {
+    let v = _mm_shufflelo_epi16(v, crate::imp::_MM_SHUFFLE(2, 3, 0, 1));
+    let v = _mm_shufflehi_epi16(v, crate::imp::_MM_SHUFFLE(2, 3, 0, 1));
+
+    _mm_or_si128(_mm_slli_epi16(v, 8), _mm_srli_epi16(v, 8))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn zeroes() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0; 4]);
+    assert_sum_eq(&[0; 100]);
+    assert_sum_eq(&[0; 1024]);
+    assert_sum_eq(&[0; 1024 - 4]);
+    #[cfg(not(miri))]
+    assert_sum_eq(&[0; 1024 * 1024]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0xff; 4]);
+    assert_sum_eq(&[0xff; 100]);
+    assert_sum_eq(&[0xff; 1024]);
+    assert_sum_eq(&[0xff; 1024 - 4]); // non-power-of-2 to test remainder handling
+    #[cfg(not(miri))]
+    assert_sum_eq(&[0xff; 1024 * 1024]);
+  }
+
+  #[test]
+  fn random() {
+    if super::get_native_imp().is_none() {
+      return;
+    } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sum_eq(&random[..4]);
+    assert_sum_eq(&random[..100]);
+    assert_sum_eq(&random[..1024]);
+    assert_sum_eq(&random[..1024 - 5]); // non-power-of-2 to test remainder handling
+    assert_sum_eq(&random[..1024 * 10]);
+  }
+
+  fn assert_sum_eq(data: &[u8]) {
+    let sums = [1, 2, 3, 4];
+
+    if let Some(update) = super::get_native_imp() {
+      let left = update(sums, data);
+      let right = crate::fletcher4::imp::scalar::update_native(sums, data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+
+    if let Some(update) = super::get_byteswap_imp() {
+      let left = update(sums, data);
+      let right = crate::fletcher4::imp::scalar::update_byteswap(sums, data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+  }
+}
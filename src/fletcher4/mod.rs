@@ -0,0 +1,263 @@
+//! ZFS fletcher4 checksum.
+//!
+//! Fletcher4 sums 32-bit words into four 64-bit accumulators without any modulus, the
+//! result is the `[a, b, c, d]` state ZFS stores as its block checksum. Like ZFS, the
+//! native variant reads words in host byte order and the byteswap variant in the
+//! opposite order, matching `fletcher_4_native` and `fletcher_4_byteswap`. A checksum
+//! written on a host of the other endianness is verified with the byteswap variant.
+//!
+//! ZFS only checksums whole words, a trailing partial word is ignored.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::fletcher4::{fletcher4_native, Fletcher4};
+//!
+//! let mut data = [0; 8];
+//! data[..4].copy_from_slice(&1u32.to_ne_bytes());
+//! data[4..].copy_from_slice(&2u32.to_ne_bytes());
+//!
+//! let mut hash = Fletcher4::new();
+//! hash.write(&data[..6]);
+//! hash.write(&data[6..]);
+//!
+//! assert_eq!(hash.finish(), [3, 4, 5, 6]);
+//! assert_eq!(fletcher4_native(&data), [3, 4, 5, 6]);
+//! ```
+use crate::fletcher::Tail;
+
+#[doc(hidden)]
+pub mod imp;
+
+use imp::{get_byteswap_imp, get_native_imp, Fletcher4Imp};
+
+/// Compute native fletcher4 checksum of `data`, reading words in host byte order.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::fletcher4::fletcher4_native;
+///
+/// let hash = fletcher4_native(&[1, 1, 1, 1, 2, 2, 2, 2]);
+/// assert_eq!(hash, [0x03030303, 0x04040404, 0x05050505, 0x06060606]);
+/// ```
+pub fn fletcher4_native(data: &[u8]) -> [u64; 4] {
+  let mut hash = Fletcher4::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// Compute byteswap fletcher4 checksum of `data`, reading words in the opposite of host
+/// byte order.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::fletcher4::fletcher4_byteswap;
+///
+/// let hash = fletcher4_byteswap(&[1, 1, 1, 1, 2, 2, 2, 2]);
+/// assert_eq!(hash, [0x03030303, 0x04040404, 0x05050505, 0x06060606]);
+/// ```
+pub fn fletcher4_byteswap(data: &[u8]) -> [u64; 4] {
+  let mut hash = Fletcher4::new_byteswap();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// A ZFS fletcher4 hash generator type.
+#[derive(Clone)]
+pub struct Fletcher4 {
+  sums: [u64; 4],
+  tail: Tail,
+  update: Fletcher4Imp,
+}
+
+impl Fletcher4 {
+  /// Constructs a new native `Fletcher4`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher4::Fletcher4;
+  ///
+  /// let mut hash = Fletcher4::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Constructs a new byteswap `Fletcher4`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher4::Fletcher4;
+  ///
+  /// let mut hash = Fletcher4::new_byteswap();
+  /// ```
+  pub fn new_byteswap() -> Self {
+    Self::from_checksum_byteswap([0; 4])
+  }
+
+  /// Constructs a new native `Fletcher4` using existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher4::Fletcher4;
+  ///
+  /// let mut hash = Fletcher4::from_checksum([10, 20, 35, 56]);
+  /// ```
+  pub fn from_checksum(checksum: [u64; 4]) -> Self {
+    Self {
+      sums: checksum,
+      tail: Tail::new(),
+      update: get_native_imp(),
+    }
+  }
+
+  /// Constructs a new byteswap `Fletcher4` using existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::fletcher4::Fletcher4;
+  ///
+  /// let mut hash = Fletcher4::from_checksum_byteswap([10, 20, 35, 56]);
+  /// ```
+  pub fn from_checksum_byteswap(checksum: [u64; 4]) -> Self {
+    Self {
+      sums: checksum,
+      tail: Tail::new(),
+      update: get_byteswap_imp(),
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  ///
+  /// Data does not need to be word aligned, a trailing partial word is kept until the
+  /// next write.
+  pub fn write(&mut self, data: &[u8]) {
+    let (sums, update) = (&mut self.sums, self.update);
+
+    self
+      .tail
+      .write(data, 4, |words| *sums = update(*sums, words));
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// A trailing partial word is not part of the hash.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> [u64; 4] {
+    self.sums
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.sums = [0; 4];
+    self.tail = Tail::new();
+  }
+}
+
+impl Default for Fletcher4 {
+  fn default() -> Self {
+    Self::from_checksum([0; 4])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{fletcher4_byteswap, fletcher4_native, Fletcher4};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn words() {
+    let mut native = [0; 16];
+    let mut byteswap = [0; 16];
+    for (i, word) in (1u32..=4).enumerate() {
+      native[i * 4..][..4].copy_from_slice(&word.to_ne_bytes());
+      byteswap[i * 4..][..4].copy_from_slice(&word.swap_bytes().to_ne_bytes());
+    }
+
+    assert_eq!(fletcher4_native(&native), [10, 20, 35, 56]);
+    assert_eq!(fletcher4_byteswap(&byteswap), [10, 20, 35, 56]);
+    assert_eq!(fletcher4_native(&native[..15]), [6, 10, 15, 21]);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &len in &[1, 4, 100, 1024 - 5, 5553, 1024 * 100] {
+      let data = &random[..len];
+
+      assert_eq!(
+        fletcher4_native(data),
+        reference(data, false),
+        "len({})",
+        len
+      );
+      assert_eq!(
+        fletcher4_byteswap(data),
+        reference(data, true),
+        "len({})",
+        len
+      );
+    }
+  }
+
+  #[test]
+  fn streaming() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut native = Fletcher4::new();
+    let mut byteswap = Fletcher4::new_byteswap();
+
+    let mut offset = 0;
+    for size in (0..).map(|i| i % 7) {
+      let end = (offset + size).min(random.len());
+
+      native.write(&random[offset..end]);
+      byteswap.write(&random[offset..end]);
+
+      let data = &random[..end];
+      assert_eq!(native.finish(), reference(data, false), "len({})", end);
+      assert_eq!(byteswap.finish(), reference(data, true), "len({})", end);
+
+      offset = end;
+      if offset == random.len() {
+        break;
+      }
+    }
+  }
+
+  #[test]
+  fn from_checksum() {
+    let mut native = Fletcher4::from_checksum(fletcher4_native(b"abcd"));
+    let mut byteswap = Fletcher4::from_checksum_byteswap(fletcher4_byteswap(b"abcd"));
+
+    native.write(b"efgh");
+    byteswap.write(b"efgh");
+
+    assert_eq!(native.finish(), fletcher4_native(b"abcdefgh"));
+    assert_eq!(byteswap.finish(), fletcher4_byteswap(b"abcdefgh"));
+  }
+
+  /// Port of `fletcher_4_native` and `fletcher_4_byteswap` from ZFS.
+  fn reference(data: &[u8], byteswap: bool) -> [u64; 4] {
+    let (mut a, mut b, mut c, mut d) = (0u64, 0u64, 0u64, 0u64);
+
+    for word in data.chunks_exact(4) {
+      let word = u32::from_ne_bytes([word[0], word[1], word[2], word[3]]);
+      let word = if byteswap { word.swap_bytes() } else { word };
+
+      a = a.wrapping_add(u64::from(word));
+      b = b.wrapping_add(a);
+      c = c.wrapping_add(b);
+      d = d.wrapping_add(c);
+    }
+
+    [a, b, c, d]
+  }
+}
@@ -0,0 +1,436 @@
+//! Length-prefixed frames with an Adler-32 trailer.
+//!
+//! A frame is the big-endian `u32` payload length, the payload and the big-endian
+//! Adler-32 of the payload. Encoding and decoding work on slices without allocating so
+//! the same code runs on both ends of a link with `no_std` peers.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::framing::{encode, encoded_len, Decoder};
+//!
+//! let mut buf = [0; 64];
+//! let len = encode(b"ping", &mut buf);
+//! assert_eq!(len, encoded_len(4));
+//!
+//! let mut decoder = Decoder::new(1024);
+//!
+//! // Frames may arrive in pieces, the buffer is passed again once more data is in.
+//! assert_eq!(decoder.decode(&buf[..5]), Ok(None));
+//!
+//! let frame = decoder.decode(&buf[..len]).unwrap().unwrap();
+//! assert_eq!((frame.payload, frame.len), (&b"ping"[..], len));
+//! ```
+use crate::Adler32;
+use core::fmt;
+
+/// Length of the frame header.
+pub const HEADER_LEN: usize = 4;
+
+/// Length of the frame trailer.
+pub const TRAILER_LEN: usize = 4;
+
+/// Returns the encoded length of a frame with a payload of `len` bytes.
+pub fn encoded_len(len: usize) -> usize {
+  HEADER_LEN + len + TRAILER_LEN
+}
+
+/// Returns the header of a frame with a payload of `len` bytes.
+///
+/// # Panics
+/// Panics if `len` does not fit in a `u32`.
+pub fn header(len: usize) -> [u8; HEADER_LEN] {
+  assert!(
+    len as u64 <= 0xffff_ffff,
+    "Payload is too large for a frame"
+  );
+
+  (len as u32).to_be_bytes()
+}
+
+/// Returns the trailer of a frame with `payload`.
+pub fn trailer(payload: &[u8]) -> [u8; TRAILER_LEN] {
+  let mut hash = Adler32::new();
+  hash.write(payload);
+
+  hash.finish().to_be_bytes()
+}
+
+/// Encodes `payload` as a frame into the start of `out` and returns its length.
+///
+/// # Panics
+/// Panics if `out` is shorter than [`encoded_len`] or the payload length does not fit in
+/// a `u32`.
+pub fn encode(payload: &[u8], out: &mut [u8]) -> usize {
+  let len = encoded_len(payload.len());
+  assert!(out.len() >= len, "Output is shorter than the frame");
+
+  let (head, rest) = out.split_at_mut(HEADER_LEN);
+  head.copy_from_slice(&header(payload.len()));
+  rest[..payload.len()].copy_from_slice(payload);
+  rest[payload.len()..payload.len() + TRAILER_LEN].copy_from_slice(&trailer(payload));
+
+  len
+}
+
+/// Writes `payload` as a frame into `writer`.
+#[cfg(feature = "std")]
+pub fn write_frame<W: std::io::Write>(
+  writer: &mut W,
+  payload: &[u8],
+) -> std::io::Result<()> {
+  writer.write_all(&header(payload.len()))?;
+  writer.write_all(payload)?;
+  writer.write_all(&trailer(payload))
+}
+
+/// Reads a frame from `reader` and returns its payload, frame errors are reported as
+/// [`InvalidData`](std::io::ErrorKind::InvalidData) errors.
+///
+/// Returns `None` if the reader is exhausted before the frame starts.
+#[cfg(feature = "std")]
+pub fn read_frame<R: std::io::Read>(
+  reader: &mut R,
+  max_len: u32,
+) -> std::io::Result<Option<Vec<u8>>> {
+  use std::io::{Error, ErrorKind, Read};
+
+  let mut head = [0; HEADER_LEN];
+  let mut filled = 0;
+  while filled < HEADER_LEN {
+    match reader.read(&mut head[filled..]) {
+      Ok(0) if filled == 0 => return Ok(None),
+      Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, FrameError::Truncated)),
+      Ok(n) => filled += n,
+      Err(err) if err.kind() == ErrorKind::Interrupted => {}
+      Err(err) => return Err(err),
+    }
+  }
+
+  let len = u32::from_be_bytes(head);
+  if len > max_len {
+    let err = FrameError::Oversize { len, max_len };
+    return Err(Error::new(ErrorKind::InvalidData, err));
+  }
+
+  let mut payload = Vec::new();
+  reader.take(u64::from(len)).read_to_end(&mut payload)?;
+
+  if payload.len() < len as usize {
+    return Err(Error::new(ErrorKind::UnexpectedEof, FrameError::Truncated));
+  }
+
+  let mut end = [0; TRAILER_LEN];
+  match reader.read_exact(&mut end) {
+    Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+      return Err(Error::new(ErrorKind::UnexpectedEof, FrameError::Truncated));
+    }
+    result => result?,
+  }
+
+  check(&payload, end).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+  Ok(Some(payload))
+}
+
+/// A decoded frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+  /// Payload of the frame.
+  pub payload: &'a [u8],
+  /// Encoded length of the frame, the bytes to drop from the buffer.
+  pub len: usize,
+}
+
+/// Incremental frame decoder.
+///
+/// The decoder is handed the buffered data starting at the frame, again each time more
+/// data is appended, until the frame is complete. Payload bytes are only checksummed
+/// once no matter how the frame is split.
+#[derive(Clone)]
+pub struct Decoder {
+  max_len: u32,
+  hash: Adler32,
+  hashed: usize,
+}
+
+impl Decoder {
+  /// Constructs a new `Decoder` rejecting payloads longer than `max_len` bytes.
+  pub fn new(max_len: u32) -> Self {
+    Self {
+      max_len,
+      hash: Adler32::new(),
+      hashed: 0,
+    }
+  }
+
+  /// Decodes the frame at the start of `buf`.
+  ///
+  /// Returns `Ok(None)` if `buf` does not hold the whole frame yet. The next call must
+  /// pass the same data with more appended, after a frame or an error is returned the
+  /// next call starts a new frame. A buffer shorter than the payload already hashed
+  /// can't continue the frame and also starts a new one.
+  pub fn decode<'a>(&mut self, buf: &'a [u8]) -> Result<Option<Frame<'a>>, FrameError> {
+    if buf.len() < HEADER_LEN {
+      return Ok(None);
+    }
+
+    let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    if len > self.max_len {
+      self.reset();
+      return Err(FrameError::Oversize {
+        len,
+        max_len: self.max_len,
+      });
+    }
+
+    let payload = &buf[HEADER_LEN..];
+    let payload = &payload[..payload.len().min(len as usize)];
+
+    if payload.len() < self.hashed {
+      self.reset();
+    }
+
+    self.hash.write(&payload[self.hashed..]);
+    self.hashed = payload.len();
+
+    let frame_len = encoded_len(len as usize);
+    if buf.len() < frame_len {
+      return Ok(None);
+    }
+
+    let end = &buf[frame_len - TRAILER_LEN..frame_len];
+    let expected = u32::from_be_bytes([end[0], end[1], end[2], end[3]]);
+    let actual = self.hash.finish();
+    self.reset();
+
+    if expected != actual {
+      return Err(FrameError::Checksum { expected, actual });
+    }
+
+    Ok(Some(Frame {
+      payload,
+      len: frame_len,
+    }))
+  }
+
+  /// Checks the data left over when the input ends, returns an error if it holds a
+  /// partial frame.
+  pub fn finish(&mut self, buf: &[u8]) -> Result<(), FrameError> {
+    self.reset();
+
+    if buf.is_empty() {
+      Ok(())
+    } else {
+      Err(FrameError::Truncated)
+    }
+  }
+
+  /// Discards the state of a partially decoded frame.
+  pub fn reset(&mut self) {
+    self.hash.reset();
+    self.hashed = 0;
+  }
+}
+
+/// Error returned when decoding an invalid frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+  /// The input ends inside a frame.
+  Truncated,
+  /// The payload length exceeds the maximum.
+  Oversize {
+    /// Payload length in the header.
+    len: u32,
+    /// Maximum payload length of the decoder.
+    max_len: u32,
+  },
+  /// The trailer does not match the payload.
+  Checksum {
+    /// Checksum in the trailer.
+    expected: u32,
+    /// Checksum of the payload.
+    actual: u32,
+  },
+}
+
+impl fmt::Display for FrameError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FrameError::Truncated => write!(f, "truncated frame"),
+      FrameError::Oversize { len, max_len } => {
+        write!(
+          f,
+          "frame payload of {} bytes exceeds {} bytes",
+          len, max_len
+        )
+      }
+      FrameError::Checksum { expected, actual } => write!(
+        f,
+        "frame checksum mismatch, expected {:#010x} got {:#010x}",
+        expected, actual
+      ),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FrameError {}
+
+#[cfg(feature = "std")]
+fn check(payload: &[u8], end: [u8; TRAILER_LEN]) -> Result<(), FrameError> {
+  let expected = u32::from_be_bytes(end);
+  let actual = u32::from_be_bytes(trailer(payload));
+
+  if expected == actual {
+    Ok(())
+  } else {
+    Err(FrameError::Checksum { expected, actual })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{encode, encoded_len, Decoder, Frame, FrameError};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn known_frame() {
+    let mut buf = [0; 16];
+
+    assert_eq!(encode(b"ping", &mut buf), 12);
+    assert_eq!(
+      buf[..12],
+      [0, 0, 0, 4, b'p', b'i', b'n', b'g', 0x04, 0x42, 0x01, 0xaf]
+    );
+    assert_eq!(encode(b"", &mut buf), 8);
+    assert_eq!(buf[..8], [0, 0, 0, 0, 0, 0, 0, 1]);
+  }
+
+  #[test]
+  fn split() {
+    let mut random = [0; 1024 * 20];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let payloads = [&random[..0], &random[..1], &random[..5553], &random[..]];
+    let mut stream = [0; 4 * 8 + 1 + 5553 + 1024 * 20];
+    let mut start = 0;
+    for payload in &payloads {
+      let len = encode(payload, &mut stream[start..]);
+      assert_eq!(len, encoded_len(payload.len()));
+      start += len;
+    }
+    assert_eq!(start, stream.len());
+
+    // Feed the stream in pieces of every size from 1 to 999.
+    for &step in &[1, 7, 100, 999] {
+      let mut decoder = Decoder::new(1024 * 20);
+      let (mut start, mut end) = (0, 0);
+      let mut decoded = 0;
+
+      while end < stream.len() {
+        end = (end + step).min(stream.len());
+
+        while let Some(Frame { payload, len }) =
+          decoder.decode(&stream[start..end]).unwrap()
+        {
+          assert_eq!(payload, payloads[decoded], "step({})", step);
+          decoded += 1;
+          start += len;
+        }
+      }
+
+      assert_eq!(decoder.finish(&stream[start..end]), Ok(()));
+      assert_eq!(decoded, payloads.len(), "step({})", step);
+    }
+  }
+
+  #[test]
+  fn shorter_buffer() {
+    let mut long = [0; 64];
+    let mut short = [0; 16];
+    encode(b"a longer frame, split in two", &mut long);
+    let len = encode(b"ping", &mut short);
+
+    let mut decoder = Decoder::new(64);
+    assert_eq!(decoder.decode(&long[..20]), Ok(None));
+
+    let frame = decoder.decode(&short[..len]).unwrap().unwrap();
+    assert_eq!(frame.payload, b"ping");
+  }
+
+  #[test]
+  fn errors() {
+    let mut buf = [0; 64];
+    let len = encode(b"hello, world", &mut buf);
+
+    let mut decoder = Decoder::new(8);
+    assert_eq!(
+      decoder.decode(&buf[..4]),
+      Err(FrameError::Oversize {
+        len: 12,
+        max_len: 8
+      })
+    );
+
+    let mut decoder = Decoder::new(64);
+    assert_eq!(decoder.decode(&buf[..len - 1]), Ok(None));
+    assert_eq!(decoder.finish(&buf[..len - 1]), Err(FrameError::Truncated));
+
+    buf[11] ^= 1;
+    assert_eq!(
+      decoder.decode(&buf[..len]),
+      Err(FrameError::Checksum {
+        expected: adler2::adler32_slice(b"hello, world"),
+        actual: adler2::adler32_slice(b"hello, vorld"),
+      })
+    );
+
+    buf[11] ^= 1;
+    assert!(decoder.decode(&buf[..len]).unwrap().is_some());
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn readers() {
+    use std::io::ErrorKind;
+
+    let mut stream = Vec::new();
+    super::write_frame(&mut stream, b"first").unwrap();
+    super::write_frame(&mut stream, b"").unwrap();
+
+    let mut reader = &stream[..];
+    assert_eq!(
+      super::read_frame(&mut reader, 8).unwrap(),
+      Some(b"first".to_vec())
+    );
+    assert_eq!(super::read_frame(&mut reader, 8).unwrap(), Some(Vec::new()));
+    assert_eq!(super::read_frame(&mut reader, 8).unwrap(), None);
+
+    let truncated = super::read_frame(&mut &stream[..6], 8).unwrap_err();
+    assert_eq!(truncated.kind(), ErrorKind::UnexpectedEof);
+
+    let oversize = super::read_frame(&mut &stream[..], 4).unwrap_err();
+    assert_eq!(oversize.kind(), ErrorKind::InvalidData);
+
+    let mut corrupted = stream.clone();
+    corrupted[4] ^= 1;
+    let mismatch = super::read_frame(&mut &corrupted[..], 8).unwrap_err();
+    assert_eq!(mismatch.kind(), ErrorKind::InvalidData);
+
+    // Only the end of the input means a truncated trailer.
+    let mut failing = std::io::Read::chain(&stream[..9], Failing);
+    let failed = super::read_frame(&mut failing, 8).unwrap_err();
+    assert_eq!(failed.kind(), ErrorKind::BrokenPipe);
+  }
+
+  /// Reader failing every read.
+  #[cfg(feature = "std")]
+  struct Failing;
+
+  #[cfg(feature = "std")]
+  impl std::io::Read for Failing {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+      Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+  }
+}
@@ -1,30 +1,36 @@
+use crate::imp::{scalar, MOD, SHORT_LEN};
 use crate::{Adler32, Adler32Hash};
 
+/// Hashes `data`, short inputs skip resolving an implementation.
+fn hash_slice(data: &[u8]) -> u32 {
+  if data.len() < SHORT_LEN {
+    let (a, b) = scalar::update_short(1, 0, data, MOD);
+
+    return (u32::from(b) << 16) | u32::from(a);
+  }
+
+  let mut hash = Adler32::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
 impl Adler32Hash for &[u8] {
   fn hash(&self) -> u32 {
-    let mut hash = Adler32::new();
-
-    hash.write(self);
-    hash.finish()
+    hash_slice(self)
   }
 }
 
 impl Adler32Hash for &str {
   fn hash(&self) -> u32 {
-    let mut hash = Adler32::new();
-
-    hash.write(self.as_bytes());
-    hash.finish()
+    hash_slice(self.as_bytes())
   }
 }
 
 #[cfg(feature = "const-generics")]
 impl<const SIZE: usize> Adler32Hash for [u8; SIZE] {
   fn hash(&self) -> u32 {
-    let mut hash = Adler32::new();
-
-    hash.write(self);
-    hash.finish()
+    hash_slice(self)
   }
 }
 
@@ -37,10 +43,7 @@ macro_rules! array_impl {
     #[cfg(not(feature = "const-generics"))]
     impl Adler32Hash for [u8; $size] {
       fn hash(&self) -> u32 {
-        let mut hash = Adler32::new();
-
-        hash.write(self);
-        hash.finish()
+        hash_slice(self)
       }
     }
   };
@@ -1,15 +1,36 @@
-use super::Adler32Imp;
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp, ScanImp};
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "avx2")
+))]
+pub use self::imp::Avx2;
 
 /// Resolves update implementation if CPU supports avx2 instructions.
 pub fn get_imp() -> Option<Adler32Imp> {
-  get_imp_inner()
+  get_imp_inner().map(|(update, _, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports avx2 instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports avx2 instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks, _)| update_blocks)
+}
+
+/// Resolves window scan implementation if CPU supports avx2 instructions.
+pub fn get_scan_imp() -> Option<ScanImp> {
+  get_imp_inner().map(|(_, _, _, scan)| scan)
 }
 
 #[inline]
 #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
   if std::is_x86_feature_detected!("avx2") {
-    Some(imp::update)
+    Some((imp::update, imp::update_mod, imp::update_blocks, imp::scan))
   } else {
     None
   }
@@ -20,8 +41,8 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   target_feature = "avx2",
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
-  Some(imp::update)
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks, imp::scan))
 }
 
 #[inline]
@@ -29,7 +50,7 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   not(target_feature = "avx2"),
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
   None
 }
 
@@ -38,39 +59,196 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   any(feature = "std", target_feature = "avx2")
 ))]
 mod imp {
-  const MOD: u32 = 65521;
-  const NMAX: usize = 5552;
+  use crate::backend::Backend;
+  use crate::imp::{scalar, Modulus, HEAD_LEN, HEAD_WEIGHTS};
+
   const BLOCK_SIZE: usize = 32;
-  const CHUNK_SIZE: usize = NMAX / BLOCK_SIZE * BLOCK_SIZE;
+
+  /// Blocks summed per iteration of the main loop, each with its own accumulator.
+  const UNROLL: usize = 4;
+
+  /// Bytes summed between reductions. `a` and the prefix sums are kept in 64-bit lanes,
+  /// the 32-bit weighted sums grow by at most `255 * (32 + 31 + 30 + 29)` per block and
+  /// lane, which fits `2^32` for well over a megabyte.
+  const CHUNK_SIZE: usize = 1 << 20;
 
   #[cfg(target_arch = "x86")]
   use core::arch::x86::*;
   #[cfg(target_arch = "x86_64")]
   use core::arch::x86_64::*;
 
+  #[inline(always)]
   pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
     unsafe { update_imp(a, b, data) }
   }
 
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  pub fn scan(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    unsafe { scan_imp(s1, s2, data, window, offset, mask) }
+  }
+
+  /// Statically dispatched avx2 backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Avx2(());
+
+  impl Backend for Avx2 {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Avx2(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
   #[inline]
   #[target_feature(enable = "avx2")]
   unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
-    let mut a = a as u32;
-    let mut b = b as u32;
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "avx2")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "avx2")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  /// Rolls the sums over sixteen windows at a time, see the sse2 implementation.
+  #[inline]
+  #[target_feature(enable = "avx2")]
+  unsafe fn scan_imp(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    let len = data.len().saturating_sub(window);
+
+    let weight_v = _mm256_set1_epi16(window as i16);
+    let bias_v = _mm256_set1_epi16((window as u16).wrapping_mul(offset) as i16);
+    let mask_s1_v = _mm256_set1_epi16((mask >> 16) as i16);
+    let mask_s2_v = _mm256_set1_epi16(mask as i16);
+
+    let lanes_v =
+      _mm256_set_epi16(16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1);
+
+    let mut s1_v = _mm256_set1_epi16(s1 as i16);
+    let mut s2_v = _mm256_set1_epi16(s2 as i16);
+
+    let mut n = 0;
+    while n + 16 <= len {
+      let ptr = data.as_ptr().add(n);
+      let old_v = _mm256_cvtepu8_epi16(_mm_loadu_si128(ptr as *const _));
+      let new_v = _mm256_cvtepu8_epi16(_mm_loadu_si128(ptr.add(window) as *const _));
+
+      let removed_v = _mm256_add_epi16(_mm256_mullo_epi16(old_v, weight_v), bias_v);
+      let d1_v = prefix_sum(_mm256_sub_epi16(new_v, old_v));
+      let d2_v = prefix_sum(_mm256_sub_epi16(d1_v, removed_v));
+
+      let sums1_v = _mm256_add_epi16(s1_v, d1_v);
+      let sums2_v = _mm256_add_epi16(
+        _mm256_add_epi16(s2_v, _mm256_mullo_epi16(s1_v, lanes_v)),
+        d2_v,
+      );
+
+      let hits_v = _mm256_and_si256(
+        _mm256_cmpeq_epi16(_mm256_and_si256(sums1_v, mask_s1_v), mask_s1_v),
+        _mm256_cmpeq_epi16(_mm256_and_si256(sums2_v, mask_s2_v), mask_s2_v),
+      );
+      let hits = _mm256_movemask_epi8(hits_v);
+      if hits != 0 {
+        return Some(n + hits.trailing_zeros() as usize / 2 + 1);
+      }
+
+      s2_v = _mm256_add_epi16(
+        _mm256_add_epi16(s2_v, _mm256_slli_epi16(s1_v, 4)),
+        broadcast_last(d2_v),
+      );
+      s1_v = _mm256_add_epi16(s1_v, broadcast_last(d1_v));
+      n += 16;
+    }
+
+    let s1 = _mm256_extract_epi16(s1_v, 0) as u16;
+    let s2 = _mm256_extract_epi16(s2_v, 0) as u16;
+
+    scalar::scan(s1, s2, &data[n..], window, offset, mask).map(|m| n + m)
+  }
+
+  /// Returns the inclusive prefix sums of the 16-bit lanes of `v`.
+  #[inline(always)]
+  unsafe fn prefix_sum(v: __m256i) -> __m256i {
+    let v = _mm256_add_epi16(v, _mm256_slli_si256(v, 2));
+    let v = _mm256_add_epi16(v, _mm256_slli_si256(v, 4));
+    let v = _mm256_add_epi16(v, _mm256_slli_si256(v, 8));
+
+    // The shifts stay within 128-bit halves, carry the low half's total into the high.
+    let low = broadcast_half(v);
+    _mm256_add_epi16(v, _mm256_permute2x128_si256(low, low, 0x08))
+  }
+
+  /// Copies the last 16-bit lane of `v` to every lane.
+  #[inline(always)]
+  unsafe fn broadcast_last(v: __m256i) -> __m256i {
+    let high = broadcast_half(v);
+    _mm256_permute2x128_si256(high, high, 0x11)
+  }
+
+  /// Copies the last 16-bit lane of each 128-bit half of `v` to every lane of the half.
+  #[inline(always)]
+  unsafe fn broadcast_half(v: __m256i) -> __m256i {
+    let v = _mm256_shufflehi_epi16(v, crate::imp::_MM_SHUFFLE(3, 3, 3, 3));
+
+    _mm256_shuffle_epi32(v, crate::imp::_MM_SHUFFLE(3, 3, 3, 3))
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = u64::from(a);
+    let mut b = u64::from(b);
+    let modulus = u64::from(modulus.value());
 
     let chunks = data.chunks_exact(CHUNK_SIZE);
     let remainder = chunks.remainder();
     for chunk in chunks {
-      update_chunk_block(&mut a, &mut b, chunk);
+      update_chunk_block(&mut a, &mut b, chunk, modulus);
     }
 
-    update_block(&mut a, &mut b, remainder);
+    update_block(&mut a, &mut b, remainder, modulus);
 
     (a as u16, b as u16)
   }
 
   #[inline]
-  unsafe fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  #[target_feature(enable = "avx2")]
+  unsafe fn update_chunk_block(a: &mut u64, b: &mut u64, chunk: &[u8], modulus: u64) {
     debug_assert_eq!(
       chunk.len(),
       CHUNK_SIZE,
@@ -81,12 +259,13 @@ mod imp {
 
     reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline]
-  unsafe fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  #[target_feature(enable = "avx2")]
+  unsafe fn update_block(a: &mut u64, b: &mut u64, chunk: &[u8], modulus: u64) {
     debug_assert!(
       chunk.len() <= CHUNK_SIZE,
       "Unexpected chunk size (expected <= {}, got {})",
@@ -94,62 +273,117 @@ mod imp {
       chunk.len()
     );
 
-    for byte in reduce_add_blocks(a, b, chunk) {
-      *a += *byte as u32;
-      *b += *a;
-    }
+    reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline(always)]
-  unsafe fn reduce_add_blocks<'a>(a: &mut u32, b: &mut u32, chunk: &'a [u8]) -> &'a [u8] {
-    if chunk.len() < BLOCK_SIZE {
-      return chunk;
+  unsafe fn reduce_add_blocks(a: &mut u64, b: &mut u64, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u64::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
     }
 
-    let blocks = chunk.chunks_exact(BLOCK_SIZE);
-    let blocks_remainder = blocks.remainder();
+    let groups = chunk[head..].chunks_exact(UNROLL * BLOCK_SIZE);
+    let blocks = groups.remainder().chunks_exact(BLOCK_SIZE);
 
     let one_v = _mm256_set1_epi16(1);
     let zero_v = _mm256_setzero_si256();
     let weights = get_weights();
 
-    let mut p_v = _mm256_set_epi32(0, 0, 0, 0, 0, 0, 0, (*a * blocks.len() as u32) as _);
+    // `p_v` sums `a_v` once per group and `q_v` the offsets of the blocks within their
+    // group, both in 64-bit lanes like `a_v`.
+    let mut p_v = _mm256_setzero_si256();
+    let mut q_v = _mm256_setzero_si256();
     let mut a_v = _mm256_setzero_si256();
-    let mut b_v = _mm256_set_epi32(0, 0, 0, 0, 0, 0, 0, *b as _);
+    let mut b0_v = _mm256_setzero_si256();
+    let mut b1_v = _mm256_setzero_si256();
 
-    for block in blocks {
-      let block_ptr = block.as_ptr() as *const _;
-      let block = _mm256_loadu_si256(block_ptr);
+    if head != 0 {
+      let head_v = _mm256_loadu_si256(HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr() as _);
+      let block = _mm256_loadu_si256(chunk.as_ptr() as *const _);
+      let block = _mm256_andnot_si256(_mm256_cmpeq_epi8(head_v, zero_v), block);
 
-      p_v = _mm256_add_epi32(p_v, a_v);
+      a_v = _mm256_sad_epu8(block, zero_v);
+      let mad = _mm256_maddubs_epi16(block, head_v);
+      b0_v = _mm256_madd_epi16(mad, one_v);
+    }
 
-      a_v = _mm256_add_epi32(a_v, _mm256_sad_epu8(block, zero_v));
+    for group in groups {
+      let ptr = group.as_ptr() as *const __m256i;
+      let block0 = _mm256_loadu_si256(ptr);
+      let block1 = _mm256_loadu_si256(ptr.add(1));
+      let block2 = _mm256_loadu_si256(ptr.add(2));
+      let block3 = _mm256_loadu_si256(ptr.add(3));
+
+      let sum0 = _mm256_sad_epu8(block0, zero_v);
+      let sum01 = _mm256_add_epi64(sum0, _mm256_sad_epu8(block1, zero_v));
+      let sum012 = _mm256_add_epi64(sum01, _mm256_sad_epu8(block2, zero_v));
+      let sum0123 = _mm256_add_epi64(sum012, _mm256_sad_epu8(block3, zero_v));
+
+      p_v = _mm256_add_epi64(p_v, a_v);
+      q_v = _mm256_add_epi64(q_v, _mm256_add_epi64(sum0, sum01));
+      q_v = _mm256_add_epi64(q_v, sum012);
+      a_v = _mm256_add_epi64(a_v, sum0123);
+
+      // Two blocks sum to at most `2 * 255 * (32 + 31)`, which still fits an `i16`.
+      let mad01 = _mm256_add_epi16(
+        _mm256_maddubs_epi16(block0, weights),
+        _mm256_maddubs_epi16(block1, weights),
+      );
+      let mad23 = _mm256_add_epi16(
+        _mm256_maddubs_epi16(block2, weights),
+        _mm256_maddubs_epi16(block3, weights),
+      );
+      b0_v = _mm256_add_epi32(b0_v, _mm256_madd_epi16(mad01, one_v));
+      b1_v = _mm256_add_epi32(b1_v, _mm256_madd_epi16(mad23, one_v));
+    }
+
+    // From here on `p_v` counts single blocks.
+    p_v = _mm256_add_epi64(_mm256_slli_epi64(p_v, 2), q_v);
+
+    for block in blocks {
+      let block = _mm256_loadu_si256(block.as_ptr() as *const _);
+
+      p_v = _mm256_add_epi64(p_v, a_v);
+      a_v = _mm256_add_epi64(a_v, _mm256_sad_epu8(block, zero_v));
       let mad = _mm256_maddubs_epi16(block, weights);
-      b_v = _mm256_add_epi32(b_v, _mm256_madd_epi16(mad, one_v));
+      b0_v = _mm256_add_epi32(b0_v, _mm256_madd_epi16(mad, one_v));
     }
 
-    b_v = _mm256_add_epi32(b_v, _mm256_slli_epi32(p_v, 5));
+    let b_v = widen_add(_mm256_add_epi32(b0_v, b1_v));
+    let b_v = _mm256_add_epi64(b_v, _mm256_slli_epi64(p_v, 5));
 
+    *b += chunk.len() as u64 * *a + reduce_add(b_v);
     *a += reduce_add(a_v);
-    *b = reduce_add(b_v);
+  }
+
+  /// Adds the odd 32-bit lanes to the even ones, as 64-bit lanes.
+  #[inline(always)]
+  unsafe fn widen_add(v: __m256i) -> __m256i {
+    let lo = _mm256_and_si256(v, _mm256_set1_epi64x(0xffff_ffff));
 
-    blocks_remainder
+    _mm256_add_epi64(lo, _mm256_srli_epi64(v, 32))
   }
 
   #[inline(always)]
-  unsafe fn reduce_add(v: __m256i) -> u32 {
-    let sum = _mm_add_epi32(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
+  unsafe fn reduce_add(v: __m256i) -> u64 {
+    let sum = _mm_add_epi64(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
     let hi = _mm_unpackhi_epi64(sum, sum);
 
-    let sum = _mm_add_epi32(hi, sum);
-    let hi = _mm_shuffle_epi32(sum, crate::imp::_MM_SHUFFLE(2, 3, 0, 1));
+    let sum: [u64; 2] = core::mem::transmute(_mm_add_epi64(hi, sum));
 
-    let sum = _mm_add_epi32(sum, hi);
-
-    _mm_cvtsi128_si32(sum) as _
+    sum[0]
   }
 
   #[inline(always)]
@@ -164,6 +398,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
   use rand::{Rng, SeedableRng, rngs::SmallRng};
+  use crate::imp::{scalar, Modulus};
 
   #[test]
   fn zeroes() {
@@ -202,6 +437,65 @@ mod tests {
     assert_sum_eq(&random[..1024 * 10]);
   }
 
+  #[test]
+  fn modulus() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    if super::get_blocks_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  fn chunks() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    // Saturated sums on both sides of the 1 MiB chunk boundary, static as it's too
+    // large for the stack.
+    static DATA: [u8; (1 << 21) + 100] = [0xff; (1 << 21) + 100];
+    let data = &DATA;
+
+    for &len in &[(1 << 20) - 5, 1 << 20, (1 << 20) + 5, data.len()] {
+      for &modulus in &[65536, 65521] {
+        let update = super::get_mod_imp().unwrap();
+        let left = update(0xfff0, 0xfff0, &data[..len], Modulus::new(modulus));
+        let right = scalar::update_mod(0xfff0, 0xfff0, &data[..len], Modulus::new(modulus));
+
+        assert_eq!(left, right, "len({}), modulus({})", len, modulus);
+      }
+    }
+  }
+
+  #[test]
+  fn scan() {
+    if super::get_scan_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 4];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &mask in &[0, 0x0000_00ff, 0x0001_0fff, 0x7fff_ffff] {
+      for start in 0..32 {
+        assert_scan_eq(&random[start..], mask);
+      }
+    }
+    assert_scan_eq(&[0xff; 1024], 0);
+    assert_scan_eq(&random[..70], 0x7fff_ffff);
+  }
+
   /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
   #[test]
   fn wiki() {
@@ -217,4 +511,33 @@ mod tests {
       assert_eq!(left, right, "len({})", data.len());
     }
   }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+
+  fn assert_scan_eq(data: &[u8], mask: u32) {
+    if let Some(scan) = super::get_scan_imp() {
+      let left = scan(0x1234, 0xfedc, data, 64, 31, mask);
+      let right = scalar::scan(0x1234, 0xfedc, data, 64, 31, mask);
+
+      assert_eq!(left, right, "len({}), mask({:#x})", data.len(), mask);
+    }
+  }
 }
@@ -1,8 +1,28 @@
-use super::Adler32Imp;
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp};
+
+#[cfg(all(
+  feature = "nightly",
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(
+    feature = "std",
+    all(target_feature = "avx512f", target_feature = "avx512bw")
+  )
+))]
+pub use self::imp::Avx512;
 
 /// Resolves update implementation if CPU supports avx512f and avx512bw instructions.
 pub fn get_imp() -> Option<Adler32Imp> {
-  get_imp_inner()
+  get_imp_inner().map(|(update, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports avx512f and avx512bw instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports avx512f and avx512bw instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks)| update_blocks)
 }
 
 #[inline]
@@ -11,12 +31,12 @@ pub fn get_imp() -> Option<Adler32Imp> {
   feature = "nightly",
   any(target_arch = "x86", target_arch = "x86_64")
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
   let has_avx512f = std::is_x86_feature_detected!("avx512f");
   let has_avx512bw = std::is_x86_feature_detected!("avx512bw");
 
   if has_avx512f && has_avx512bw {
-    Some(imp::update)
+    Some((imp::update, imp::update_mod, imp::update_blocks))
   } else {
     None
   }
@@ -28,8 +48,8 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   all(target_feature = "avx512f", target_feature = "avx512bw"),
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
-  Some(imp::update)
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks))
 }
 
 #[inline]
@@ -41,7 +61,7 @@ fn get_imp_inner() -> Option<Adler32Imp> {
     any(target_arch = "x86", target_arch = "x86_64")
   ))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
   None
 }
 
@@ -54,40 +74,99 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   )
 ))]
 mod imp {
-  const MOD: u32 = 65521;
-  const NMAX: usize = 5552;
+  use crate::backend::Backend;
+  use crate::imp::Modulus;
+
   const BLOCK_SIZE: usize = 64;
-  const CHUNK_SIZE: usize = NMAX / BLOCK_SIZE * BLOCK_SIZE;
+
+  /// Blocks summed per iteration of the main loop, each with its own accumulator.
+  const UNROLL: usize = 4;
+
+  /// Bytes summed between reductions. `a` and the prefix sums are kept in 64-bit lanes,
+  /// the 32-bit weighted sums grow by at most `255 * (64 + 63 + 62 + 61)` per block and
+  /// lane, which fits `2^32` for well over a megabyte.
+  const CHUNK_SIZE: usize = 1 << 20;
 
   #[cfg(target_arch = "x86")]
   use core::arch::x86::*;
   #[cfg(target_arch = "x86_64")]
   use core::arch::x86_64::*;
 
+  #[inline(always)]
   pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
     unsafe { update_imp(a, b, data) }
   }
 
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  /// Statically dispatched avx512 backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Avx512(());
+
+  impl Backend for Avx512 {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Avx512(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
   #[inline]
   #[target_feature(enable = "avx512f")]
   #[target_feature(enable = "avx512bw")]
   unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
-    let mut a = a as u32;
-    let mut b = b as u32;
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "avx512f")]
+  #[target_feature(enable = "avx512bw")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "avx512f")]
+  #[target_feature(enable = "avx512bw")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = u64::from(a);
+    let mut b = u64::from(b);
+    let modulus = u64::from(modulus.value());
 
     let chunks = data.chunks_exact(CHUNK_SIZE);
     let remainder = chunks.remainder();
     for chunk in chunks {
-      update_chunk_block(&mut a, &mut b, chunk);
+      update_chunk_block(&mut a, &mut b, chunk, modulus);
     }
 
-    update_block(&mut a, &mut b, remainder);
+    update_block(&mut a, &mut b, remainder, modulus);
 
     (a as u16, b as u16)
   }
 
   #[inline]
-  unsafe fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  #[target_feature(enable = "avx512f")]
+  #[target_feature(enable = "avx512bw")]
+  unsafe fn update_chunk_block(a: &mut u64, b: &mut u64, chunk: &[u8], modulus: u64) {
     debug_assert_eq!(
       chunk.len(),
       CHUNK_SIZE,
@@ -98,12 +177,14 @@ mod imp {
 
     reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline]
-  unsafe fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  #[target_feature(enable = "avx512f")]
+  #[target_feature(enable = "avx512bw")]
+  unsafe fn update_block(a: &mut u64, b: &mut u64, chunk: &[u8], modulus: u64) {
     debug_assert!(
       chunk.len() <= CHUNK_SIZE,
       "Unexpected chunk size (expected <= {}, got {})",
@@ -111,72 +192,118 @@ mod imp {
       chunk.len()
     );
 
-    for byte in reduce_add_blocks(a, b, chunk) {
-      *a += *byte as u32;
-      *b += *a;
-    }
+    let tail = reduce_add_blocks(a, b, chunk);
+    reduce_add_tail(a, b, tail);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline(always)]
-  unsafe fn reduce_add_blocks<'a>(a: &mut u32, b: &mut u32, chunk: &'a [u8]) -> &'a [u8] {
+  unsafe fn reduce_add_blocks<'a>(a: &mut u64, b: &mut u64, chunk: &'a [u8]) -> &'a [u8] {
     if chunk.len() < BLOCK_SIZE {
       return chunk;
     }
 
-    let blocks = chunk.chunks_exact(BLOCK_SIZE);
-    let blocks_remainder = blocks.remainder();
+    let len = chunk.len() / BLOCK_SIZE * BLOCK_SIZE;
+    let groups = chunk[..len].chunks_exact(UNROLL * BLOCK_SIZE);
+    let blocks = groups.remainder().chunks_exact(BLOCK_SIZE);
 
     let one_v = _mm512_set1_epi16(1);
     let zero_v = _mm512_setzero_si512();
     let weights = get_weights();
 
-    let p_v = (*a * blocks.len() as u32) as _;
-    let mut p_v = _mm512_set_epi32(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, p_v);
+    // `p_v` sums `a_v` once per group and `q_v` the offsets of the blocks within their
+    // group, both in 64-bit lanes like `a_v`.
+    let mut p_v = _mm512_setzero_si512();
+    let mut q_v = _mm512_setzero_si512();
     let mut a_v = _mm512_setzero_si512();
-    let mut b_v = _mm512_set_epi32(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, *b as _);
+    let mut b0_v = _mm512_setzero_si512();
+    let mut b1_v = _mm512_setzero_si512();
+
+    for group in groups {
+      let ptr = group.as_ptr() as *const __m512i;
+      let block0 = _mm512_loadu_si512(ptr as *const _);
+      let block1 = _mm512_loadu_si512(ptr.add(1) as *const _);
+      let block2 = _mm512_loadu_si512(ptr.add(2) as *const _);
+      let block3 = _mm512_loadu_si512(ptr.add(3) as *const _);
+
+      let sum0 = _mm512_sad_epu8(block0, zero_v);
+      let sum01 = _mm512_add_epi64(sum0, _mm512_sad_epu8(block1, zero_v));
+      let sum012 = _mm512_add_epi64(sum01, _mm512_sad_epu8(block2, zero_v));
+      let sum0123 = _mm512_add_epi64(sum012, _mm512_sad_epu8(block3, zero_v));
+
+      p_v = _mm512_add_epi64(p_v, a_v);
+      q_v = _mm512_add_epi64(q_v, _mm512_add_epi64(sum0, sum01));
+      q_v = _mm512_add_epi64(q_v, sum012);
+      a_v = _mm512_add_epi64(a_v, sum0123);
+
+      let mad0 = _mm512_madd_epi16(_mm512_maddubs_epi16(block0, weights), one_v);
+      let mad1 = _mm512_madd_epi16(_mm512_maddubs_epi16(block1, weights), one_v);
+      let mad2 = _mm512_madd_epi16(_mm512_maddubs_epi16(block2, weights), one_v);
+      let mad3 = _mm512_madd_epi16(_mm512_maddubs_epi16(block3, weights), one_v);
+      b0_v = _mm512_add_epi32(b0_v, _mm512_add_epi32(mad0, mad1));
+      b1_v = _mm512_add_epi32(b1_v, _mm512_add_epi32(mad2, mad3));
+    }
 
-    for block in blocks {
-      let block_ptr = block.as_ptr() as *const _;
-      let block = _mm512_loadu_si512(block_ptr);
+    // From here on `p_v` counts single blocks.
+    p_v = _mm512_add_epi64(_mm512_slli_epi64(p_v, 2), q_v);
 
-      p_v = _mm512_add_epi32(p_v, a_v);
+    for block in blocks {
+      let block = _mm512_loadu_si512(block.as_ptr() as *const _);
 
-      a_v = _mm512_add_epi32(a_v, _mm512_sad_epu8(block, zero_v));
+      p_v = _mm512_add_epi64(p_v, a_v);
+      a_v = _mm512_add_epi64(a_v, _mm512_sad_epu8(block, zero_v));
       let mad = _mm512_maddubs_epi16(block, weights);
-      b_v = _mm512_add_epi32(b_v, _mm512_madd_epi16(mad, one_v));
+      b0_v = _mm512_add_epi32(b0_v, _mm512_madd_epi16(mad, one_v));
     }
 
-    b_v = _mm512_add_epi32(b_v, _mm512_slli_epi32(p_v, 6));
+    let b_v = widen_add(_mm512_add_epi32(b0_v, b1_v));
+    let b_v = _mm512_add_epi64(b_v, _mm512_slli_epi64(p_v, 6));
 
+    *b += len as u64 * *a + reduce_add(b_v);
     *a += reduce_add(a_v);
-    *b = reduce_add(b_v);
 
-    blocks_remainder
+    &chunk[len..]
   }
 
+  /// Adds a tail shorter than a block, loaded into the low bytes of a vector with a mask.
   #[inline(always)]
-  unsafe fn reduce_add(v: __m512i) -> u32 {
-    let v: [__m256i; 2] = core::mem::transmute(v);
+  unsafe fn reduce_add_tail(a: &mut u64, b: &mut u64, tail: &[u8]) {
+    if tail.is_empty() {
+      return;
+    }
 
-    reduce_add_256(v[0]) + reduce_add_256(v[1])
+    let mask = (1u64 << tail.len()) - 1;
+    let block = _mm512_maskz_loadu_epi8(mask, tail.as_ptr() as *const _);
+
+    let a_v = _mm512_sad_epu8(block, _mm512_setzero_si512());
+    let mad = _mm512_maddubs_epi16(block, get_weights());
+    let b_v = _mm512_madd_epi16(mad, _mm512_set1_epi16(1));
+
+    // Byte `i` is weighted `BLOCK_SIZE - i` instead of `tail.len() - i`.
+    let sum = reduce_add(a_v);
+    let padding = (BLOCK_SIZE - tail.len()) as u64 * sum;
+
+    *b += tail.len() as u64 * *a + reduce_add(widen_add(b_v)) - padding;
+    *a += sum;
   }
 
+  /// Adds the odd 32-bit lanes to the even ones, as 64-bit lanes.
   #[inline(always)]
-  unsafe fn reduce_add_256(v: __m256i) -> u32 {
-    let v: [__m128i; 2] = core::mem::transmute(v);
-    let sum = _mm_add_epi32(v[0], v[1]);
-    let hi = _mm_unpackhi_epi64(sum, sum);
+  unsafe fn widen_add(v: __m512i) -> __m512i {
+    let lo = _mm512_and_si512(v, _mm512_set1_epi64(0xffff_ffff));
 
-    let sum = _mm_add_epi32(hi, sum);
-    let hi = _mm_shuffle_epi32(sum, crate::imp::_MM_SHUFFLE(2, 3, 0, 1));
+    _mm512_add_epi64(lo, _mm512_srli_epi64(v, 32))
+  }
 
-    let sum = _mm_add_epi32(sum, hi);
-    let sum = _mm_cvtsi128_si32(sum) as _;
+  #[inline(always)]
+  unsafe fn reduce_add(v: __m512i) -> u64 {
+    let v: [__m256i; 2] = core::mem::transmute(v);
+    let v: [__m128i; 2] = core::mem::transmute(_mm256_add_epi64(v[0], v[1]));
+    let sum: [u64; 2] = core::mem::transmute(_mm_add_epi64(v[0], v[1]));
 
-    sum
+    sum[0] + sum[1]
   }
 
   #[inline(always)]
@@ -192,6 +319,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
   use rand::{Rng, SeedableRng, rngs::SmallRng};
+  use crate::imp::{scalar, Modulus};
 
   #[test]
   fn zeroes() {
@@ -230,6 +358,50 @@ mod tests {
     assert_sum_eq(&random[..1024 * 10]);
   }
 
+  #[test]
+  fn modulus() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    if super::get_blocks_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  #[test]
+  #[cfg(not(miri))]
+  fn chunks() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    // Saturated sums on both sides of the 1 MiB chunk boundary, static as it's too
+    // large for the stack.
+    static DATA: [u8; (1 << 21) + 100] = [0xff; (1 << 21) + 100];
+    let data = &DATA;
+
+    for &len in &[(1 << 20) - 5, 1 << 20, (1 << 20) + 5, data.len()] {
+      for &modulus in &[65536, 65521] {
+        let update = super::get_mod_imp().unwrap();
+        let left = update(0xfff0, 0xfff0, &data[..len], Modulus::new(modulus));
+        let right = scalar::update_mod(0xfff0, 0xfff0, &data[..len], Modulus::new(modulus));
+
+        assert_eq!(left, right, "len({}), modulus({})", len, modulus);
+      }
+    }
+  }
+
   /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
   #[test]
   fn wiki() {
@@ -245,4 +417,24 @@ mod tests {
       assert_eq!(left, right, "len({})", data.len());
     }
   }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
 }
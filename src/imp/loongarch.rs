@@ -0,0 +1,545 @@
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp};
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(feature = "std", target_feature = "lasx")
+))]
+pub use self::lasx::Lasx;
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(
+    feature = "std",
+    all(target_feature = "lsx", not(target_feature = "lasx"))
+  )
+))]
+pub use self::lsx::Lsx;
+
+/// Resolves update implementation if CPU supports lasx or lsx instructions.
+pub fn get_imp() -> Option<Adler32Imp> {
+  get_imp_inner().map(|(update, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports lasx or lsx instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports lasx or lsx instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks)| update_blocks)
+}
+
+#[inline]
+#[cfg(all(feature = "std", feature = "nightly", target_arch = "loongarch64"))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  if std::arch::is_loongarch_feature_detected!("lasx") {
+    Some((lasx::update, lasx::update_mod, lasx::update_blocks))
+  } else if std::arch::is_loongarch_feature_detected!("lsx") {
+    Some((lsx::update, lsx::update_mod, lsx::update_blocks))
+  } else {
+    None
+  }
+}
+
+#[inline]
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  target_feature = "lasx",
+  not(feature = "std")
+))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((lasx::update, lasx::update_mod, lasx::update_blocks))
+}
+
+#[inline]
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  target_feature = "lsx",
+  not(target_feature = "lasx"),
+  not(feature = "std")
+))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((lsx::update, lsx::update_mod, lsx::update_blocks))
+}
+
+#[inline]
+#[cfg(not(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(feature = "std", target_feature = "lsx")
+)))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  None
+}
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(feature = "std", target_feature = "lasx")
+))]
+mod lasx {
+  use crate::backend::Backend;
+  use crate::imp::{Modulus, HEAD_LEN, HEAD_WEIGHTS};
+  use core::arch::loongarch64::*;
+
+  const BLOCK_SIZE: usize = 32;
+
+  #[inline(always)]
+  pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    unsafe { update_imp(a, b, data) }
+  }
+
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  /// Statically dispatched LoongArch lasx backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Lasx(());
+
+  impl Backend for Lasx {
+    #[inline]
+    fn detect() -> Option<Self> {
+      if detected() {
+        Some(Lasx(()))
+      } else {
+        None
+      }
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
+  #[inline]
+  #[cfg(feature = "std")]
+  fn detected() -> bool {
+    std::arch::is_loongarch_feature_detected!("lasx")
+  }
+
+  /// Without `std` the module is only built when `lasx` is enabled at compile time.
+  #[inline]
+  #[cfg(not(feature = "std"))]
+  fn detected() -> bool {
+    true
+  }
+
+  #[inline]
+  #[target_feature(enable = "lasx")]
+  unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "lasx")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "lasx")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = a as u32;
+    let mut b = b as u32;
+
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
+    let remainder = chunks.remainder();
+    let modulus = modulus.value();
+    for chunk in chunks {
+      reduce_add_blocks(&mut a, &mut b, chunk);
+
+      a %= modulus;
+      b %= modulus;
+    }
+
+    reduce_add_blocks(&mut a, &mut b, remainder);
+
+    a %= modulus;
+    b %= modulus;
+
+    (a as u16, b as u16)
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
+    }
+
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
+
+    let weights = lasx_xvld::<0>(WEIGHTS.as_ptr() as *const _);
+
+    let zero_v = lasx_xvreplgr2vr_w(0);
+    let mut p_v = zero_v;
+    let mut a_v = zero_v;
+    let mut b_v = lasx_xvinsgr2vr_w::<0>(zero_v, (*b + *a * chunk.len() as u32) as _);
+
+    if head != 0 {
+      let head_v = lasx_xvld::<0>(HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr() as *const _);
+      let block = lasx_xvld::<0>(chunk.as_ptr() as *const _);
+      let block = lasx_xvand_v(block, lasx_xvslt_bu(zero_v, head_v));
+
+      let sum = lasx_xvhaddw_hu_bu(block, block);
+      a_v = lasx_xvhaddw_wu_hu(sum, sum);
+
+      let mad = lasx_xvmulwev_h_bu(block, head_v);
+      let mad = lasx_xvmaddwod_h_bu(mad, block, head_v);
+      b_v = lasx_xvadd_w(b_v, lasx_xvhaddw_wu_hu(mad, mad));
+    }
+
+    for block in blocks {
+      let block = lasx_xvld::<0>(block.as_ptr() as *const _);
+
+      p_v = lasx_xvadd_w(p_v, a_v);
+
+      let sum = lasx_xvhaddw_hu_bu(block, block);
+      a_v = lasx_xvadd_w(a_v, lasx_xvhaddw_wu_hu(sum, sum));
+
+      let mad = lasx_xvmulwev_h_bu(block, weights);
+      let mad = lasx_xvmaddwod_h_bu(mad, block, weights);
+      b_v = lasx_xvadd_w(b_v, lasx_xvhaddw_wu_hu(mad, mad));
+    }
+
+    b_v = lasx_xvadd_w(b_v, lasx_xvslli_w::<5>(p_v));
+
+    *a += reduce_add(a_v);
+    *b = reduce_add(b_v);
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add(v: m256i) -> u32 {
+    lasx_xvpickve2gr_wu::<0>(v)
+      + lasx_xvpickve2gr_wu::<1>(v)
+      + lasx_xvpickve2gr_wu::<2>(v)
+      + lasx_xvpickve2gr_wu::<3>(v)
+      + lasx_xvpickve2gr_wu::<4>(v)
+      + lasx_xvpickve2gr_wu::<5>(v)
+      + lasx_xvpickve2gr_wu::<6>(v)
+      + lasx_xvpickve2gr_wu::<7>(v)
+  }
+
+  static WEIGHTS: [u8; BLOCK_SIZE] = [
+    32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12,
+    11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1,
+  ];
+}
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "loongarch64",
+  any(
+    feature = "std",
+    all(target_feature = "lsx", not(target_feature = "lasx"))
+  )
+))]
+mod lsx {
+  use crate::backend::Backend;
+  use crate::imp::{Modulus, HEAD_LEN, HEAD_WEIGHTS};
+  use core::arch::loongarch64::*;
+
+  const BLOCK_SIZE: usize = 32;
+
+  #[inline(always)]
+  pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    unsafe { update_imp(a, b, data) }
+  }
+
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  /// Statically dispatched LoongArch lsx backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Lsx(());
+
+  impl Backend for Lsx {
+    #[inline]
+    fn detect() -> Option<Self> {
+      if detected() {
+        Some(Lsx(()))
+      } else {
+        None
+      }
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
+  #[inline]
+  #[cfg(feature = "std")]
+  fn detected() -> bool {
+    std::arch::is_loongarch_feature_detected!("lsx")
+  }
+
+  /// Without `std` the module is only built when `lsx` is enabled at compile time.
+  #[inline]
+  #[cfg(not(feature = "std"))]
+  fn detected() -> bool {
+    true
+  }
+
+  #[inline]
+  #[target_feature(enable = "lsx")]
+  unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "lsx")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "lsx")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = a as u32;
+    let mut b = b as u32;
+
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
+    let remainder = chunks.remainder();
+    let modulus = modulus.value();
+    for chunk in chunks {
+      reduce_add_blocks(&mut a, &mut b, chunk);
+
+      a %= modulus;
+      b %= modulus;
+    }
+
+    reduce_add_blocks(&mut a, &mut b, remainder);
+
+    a %= modulus;
+    b %= modulus;
+
+    (a as u16, b as u16)
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
+    }
+
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
+
+    let weight_hi_v = lsx_vld::<0>(WEIGHTS.as_ptr() as *const _);
+    let weight_lo_v = lsx_vld::<16>(WEIGHTS.as_ptr() as *const _);
+
+    let zero_v = lsx_vreplgr2vr_w(0);
+    let mut p_v = zero_v;
+    let mut a_v = zero_v;
+    let mut b_v = lsx_vinsgr2vr_w::<0>(zero_v, (*b + *a * chunk.len() as u32) as _);
+
+    if head != 0 {
+      let weights = HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr() as *const _;
+      let head_hi_v = lsx_vld::<0>(weights);
+      let head_lo_v = lsx_vld::<16>(weights);
+
+      let block_ptr = chunk.as_ptr() as *const _;
+      let left_v = lsx_vld::<0>(block_ptr);
+      let right_v = lsx_vld::<16>(block_ptr);
+      let left_v = lsx_vand_v(left_v, lsx_vslt_bu(zero_v, head_hi_v));
+      let right_v = lsx_vand_v(right_v, lsx_vslt_bu(zero_v, head_lo_v));
+
+      let sum = lsx_vhaddw_hu_bu(left_v, left_v);
+      a_v = lsx_vhaddw_wu_hu(sum, sum);
+      let mad = lsx_vmulwev_h_bu(left_v, head_hi_v);
+      let mad = lsx_vmaddwod_h_bu(mad, left_v, head_hi_v);
+      b_v = lsx_vadd_w(b_v, lsx_vhaddw_wu_hu(mad, mad));
+
+      let sum = lsx_vhaddw_hu_bu(right_v, right_v);
+      a_v = lsx_vadd_w(a_v, lsx_vhaddw_wu_hu(sum, sum));
+      let mad = lsx_vmulwev_h_bu(right_v, head_lo_v);
+      let mad = lsx_vmaddwod_h_bu(mad, right_v, head_lo_v);
+      b_v = lsx_vadd_w(b_v, lsx_vhaddw_wu_hu(mad, mad));
+    }
+
+    for block in blocks {
+      let block_ptr = block.as_ptr() as *const _;
+      let left_v = lsx_vld::<0>(block_ptr);
+      let right_v = lsx_vld::<16>(block_ptr);
+
+      p_v = lsx_vadd_w(p_v, a_v);
+
+      let sum = lsx_vhaddw_hu_bu(left_v, left_v);
+      a_v = lsx_vadd_w(a_v, lsx_vhaddw_wu_hu(sum, sum));
+      let mad = lsx_vmulwev_h_bu(left_v, weight_hi_v);
+      let mad = lsx_vmaddwod_h_bu(mad, left_v, weight_hi_v);
+      b_v = lsx_vadd_w(b_v, lsx_vhaddw_wu_hu(mad, mad));
+
+      let sum = lsx_vhaddw_hu_bu(right_v, right_v);
+      a_v = lsx_vadd_w(a_v, lsx_vhaddw_wu_hu(sum, sum));
+      let mad = lsx_vmulwev_h_bu(right_v, weight_lo_v);
+      let mad = lsx_vmaddwod_h_bu(mad, right_v, weight_lo_v);
+      b_v = lsx_vadd_w(b_v, lsx_vhaddw_wu_hu(mad, mad));
+    }
+
+    b_v = lsx_vadd_w(b_v, lsx_vslli_w::<5>(p_v));
+
+    *a += reduce_add(a_v);
+    *b = reduce_add(b_v);
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add(v: m128i) -> u32 {
+    lsx_vpickve2gr_wu::<0>(v)
+      + lsx_vpickve2gr_wu::<1>(v)
+      + lsx_vpickve2gr_wu::<2>(v)
+      + lsx_vpickve2gr_wu::<3>(v)
+  }
+
+  static WEIGHTS: [u8; BLOCK_SIZE] = [
+    32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12,
+    11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1,
+  ];
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+  use crate::imp::{scalar, Modulus};
+
+  #[test]
+  fn zeroes() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0]);
+    assert_sum_eq(&[0, 0]);
+    assert_sum_eq(&[0; 100]);
+    assert_sum_eq(&[0; 1024]);
+    assert_sum_eq(&[0; 1024 * 1024]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[1]);
+    assert_sum_eq(&[1, 1]);
+    assert_sum_eq(&[1; 100]);
+    assert_sum_eq(&[1; 1024]);
+    assert_sum_eq(&[1; 1024 * 1024]);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sum_eq(&random[..1]);
+    assert_sum_eq(&random[..100]);
+    assert_sum_eq(&random[..1024]);
+    assert_sum_eq(&random[..1024 * 1024]);
+  }
+
+  #[test]
+  fn modulus() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
+  #[test]
+  fn wiki() {
+    assert_sum_eq(b"Wikipedia");
+  }
+
+  fn assert_sum_eq(data: &[u8]) {
+    if let Some(update) = super::get_imp() {
+      let (a, b) = update(1, 0, data);
+      let left = u32::from(b) << 16 | u32::from(a);
+      let right = adler2::adler32_slice(data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+  }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+}
@@ -1,13 +1,138 @@
 pub mod avx2;
 pub mod avx512;
+pub mod loongarch;
 pub mod neon;
+pub mod portable;
+pub mod rvv;
+pub mod s390x;
 pub mod scalar;
 pub mod sse2;
 pub mod ssse3;
+pub mod vsx;
 pub mod wasm;
 
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::{mem, ptr};
+
 pub type Adler32Imp = fn(u16, u16, &[u8]) -> (u16, u16);
 
+/// Update function reducing the running sums by a caller supplied [`Modulus`] instead
+/// of the Adler-32 prime.
+pub type AdlerModImp = fn(u16, u16, &[u8], Modulus) -> (u16, u16);
+
+/// Update function writing the Adler-32 of every `block_size` byte block of `data` to
+/// `out`, the last block may be short.
+pub type Adler32BlocksImp = fn(&[u8], usize, &mut [u32]);
+
+/// Scan function rolling `(s1, s2)`, the sums of `data[..window]` with `offset` added to
+/// every byte, over the following windows `data[n..n + window]`. Returns the first `n`
+/// whose digest `s1 << 16 | s2` has every bit of `mask` set.
+pub type ScanImp = fn(u16, u16, &[u8], usize, u16, u32) -> Option<usize>;
+
+/// Adler-32 modulus, the largest prime below `2^16`.
+pub const MOD: u32 = 65521;
+
+/// Number of bytes summable without overflow for the Adler-32 modulus.
+pub const NMAX: usize = 5552;
+
+/// Inputs shorter than this are hashed with [`scalar::update_short`], which measured
+/// faster than the x86 vector implementations below a single 32 byte block. See the
+/// `short` group of `bench/variants.rs`.
+pub const SHORT_LEN: usize = 32;
+
+/// The shortest partial block at the start of an input that kernels hash as a masked
+/// vector rather than one byte at a time. See the `short` group of
+/// `bench/variants.rs`.
+pub const HEAD_LEN: usize = 16;
+
+/// Position weights of a partial block of `n` bytes at the start of an input, read as
+/// `HEAD_WEIGHTS[32 - n..][..32]`.
+///
+/// Kernels load the first whole block of the input and keep the bytes with a nonzero
+/// weight, which hashes the partial block as if it were zero-padded at the front.
+pub static HEAD_WEIGHTS: [u8; 64] = [
+  32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12, 11,
+  10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// A modulus within `1..=65536` together with its [`nmax`], computed once on
+/// construction so kernels don't search for it on every call.
+#[derive(Clone, Copy, Debug)]
+pub struct Modulus {
+  value: u32,
+  nmax: usize,
+}
+
+impl Modulus {
+  /// The Adler-32 modulus.
+  pub const ADLER32: Modulus = Modulus {
+    value: MOD,
+    nmax: NMAX,
+  };
+
+  pub const fn new(value: u32) -> Self {
+    Modulus {
+      value,
+      nmax: nmax(value),
+    }
+  }
+
+  #[inline(always)]
+  pub const fn value(self) -> u32 {
+    self.value
+  }
+
+  #[inline(always)]
+  pub const fn nmax(self) -> usize {
+    self.nmax
+  }
+
+  /// Returns [`nmax`] rounded down to a multiple of `block_size`.
+  #[inline(always)]
+  pub const fn chunk_size(self, block_size: usize) -> usize {
+    self.nmax / block_size * block_size
+  }
+}
+
+/// Returns the largest `n` such that `255n(n + 1) / 2 + (n + 1)(modulus - 1)` fits in a
+/// `u32`, the number of bytes that can be summed before `a` and `b` must be reduced.
+///
+/// Binary searches the range every modulus up to `2^16` falls into, `NMAX..5804`. The
+/// steps are unrolled and branch free so this stays a `const fn` on the MSRV.
+pub const fn nmax(modulus: u32) -> usize {
+  // `modulus - 1`, saturating at zero.
+  let m = modulus as u64 - (modulus != 0) as u64;
+  let (lo, hi) = (NMAX as u64, 5804);
+
+  // Nine halvings narrow the 252 wide range down to a single value.
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, hi) = nmax_step(lo, hi, m);
+  let (lo, _) = nmax_step(lo, hi, m);
+
+  lo as usize
+}
+
+/// Returns [`nmax`] rounded down to a multiple of `block_size`.
+#[inline(always)]
+pub const fn chunk_size(modulus: u32, block_size: usize) -> usize {
+  nmax(modulus) / block_size * block_size
+}
+
+/// Moves `lo` up to the midpoint if it fits, `hi` down to it otherwise.
+const fn nmax_step(lo: u64, hi: u64, m: u64) -> (u64, u64) {
+  let mid = (lo + hi) / 2;
+  let fits = (255 * mid * (mid + 1) / 2 + (mid + 1) * m <= 0xffff_ffff) as u64;
+
+  (lo + fits * (mid - lo), mid + fits * (hi - mid))
+}
+
 #[inline]
 #[allow(non_snake_case)]
 pub const fn _MM_SHUFFLE(z: u32, y: u32, x: u32, w: u32) -> i32 {
@@ -17,9 +142,131 @@ pub const fn _MM_SHUFFLE(z: u32, y: u32, x: u32, w: u32) -> i32 {
 pub fn get_imp() -> Adler32Imp {
   avx512::get_imp()
     .or_else(neon::get_imp)
+    .or_else(rvv::get_imp)
+    .or_else(loongarch::get_imp)
+    .or_else(vsx::get_imp)
+    .or_else(s390x::get_imp)
     .or_else(avx2::get_imp)
     .or_else(ssse3::get_imp)
     .or_else(sse2::get_imp)
     .or_else(wasm::get_imp)
+    .or_else(portable::get_imp)
     .unwrap_or(scalar::update)
 }
+
+/// Implementation resolved by the first [`cached_imp`] call, null before.
+static IMP: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Returns the implementation [`get_imp`] resolves, resolving it on the first call.
+pub fn cached_imp() -> Adler32Imp {
+  let imp = IMP.load(Ordering::Relaxed);
+
+  if imp.is_null() {
+    let imp = get_imp();
+    IMP.store(imp as *mut (), Ordering::Relaxed);
+    imp
+  } else {
+    // Only ever set from an `Adler32Imp`.
+    unsafe { mem::transmute::<*mut (), Adler32Imp>(imp) }
+  }
+}
+
+/// [`AdlerModImp`] for [`Modulus::ADLER32`], forwarding to [`cached_imp`].
+pub fn update_adler32(a: u16, b: u16, data: &[u8], _modulus: Modulus) -> (u16, u16) {
+  cached_imp()(a, b, data)
+}
+
+pub fn get_mod_imp() -> AdlerModImp {
+  avx512::get_mod_imp()
+    .or_else(neon::get_mod_imp)
+    .or_else(rvv::get_mod_imp)
+    .or_else(loongarch::get_mod_imp)
+    .or_else(vsx::get_mod_imp)
+    .or_else(s390x::get_mod_imp)
+    .or_else(avx2::get_mod_imp)
+    .or_else(ssse3::get_mod_imp)
+    .or_else(sse2::get_mod_imp)
+    .or_else(wasm::get_mod_imp)
+    .or_else(portable::get_mod_imp)
+    .unwrap_or(scalar::update_mod)
+}
+
+pub fn get_blocks_imp() -> Adler32BlocksImp {
+  avx512::get_blocks_imp()
+    .or_else(neon::get_blocks_imp)
+    .or_else(rvv::get_blocks_imp)
+    .or_else(loongarch::get_blocks_imp)
+    .or_else(vsx::get_blocks_imp)
+    .or_else(s390x::get_blocks_imp)
+    .or_else(avx2::get_blocks_imp)
+    .or_else(ssse3::get_blocks_imp)
+    .or_else(sse2::get_blocks_imp)
+    .or_else(wasm::get_blocks_imp)
+    .or_else(portable::get_blocks_imp)
+    .unwrap_or(scalar::update_blocks)
+}
+
+pub fn get_scan_imp() -> ScanImp {
+  neon::get_scan_imp()
+    .or_else(avx2::get_scan_imp)
+    .or_else(sse2::get_scan_imp)
+    .or_else(wasm::get_scan_imp)
+    .unwrap_or(scalar::scan)
+}
+
+#[cfg(test)]
+mod tests {
+  #[test]
+  fn nmax() {
+    assert_eq!(super::nmax(super::MOD), super::NMAX);
+    assert_eq!(super::nmax(65536), 5552);
+    assert_eq!(super::nmax(255), 5802);
+    assert_eq!(super::nmax(1), 5803);
+    assert_eq!(super::Modulus::ADLER32.chunk_size(32), 5536);
+
+    for modulus in 1..=65536 {
+      let n = super::nmax(modulus) as u64;
+      let sum = |n: u64| 255 * n * (n + 1) / 2 + (n + 1) * u64::from(modulus - 1);
+
+      assert!(
+        sum(n) <= 0xffff_ffff && sum(n + 1) > 0xffff_ffff,
+        "modulus({})",
+        modulus
+      );
+      assert_eq!(super::Modulus::new(modulus).nmax(), n as usize);
+    }
+  }
+
+  #[test]
+  fn tails() {
+    let imps: [fn() -> Option<super::Adler32Imp>; 11] = [
+      super::avx512::get_imp,
+      super::avx2::get_imp,
+      super::ssse3::get_imp,
+      super::sse2::get_imp,
+      super::neon::get_imp,
+      super::wasm::get_imp,
+      super::rvv::get_imp,
+      super::loongarch::get_imp,
+      super::vsx::get_imp,
+      super::s390x::get_imp,
+      super::portable::get_imp,
+    ];
+
+    let mut data = [0; super::NMAX * 2 + 200];
+    for (i, byte) in data.iter_mut().enumerate() {
+      *byte = 255 - i as u8;
+    }
+
+    for update in imps.iter().filter_map(|get_imp| get_imp()) {
+      for len in (0..200).chain(super::NMAX * 2 - 100..data.len()) {
+        assert_eq!(
+          update(0xfff0, 0xfff0, &data[..len]),
+          super::scalar::update(0xfff0, 0xfff0, &data[..len]),
+          "len({})",
+          len
+        );
+      }
+    }
+  }
+}
@@ -1,4 +1,7 @@
-use super::Adler32Imp;
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp, ScanImp};
+
+#[cfg(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly")))]
+pub use self::imp::Neon;
 
 #[cfg(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly")))]
 pub fn get_imp() -> Option<Adler32Imp> {
@@ -10,68 +13,211 @@ pub fn get_imp() -> Option<Adler32Imp> {
   None
 }
 
+#[cfg(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly")))]
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  Some(imp::update_mod)
+}
+
+#[cfg(not(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly"))))]
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  None
+}
+
+#[cfg(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly")))]
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  Some(imp::update_blocks)
+}
+
+#[cfg(not(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly"))))]
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  None
+}
+
+#[cfg(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly")))]
+pub fn get_scan_imp() -> Option<ScanImp> {
+  Some(imp::scan)
+}
+
+#[cfg(not(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly"))))]
+pub fn get_scan_imp() -> Option<ScanImp> {
+  None
+}
+
 #[cfg(all(target_feature = "neon", any(target_arch = "aarch64", feature = "nightly")))]
 mod imp {
-  const MOD: u32 = 65521;
-  const NMAX: usize = 5552;
+  use crate::backend::Backend;
+  use crate::imp::{chunk_size, scalar, Modulus, HEAD_LEN, HEAD_WEIGHTS};
+
   const BLOCK_SIZE: usize = 32;
-  const CHUNK_SIZE: usize = NMAX / BLOCK_SIZE * BLOCK_SIZE;
 
   #[cfg(target_arch = "aarch64")]
   use core::arch::aarch64::*;
   #[cfg(target_arch = "arm")]
   use core::arch::arm::*;
 
+  #[inline(always)]
   pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  /// Rolls the sums over eight windows at a time, see the sse2 implementation.
+  pub fn scan(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    let len = data.len().saturating_sub(window);
+    let mut n = 0;
+
+    unsafe {
+      let weight_v = vdupq_n_u16(window as u16);
+      let bias_v = vdupq_n_u16((window as u16).wrapping_mul(offset));
+      let mask_s1_v = vdupq_n_u16((mask >> 16) as u16);
+      let mask_s2_v = vdupq_n_u16(mask as u16);
+
+      let lanes: [u16; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+      let lanes_v = vld1q_u16(lanes.as_ptr());
+
+      let mut s1_v = vdupq_n_u16(s1);
+      let mut s2_v = vdupq_n_u16(s2);
+
+      while n + 8 <= len {
+        let ptr = data.as_ptr().add(n);
+        let old_v = vmovl_u8(vld1_u8(ptr));
+        let new_v = vmovl_u8(vld1_u8(ptr.add(window)));
+
+        let removed_v = vmlaq_u16(bias_v, old_v, weight_v);
+        let d1_v = prefix_sum(vsubq_u16(new_v, old_v));
+        let d2_v = prefix_sum(vsubq_u16(d1_v, removed_v));
+
+        let sums1_v = vaddq_u16(s1_v, d1_v);
+        let sums2_v = vaddq_u16(vmlaq_u16(s2_v, s1_v, lanes_v), d2_v);
+
+        let hits_v = vandq_u16(
+          vceqq_u16(vandq_u16(sums1_v, mask_s1_v), mask_s1_v),
+          vceqq_u16(vandq_u16(sums2_v, mask_s2_v), mask_s2_v),
+        );
+        if vget_lane_u64(vreinterpret_u64_u8(vmovn_u16(hits_v)), 0) != 0 {
+          // Stored rather than reinterpreted, the lane order holds on either endianness.
+          let mut hits = [0u16; 8];
+          vst1q_u16(hits.as_mut_ptr(), hits_v);
+
+          return hits.iter().position(|&hit| hit != 0).map(|lane| n + lane + 1);
+        }
+
+        let carry1_v = vdupq_n_u16(vgetq_lane_u16(d1_v, 7));
+        let carry2_v = vdupq_n_u16(vgetq_lane_u16(d2_v, 7));
+        s2_v = vaddq_u16(vaddq_u16(s2_v, vshlq_n_u16(s1_v, 3)), carry2_v);
+        s1_v = vaddq_u16(s1_v, carry1_v);
+        n += 8;
+      }
+
+      let s1 = vgetq_lane_u16(s1_v, 0);
+      let s2 = vgetq_lane_u16(s2_v, 0);
+
+      scalar::scan(s1, s2, &data[n..], window, offset, mask).map(|m| n + m)
+    }
+  }
+
+  /// Returns the inclusive prefix sums of the 16-bit lanes of `v`.
+  #[inline(always)]
+  unsafe fn prefix_sum(v: uint16x8_t) -> uint16x8_t {
+    let zero_v = vdupq_n_u16(0);
+    let v = vaddq_u16(v, vextq_u16(zero_v, v, 7));
+    let v = vaddq_u16(v, vextq_u16(zero_v, v, 6));
+
+    vaddq_u16(v, vextq_u16(zero_v, v, 4))
+  }
+
+  /// Statically dispatched neon backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Neon(());
+
+  impl Backend for Neon {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Neon(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
+  #[inline(always)]
+  fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
     let mut a = a as u32;
     let mut b = b as u32;
 
-    let chunks = data.chunks_exact(CHUNK_SIZE);
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
     let remainder = chunks.remainder();
+    let modulus = modulus.value();
     for chunk in chunks {
-      update_chunk_block(&mut a, &mut b, chunk);
+      update_chunk_block(&mut a, &mut b, chunk, modulus);
     }
 
-    update_block(&mut a, &mut b, remainder);
+    update_block(&mut a, &mut b, remainder, modulus);
 
     (a as u16, b as u16)
   }
 
-  fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert!(
-      chunk.len() <= CHUNK_SIZE,
+      chunk.len() <= chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected <= {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
-    for byte in reduce_add_blocks(a, b, chunk) {
-      *a += *byte as u32;
-      *b += *a;
-    }
+    reduce_add_blocks(a, b, chunk, modulus);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
-  fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert_eq!(
       chunk.len(),
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
-    reduce_add_blocks(a, b, chunk);
+    reduce_add_blocks(a, b, chunk, modulus);
   }
 
-  fn reduce_add_blocks<'a>(a: &mut u32, b: &mut u32, chunk: &'a [u8]) -> &'a [u8] {
-    if chunk.len() < BLOCK_SIZE {
-      return chunk;
+  fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8], modulus: u32) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
     }
-    let blocks = chunk.chunks_exact(BLOCK_SIZE);
-    let blocks_remainder = blocks.remainder();
+
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
 
     // Conversion of the code from Chromium zlib:
     // https://chromium.googlesource.com/chromium/src/third_party/+/main/zlib/adler32_simd.c
@@ -79,8 +225,6 @@ mod imp {
       // a and b accumulators are initially zero.
       let mut a_v: uint32x4_t = vdupq_n_u32(0);
       let mut b_v: uint32x4_t = vdupq_n_u32(0);
-      // b_v[3] contains the last term (n) for the B part
-      b_v = vsetq_lane_u32(*a * (blocks.len() as u32), b_v, 3);
 
       // Computing the unrolled prefix-sum
       let mut v_column_sum_1: uint16x8_t = vdupq_n_u16(0);
@@ -88,6 +232,25 @@ mod imp {
       let mut v_column_sum_3: uint16x8_t = vdupq_n_u16(0);
       let mut v_column_sum_4: uint16x8_t = vdupq_n_u16(0);
 
+      // The columns weight the masked partial block as a whole block, `c_v` holds the
+      // excess weight of its bytes.
+      let mut c_v: uint32x4_t = vdupq_n_u32(0);
+      if head != 0 {
+        let weights = HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr();
+        let mask1 = vtstq_u8(vld1q_u8(weights), vld1q_u8(weights));
+        let mask2 = vtstq_u8(vld1q_u8(weights.add(16)), vld1q_u8(weights.add(16)));
+        let bytes1: uint8x16_t = vandq_u8(vld1q_u8(chunk.as_ptr()), mask1);
+        let bytes2: uint8x16_t = vandq_u8(vld1q_u8(chunk.as_ptr().add(16)), mask2);
+
+        a_v = vpaddlq_u16(vpadalq_u8(vpaddlq_u8(bytes1), bytes2));
+        c_v = vmulq_n_u32(a_v, (BLOCK_SIZE - head) as u32);
+
+        v_column_sum_1 = vmovl_u8(vget_low_u8(bytes1));
+        v_column_sum_2 = vmovl_u8(vget_high_u8(bytes1));
+        v_column_sum_3 = vmovl_u8(vget_low_u8(bytes2));
+        v_column_sum_4 = vmovl_u8(vget_high_u8(bytes2));
+      }
+
       for block in blocks {
         let block_ptr = block.as_ptr();
         // Slurp in 32 bytes
@@ -131,19 +294,18 @@ mod imp {
       b_v = vmlal_u16(b_v, vget_high_u16(v_column_sum_3), vld1_u16(w6.as_ptr()));
       b_v = vmlal_u16(b_v, vget_low_u16(v_column_sum_4), vld1_u16(w7.as_ptr()));
       b_v = vmlal_u16(b_v, vget_high_u16(v_column_sum_4), vld1_u16(w8.as_ptr()));
+      b_v = vsubq_u32(b_v, c_v);
 
       // Pyramid pairwise-add to get the final output.
       // *a = vaddvq_u32(a_v) would also do the job.
       let sum1: uint32x2_t = vpadd_u32(vget_low_u32(a_v), vget_high_u32(a_v));
       let sum2: uint32x2_t = vpadd_u32(vget_low_u32(b_v), vget_high_u32(b_v));
       let sum3: uint32x2_t = vpadd_u32(sum1, sum2);
+      *b += *a * chunk.len() as u32 + vget_lane_u32(sum3, 1);
       *a += vget_lane_u32(sum3, 0);
-      *b += vget_lane_u32(sum3, 1);
 
-      *a %= MOD;
-      *b %= MOD;
-
-      blocks_remainder
+      *a %= modulus;
+      *b %= modulus;
     }
   }
 }
@@ -151,6 +313,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
   use rand::{rngs::SmallRng, Rng, SeedableRng};
+  use crate::imp::{scalar, Modulus};
 
   #[test]
   fn zeroes() {
@@ -183,6 +346,44 @@ mod tests {
     assert_sum_eq(&random[..1024 * 1024]);
   }
 
+  #[test]
+  fn modulus() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    if super::get_blocks_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  #[test]
+  fn scan() {
+    if super::get_scan_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 4];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &mask in &[0, 0x0000_00ff, 0x0001_0fff, 0x7fff_ffff] {
+      for start in 0..32 {
+        assert_scan_eq(&random[start..], mask);
+      }
+    }
+    assert_scan_eq(&[0xff; 1024], 0);
+    assert_scan_eq(&random[..70], 0x7fff_ffff);
+  }
+
   /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
   #[test]
   fn wiki() {
@@ -198,4 +399,33 @@ mod tests {
       assert_eq!(left, right, "len({})", data.len());
     }
   }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+
+  fn assert_scan_eq(data: &[u8], mask: u32) {
+    if let Some(scan) = super::get_scan_imp() {
+      let left = scan(0x1234, 0xfedc, data, 64, 31, mask);
+      let right = scalar::scan(0x1234, 0xfedc, data, 64, 31, mask);
+
+      assert_eq!(left, right, "len({}), mask({:#x})", data.len(), mask);
+    }
+  }
 }
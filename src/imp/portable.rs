@@ -0,0 +1,247 @@
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp};
+
+#[cfg(feature = "nightly")]
+pub use self::imp::Portable;
+
+/// Resolves update implementation written with `core::simd`, available on every target
+/// with the `nightly` feature.
+pub fn get_imp() -> Option<Adler32Imp> {
+  get_imp_inner().map(|(update, _, _)| update)
+}
+
+/// Resolves modular update implementation written with `core::simd`.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _)| update_mod)
+}
+
+/// Resolves block checksum implementation written with `core::simd`.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks)| update_blocks)
+}
+
+#[inline]
+#[cfg(feature = "nightly")]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks))
+}
+
+#[inline]
+#[cfg(not(feature = "nightly"))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  None
+}
+
+#[cfg(feature = "nightly")]
+mod imp {
+  use crate::backend::Backend;
+  use crate::imp::{Modulus, HEAD_LEN, HEAD_WEIGHTS};
+  use core::simd::prelude::*;
+
+  const BLOCK_SIZE: usize = 32;
+
+  #[inline(always)]
+  pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  /// Statically dispatched portable `core::simd` backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Portable(());
+
+  impl Backend for Portable {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Portable(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
+  #[inline(always)]
+  fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = a as u32;
+    let mut b = b as u32;
+
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
+    let remainder = chunks.remainder();
+    let modulus = modulus.value();
+    for chunk in chunks {
+      reduce_add_blocks(&mut a, &mut b, chunk);
+
+      a %= modulus;
+      b %= modulus;
+    }
+
+    reduce_add_blocks(&mut a, &mut b, remainder);
+
+    a %= modulus;
+    b %= modulus;
+
+    (a as u16, b as u16)
+  }
+
+  #[inline(always)]
+  fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
+    }
+
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
+
+    // Every lane sums one column of the blocks in `a_v` and the previous column sums in
+    // `p_v`, the column sums are weighted by their distance to the end of a block once
+    // done.
+    let mut p_v = u32x32::splat(0);
+    let mut a_v = u32x32::splat(0);
+
+    // The columns weight the masked partial block as a whole block, `c_v` holds the
+    // excess weight of its bytes.
+    let mut c_v = u32x32::splat(0);
+    if head != 0 {
+      let weights = u8x32::from_slice(&HEAD_WEIGHTS[BLOCK_SIZE - head..][..BLOCK_SIZE]);
+      let block = u8x32::from_slice(&chunk[..BLOCK_SIZE]);
+      let mask = weights.simd_ne(u8x32::splat(0));
+
+      a_v = mask.select(block, u8x32::splat(0)).cast::<u32>();
+      c_v = a_v * u32x32::splat((BLOCK_SIZE - head) as u32);
+    }
+
+    let initial_b = *b + *a * chunk.len() as u32;
+
+    for block in blocks {
+      let block = u8x32::from_slice(block);
+
+      p_v += a_v;
+      a_v += block.cast::<u32>();
+    }
+
+    let b_v = (p_v << 5) + a_v * u32x32::from_array(WEIGHTS) - c_v;
+
+    *a += a_v.reduce_sum();
+    *b = initial_b + b_v.reduce_sum();
+  }
+
+  const WEIGHTS: [u32; BLOCK_SIZE] = [
+    32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12,
+    11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1,
+  ];
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+  use crate::imp::{scalar, Modulus};
+
+  #[test]
+  fn zeroes() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0]);
+    assert_sum_eq(&[0, 0]);
+    assert_sum_eq(&[0; 100]);
+    assert_sum_eq(&[0; 1024]);
+    assert_sum_eq(&[0; 1024 * 1024]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[1]);
+    assert_sum_eq(&[1, 1]);
+    assert_sum_eq(&[1; 100]);
+    assert_sum_eq(&[1; 1024]);
+    assert_sum_eq(&[1; 1024 * 1024]);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sum_eq(&random[..1]);
+    assert_sum_eq(&random[..100]);
+    assert_sum_eq(&random[..1024]);
+    assert_sum_eq(&random[..1024 * 1024]);
+  }
+
+  #[test]
+  fn modulus() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
+  #[test]
+  fn wiki() {
+    assert_sum_eq(b"Wikipedia");
+  }
+
+  fn assert_sum_eq(data: &[u8]) {
+    if let Some(update) = super::get_imp() {
+      let (a, b) = update(1, 0, data);
+      let left = u32::from(b) << 16 | u32::from(a);
+      let right = adler2::adler32_slice(data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+  }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+}
@@ -0,0 +1,324 @@
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp};
+
+#[cfg(all(
+  target_arch = "riscv64",
+  any(all(feature = "std", target_os = "linux"), target_feature = "v")
+))]
+pub use self::imp::Rvv;
+
+/// Resolves update implementation if CPU supports RISC-V vector instructions.
+pub fn get_imp() -> Option<Adler32Imp> {
+  get_imp_inner().map(|(update, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports RISC-V vector instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports RISC-V vector instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks)| update_blocks)
+}
+
+#[inline]
+#[cfg(all(feature = "std", target_arch = "riscv64", target_os = "linux"))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  // `is_riscv_feature_detected!` is unstable, the kernel reports the single letter
+  // extensions in `AT_HWCAP` instead.
+  extern "C" {
+    fn getauxval(kind: std::os::raw::c_ulong) -> std::os::raw::c_ulong;
+  }
+
+  const AT_HWCAP: std::os::raw::c_ulong = 16;
+  const HWCAP_V: std::os::raw::c_ulong = 1 << (b'v' - b'a');
+
+  if unsafe { getauxval(AT_HWCAP) } & HWCAP_V != 0 {
+    Some((imp::update, imp::update_mod, imp::update_blocks))
+  } else {
+    None
+  }
+}
+
+#[inline]
+#[cfg(all(
+  target_arch = "riscv64",
+  target_feature = "v",
+  not(all(feature = "std", target_os = "linux"))
+))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks))
+}
+
+#[inline]
+#[cfg(not(any(
+  all(feature = "std", target_arch = "riscv64", target_os = "linux"),
+  all(target_arch = "riscv64", target_feature = "v")
+)))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  None
+}
+
+#[cfg(all(
+  target_arch = "riscv64",
+  any(all(feature = "std", target_os = "linux"), target_feature = "v")
+))]
+mod imp {
+  use crate::backend::Backend;
+  use crate::imp::Modulus;
+  use core::arch::asm;
+
+  #[inline(always)]
+  pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  /// Statically dispatched RISC-V vector backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Rvv(());
+
+  impl Backend for Rvv {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Rvv(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
+  #[inline(always)]
+  fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = a as u32;
+    let mut b = b as u32;
+
+    // The stripe width is the vector length, which is only known at runtime. It is
+    // capped at `nmax` so a chunk always holds at least one stripe, even on cores
+    // with a VLEN large enough for a single register to exceed it.
+    let stripe = vlmax().min(modulus.nmax());
+    let chunks = data.chunks_exact(modulus.chunk_size(stripe));
+    let remainder = chunks.remainder();
+    let modulus = modulus.value();
+    for chunk in chunks {
+      reduce_add_stripes(&mut a, &mut b, chunk, stripe);
+
+      a %= modulus;
+      b %= modulus;
+    }
+
+    // The tail is a single stripe with a shorter vector length.
+    let tail = reduce_add_stripes(&mut a, &mut b, remainder, stripe);
+    if !tail.is_empty() {
+      reduce_add_stripes(&mut a, &mut b, tail, tail.len());
+    }
+
+    a %= modulus;
+    b %= modulus;
+
+    (a as u16, b as u16)
+  }
+
+  /// Returns the number of bytes in a single `e8, m1` vector register group.
+  #[inline(always)]
+  fn vlmax() -> usize {
+    let vl: usize;
+
+    unsafe {
+      asm!(
+        ".option push",
+        ".option arch, +v",
+        "vsetvli {vl}, zero, e8, m1, ta, ma",
+        ".option pop",
+        vl = out(reg) vl,
+        options(nomem, nostack),
+      );
+    }
+
+    vl
+  }
+
+  #[inline(always)]
+  fn reduce_add_stripes<'a>(
+    a: &mut u32,
+    b: &mut u32,
+    chunk: &'a [u8],
+    stripe: usize,
+  ) -> &'a [u8] {
+    let stripes = chunk.len() / stripe;
+    if stripes == 0 {
+      return chunk;
+    }
+
+    let sum_a: usize;
+    let sum_b: usize;
+    let sum_w: usize;
+
+    // Every lane keeps a column sum in `v8` and the sum of previous column sums in
+    // `v12`, widened to 32 bits. Once done the column sums are weighted by
+    // `stripe - lane` to account for their position within a stripe.
+    unsafe {
+      asm!(
+        ".option push",
+        ".option arch, +v",
+        "vsetvli zero, {vl}, e32, m4, ta, ma",
+        "vmv.v.i v8, 0",
+        "vmv.v.i v12, 0",
+        "2:",
+        "vsetvli zero, zero, e8, m1, ta, ma",
+        "vle8.v v0, ({ptr})",
+        "vsetvli zero, zero, e32, m4, ta, ma",
+        "vadd.vv v12, v12, v8",
+        "vzext.vf4 v16, v0",
+        "vadd.vv v8, v8, v16",
+        "add {ptr}, {ptr}, {vl}",
+        "addi {n}, {n}, -1",
+        "bnez {n}, 2b",
+        "vmv.s.x v20, zero",
+        "vredsum.vs v20, v8, v20",
+        "vmv.x.s {sum_a}, v20",
+        "vmv.s.x v20, zero",
+        "vredsum.vs v20, v12, v20",
+        "vmv.x.s {sum_b}, v20",
+        "vid.v v16",
+        "vrsub.vx v16, v16, {vl}",
+        "vmul.vv v16, v16, v8",
+        "vmv.s.x v20, zero",
+        "vredsum.vs v20, v16, v20",
+        "vmv.x.s {sum_w}, v20",
+        ".option pop",
+        vl = in(reg) stripe,
+        ptr = inout(reg) chunk.as_ptr() => _,
+        n = inout(reg) stripes => _,
+        sum_a = out(reg) sum_a,
+        sum_b = out(reg) sum_b,
+        sum_w = out(reg) sum_w,
+        out("v0") _,
+        out("v8") _, out("v9") _, out("v10") _, out("v11") _,
+        out("v12") _, out("v13") _, out("v14") _, out("v15") _,
+        out("v16") _, out("v17") _, out("v18") _, out("v19") _,
+        out("v20") _, out("v21") _, out("v22") _, out("v23") _,
+        options(readonly, nostack),
+      );
+    }
+
+    // `vmv.x.s` sign extends, only the low 32 bits are meaningful.
+    let sum_a = sum_a as u32;
+    let sum_b = sum_b as u32;
+    let sum_w = sum_w as u32;
+
+    *b += (stripes * stripe) as u32 * *a + stripe as u32 * sum_b + sum_w;
+    *a += sum_a;
+
+    &chunk[stripes * stripe..]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+  use crate::imp::{scalar, Modulus};
+
+  #[test]
+  fn zeroes() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0]);
+    assert_sum_eq(&[0, 0]);
+    assert_sum_eq(&[0; 100]);
+    assert_sum_eq(&[0; 1024]);
+    assert_sum_eq(&[0; 1024 * 1024]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[1]);
+    assert_sum_eq(&[1, 1]);
+    assert_sum_eq(&[1; 100]);
+    assert_sum_eq(&[1; 1024]);
+    assert_sum_eq(&[1; 1024 * 1024]);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sum_eq(&random[..1]);
+    assert_sum_eq(&random[..100]);
+    assert_sum_eq(&random[..1024]);
+    assert_sum_eq(&random[..1024 * 1024]);
+  }
+
+  #[test]
+  fn modulus() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
+  #[test]
+  fn wiki() {
+    assert_sum_eq(b"Wikipedia");
+  }
+
+  fn assert_sum_eq(data: &[u8]) {
+    if let Some(update) = super::get_imp() {
+      let (a, b) = update(1, 0, data);
+      let left = u32::from(b) << 16 | u32::from(a);
+      let (a, b) = scalar::update(1, 0, data);
+      let right = u32::from(b) << 16 | u32::from(a);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+  }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+}
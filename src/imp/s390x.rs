@@ -0,0 +1,319 @@
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp};
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "s390x",
+  any(feature = "std", target_feature = "vector")
+))]
+pub use self::imp::S390x;
+
+/// Resolves update implementation if CPU supports vector facility instructions.
+pub fn get_imp() -> Option<Adler32Imp> {
+  get_imp_inner().map(|(update, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports vector facility instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports vector facility instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks)| update_blocks)
+}
+
+#[inline]
+#[cfg(all(feature = "std", feature = "nightly", target_arch = "s390x"))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  if std::arch::is_s390x_feature_detected!("vector") {
+    Some((imp::update, imp::update_mod, imp::update_blocks))
+  } else {
+    None
+  }
+}
+
+#[inline]
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "s390x",
+  target_feature = "vector",
+  not(feature = "std")
+))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks))
+}
+
+#[inline]
+#[cfg(not(all(
+  feature = "nightly",
+  target_arch = "s390x",
+  any(feature = "std", target_feature = "vector")
+)))]
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  None
+}
+
+#[cfg(all(
+  feature = "nightly",
+  target_arch = "s390x",
+  any(feature = "std", target_feature = "vector")
+))]
+mod imp {
+  use crate::backend::Backend;
+  use crate::imp::{Modulus, HEAD_LEN, HEAD_WEIGHTS};
+  use core::arch::s390x::*;
+
+  const BLOCK_SIZE: usize = 32;
+
+  #[inline(always)]
+  pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    unsafe { update_imp(a, b, data) }
+  }
+
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  /// Statically dispatched s390x vector backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct S390x(());
+
+  impl Backend for S390x {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| S390x(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
+  #[inline]
+  #[target_feature(enable = "vector")]
+  unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "vector")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "vector")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    let mut a = a as u32;
+    let mut b = b as u32;
+
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
+    let remainder = chunks.remainder();
+    let modulus = modulus.value();
+    for chunk in chunks {
+      reduce_add_blocks(&mut a, &mut b, chunk);
+
+      a %= modulus;
+      b %= modulus;
+    }
+
+    reduce_add_blocks(&mut a, &mut b, remainder);
+
+    a %= modulus;
+    b %= modulus;
+
+    (a as u16, b as u16)
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
+    }
+
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
+
+    // Big-endian loads put the first byte in element zero, matching the weights.
+    let weight_hi_v: vector_unsigned_char = vec_xl(0, WEIGHTS.as_ptr());
+    let weight_lo_v: vector_unsigned_char = vec_xl(16, WEIGHTS.as_ptr());
+
+    let zero_v: vector_unsigned_int = vec_splats(0u32);
+    let zero_b_v: vector_unsigned_char = vec_splats(0u8);
+    let zero_h_v: vector_unsigned_short = vec_splats(0u16);
+    let mut p_v = zero_v;
+    let mut a_v = zero_v;
+    let mut b_v = zero_v;
+
+    let initial_b = *b + *a * chunk.len() as u32;
+
+    if head != 0 {
+      let weights = HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr();
+      let head_hi_v: vector_unsigned_char = vec_xl(0, weights);
+      let head_lo_v: vector_unsigned_char = vec_xl(16, weights);
+
+      let left_v: vector_unsigned_char = vec_xl(0, chunk.as_ptr());
+      let right_v: vector_unsigned_char = vec_xl(16, chunk.as_ptr());
+      let left_v = vec_sel(zero_b_v, left_v, vec_cmpgt(head_hi_v, zero_b_v));
+      let right_v = vec_sel(zero_b_v, right_v, vec_cmpgt(head_lo_v, zero_b_v));
+
+      a_v = vec_add(vec_sum4(left_v, zero_b_v), vec_sum4(right_v, zero_b_v));
+      let mad: vector_unsigned_short = vec_mule(left_v, head_hi_v);
+      let mad = vec_moadd(left_v, head_hi_v, mad);
+      b_v = vec_sum4(mad, zero_h_v);
+      let mad: vector_unsigned_short = vec_mule(right_v, head_lo_v);
+      let mad = vec_moadd(right_v, head_lo_v, mad);
+      b_v = vec_add(b_v, vec_sum4(mad, zero_h_v));
+    }
+
+    for block in blocks {
+      let left_v: vector_unsigned_char = vec_xl(0, block.as_ptr());
+      let right_v: vector_unsigned_char = vec_xl(16, block.as_ptr());
+
+      p_v = vec_add(p_v, a_v);
+
+      a_v = vec_add(a_v, vec_sum4(left_v, zero_b_v));
+      let mad: vector_unsigned_short = vec_mule(left_v, weight_hi_v);
+      let mad = vec_moadd(left_v, weight_hi_v, mad);
+      b_v = vec_add(b_v, vec_sum4(mad, zero_h_v));
+
+      a_v = vec_add(a_v, vec_sum4(right_v, zero_b_v));
+      let mad: vector_unsigned_short = vec_mule(right_v, weight_lo_v);
+      let mad = vec_moadd(right_v, weight_lo_v, mad);
+      b_v = vec_add(b_v, vec_sum4(mad, zero_h_v));
+    }
+
+    let shift_v: vector_unsigned_int = vec_splats(5u32);
+    b_v = vec_add(b_v, vec_sl(p_v, shift_v));
+
+    *a += reduce_add(a_v);
+    *b = initial_b + reduce_add(b_v);
+  }
+
+  #[inline(always)]
+  unsafe fn reduce_add(v: vector_unsigned_int) -> u32 {
+    vec_extract(v, 0) + vec_extract(v, 1) + vec_extract(v, 2) + vec_extract(v, 3)
+  }
+
+  static WEIGHTS: [u8; BLOCK_SIZE] = [
+    32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12,
+    11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1,
+  ];
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+  use crate::imp::{scalar, Modulus};
+
+  #[test]
+  fn zeroes() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[0]);
+    assert_sum_eq(&[0, 0]);
+    assert_sum_eq(&[0; 100]);
+    assert_sum_eq(&[0; 1024]);
+    assert_sum_eq(&[0; 1024 * 1024]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sum_eq(&[]);
+    assert_sum_eq(&[1]);
+    assert_sum_eq(&[1, 1]);
+    assert_sum_eq(&[1; 100]);
+    assert_sum_eq(&[1; 1024]);
+    assert_sum_eq(&[1; 1024 * 1024]);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sum_eq(&random[..1]);
+    assert_sum_eq(&random[..100]);
+    assert_sum_eq(&random[..1024]);
+    assert_sum_eq(&random[..1024 * 1024]);
+  }
+
+  #[test]
+  fn modulus() {
+    let mut random = [0; 1024 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
+  #[test]
+  fn wiki() {
+    assert_sum_eq(b"Wikipedia");
+  }
+
+  fn assert_sum_eq(data: &[u8]) {
+    if let Some(update) = super::get_imp() {
+      let (a, b) = update(1, 0, data);
+      let left = u32::from(b) << 16 | u32::from(a);
+      let right = adler2::adler32_slice(data);
+
+      assert_eq!(left, right, "len({})", data.len());
+    }
+  }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+}
@@ -1,21 +1,131 @@
-const MOD: u32 = 65521;
-const NMAX: usize = 5552;
+use super::{Modulus, NMAX};
+use crate::backend::Backend;
 
+/// Statically dispatched scalar backend, available on every target.
+#[derive(Clone, Copy, Debug)]
+pub struct Scalar(());
+
+impl Scalar {
+  /// Returns the scalar backend, which needs no CPU support.
+  pub fn new() -> Self {
+    Scalar(())
+  }
+}
+
+impl Default for Scalar {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Backend for Scalar {
+  #[inline]
+  fn detect() -> Option<Self> {
+    Some(Scalar(()))
+  }
+
+  #[inline]
+  fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update(a, b, data)
+  }
+}
+
+#[inline]
 pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+  update_chunks(a, b, data, Modulus::ADLER32)
+}
+
+pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+  update_chunks(a, b, data, modulus)
+}
+
+pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+  for (block, out) in data.chunks(block_size).zip(out) {
+    let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+    *out = (u32::from(b) << 16) | u32::from(a);
+  }
+}
+
+pub fn scan(
+  s1: u16,
+  s2: u16,
+  data: &[u8],
+  window: usize,
+  offset: u16,
+  mask: u32,
+) -> Option<usize> {
+  let (mut s1, mut s2) = (s1, s2);
+  let weight = window as u16;
+  let new = data.get(window..).unwrap_or(&[]);
+
+  for (n, (&old, &new)) in data.iter().zip(new).enumerate() {
+    let old = u16::from(old).wrapping_add(offset);
+    let new = u16::from(new).wrapping_add(offset);
+
+    s1 = s1.wrapping_add(new).wrapping_sub(old);
+    s2 = s2.wrapping_add(s1).wrapping_sub(weight.wrapping_mul(old));
+
+    if ((u32::from(s1) << 16) | u32::from(s2)) & mask == mask {
+      return Some(n + 1);
+    }
+  }
+
+  None
+}
+
+/// Update function for inputs shorter than [`NMAX`], unrolled by four bytes and reduced
+/// once at the end.
+///
+/// Used for inputs shorter than [`SHORT_LEN`](super::SHORT_LEN), where the setup of the
+/// vector implementations costs more than it saves.
+#[inline]
+pub fn update_short(a: u16, b: u16, data: &[u8], modulus: u32) -> (u16, u16) {
+  debug_assert!(data.len() < NMAX, "Unexpected length {}", data.len());
+
   let mut a = a as u32;
   let mut b = b as u32;
 
-  let chunks = data.chunks_exact(NMAX);
+  let chunks = data.chunks_exact(4);
   let remainder = chunks.remainder();
 
+  for chunk in chunks {
+    let (x0, x1, x2, x3) = (
+      chunk[0] as u32,
+      chunk[1] as u32,
+      chunk[2] as u32,
+      chunk[3] as u32,
+    );
+
+    b += 4 * a + 4 * x0 + 3 * x1 + 2 * x2 + x3;
+    a += x0 + x1 + x2 + x3;
+  }
+
+  for byte in remainder {
+    a += *byte as u32;
+    b += a;
+  }
+
+  ((a % modulus) as u16, (b % modulus) as u16)
+}
+
+#[inline(always)]
+fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+  let mut a = a as u32;
+  let mut b = b as u32;
+
+  let chunks = data.chunks_exact(modulus.nmax());
+  let remainder = chunks.remainder();
+  let modulus = modulus.value();
+
   for chunk in chunks {
     for byte in chunk {
       a = a.wrapping_add(*byte as _);
       b = b.wrapping_add(a);
     }
 
-    a %= MOD;
-    b %= MOD;
+    a %= modulus;
+    b %= modulus;
   }
 
   for byte in remainder {
@@ -23,8 +133,8 @@ pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
     b = b.wrapping_add(a);
   }
 
-  a %= MOD;
-  b %= MOD;
+  a %= modulus;
+  b %= modulus;
 
   (a as u16, b as u16)
 }
@@ -75,6 +185,65 @@ mod tests {
     assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
   }
 
+  #[test]
+  fn short() {
+    let mut data = [0; super::NMAX - 1];
+    for (i, byte) in data.iter_mut().enumerate() {
+      *byte = 255 - i as u8;
+    }
+
+    for len in 0..100 {
+      assert_eq!(
+        super::update_short(0xfff0, 0xfff0, &data[..len], crate::imp::MOD),
+        super::update(0xfff0, 0xfff0, &data[..len]),
+        "len({})",
+        len
+      );
+    }
+
+    assert_eq!(
+      super::update_short(0xffff, 0xffff, &data, 65536),
+      super::update_mod(0xffff, 0xffff, &data, super::Modulus::new(65536))
+    );
+    assert_eq!(
+      super::update_short(1, 0, &data, 251),
+      super::update_mod(1, 0, &data, super::Modulus::new(251))
+    );
+  }
+
+  #[test]
+  fn scan() {
+    let mut data = [0; 1024];
+    for (i, byte) in data.iter_mut().enumerate() {
+      *byte = (i * 7919 % 251) as u8;
+    }
+
+    for &mask in &[0x0000_00ff, 0x0007_001f, 0x7fff_ffff] {
+      let (s1, s2) = window_sums(&data[..64]);
+      let expected = (1..=data.len() - 64).find(|&n| {
+        let (s1, s2) = window_sums(&data[n..n + 64]);
+        ((u32::from(s1) << 16) | u32::from(s2)) & mask == mask
+      });
+
+      assert_eq!(super::scan(s1, s2, &data, 64, 31, mask), expected);
+    }
+
+    assert_eq!(super::scan(0, 0, &data[..64], 64, 31, 0), None);
+    assert_eq!(super::scan(0, 0, &data[..10], 64, 31, 0), None);
+  }
+
+  /// Sums of `window` with an offset of `31` added to every byte.
+  fn window_sums(window: &[u8]) -> (u16, u16) {
+    let (mut s1, mut s2) = (0u16, 0u16);
+
+    for byte in window {
+      s1 = s1.wrapping_add(u16::from(*byte) + 31);
+      s2 = s2.wrapping_add(s1);
+    }
+
+    (s1, s2)
+  }
+
   fn adler32(data: &[u8]) -> u32 {
     let (a, b) = super::update(1, 0, data);
 
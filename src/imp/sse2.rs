@@ -1,15 +1,36 @@
-use super::Adler32Imp;
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp, ScanImp};
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "sse2")
+))]
+pub use self::imp::Sse2;
 
 /// Resolves update implementation if CPU supports sse2 instructions.
 pub fn get_imp() -> Option<Adler32Imp> {
-  get_imp_inner()
+  get_imp_inner().map(|(update, _, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports sse2 instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports sse2 instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks, _)| update_blocks)
+}
+
+/// Resolves window scan implementation if CPU supports sse2 instructions.
+pub fn get_scan_imp() -> Option<ScanImp> {
+  get_imp_inner().map(|(_, _, _, scan)| scan)
 }
 
 #[inline]
 #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
   if std::is_x86_feature_detected!("sse2") {
-    Some(imp::update)
+    Some((imp::update, imp::update_mod, imp::update_blocks, imp::scan))
   } else {
     None
   }
@@ -20,8 +41,8 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   target_feature = "sse2",
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
-  Some(imp::update)
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks, imp::scan))
 }
 
 #[inline]
@@ -29,7 +50,7 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   not(target_feature = "sse2"),
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
   None
 }
 
@@ -38,85 +59,248 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   any(feature = "std", target_feature = "sse2")
 ))]
 mod imp {
-  const MOD: u32 = 65521;
-  const NMAX: usize = 5552;
+  use crate::backend::Backend;
+  use crate::imp::{chunk_size, scalar, Modulus, HEAD_LEN, HEAD_WEIGHTS};
+
   const BLOCK_SIZE: usize = 32;
-  const CHUNK_SIZE: usize = NMAX / BLOCK_SIZE * BLOCK_SIZE;
 
   #[cfg(target_arch = "x86")]
   use core::arch::x86::*;
   #[cfg(target_arch = "x86_64")]
   use core::arch::x86_64::*;
 
+  #[inline(always)]
   pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
     unsafe { update_imp(a, b, data) }
   }
 
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  pub fn scan(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    unsafe { scan_imp(s1, s2, data, window, offset, mask) }
+  }
+
+  /// Statically dispatched sse2 backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Sse2(());
+
+  impl Backend for Sse2 {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Sse2(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
   #[inline]
   #[target_feature(enable = "sse2")]
   unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "sse2")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "sse2")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  /// Rolls the sums over eight windows at a time. The changes of `s1` and `s2` between
+  /// consecutive windows are computed per lane, their prefix sums give the sums of every
+  /// window. All arithmetic wraps in 16-bit lanes, like the scalar sums.
+  ///
+  /// The prefix sums don't depend on the running sums, so consecutive vectors overlap.
+  #[inline]
+  #[target_feature(enable = "sse2")]
+  unsafe fn scan_imp(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    let len = data.len().saturating_sub(window);
+
+    let zero_v = _mm_setzero_si128();
+    let weight_v = _mm_set1_epi16(window as i16);
+    let bias_v = _mm_set1_epi16((window as u16).wrapping_mul(offset) as i16);
+    let mask_s1_v = _mm_set1_epi16((mask >> 16) as i16);
+    let mask_s2_v = _mm_set1_epi16(mask as i16);
+
+    let lanes_v = _mm_set_epi16(8, 7, 6, 5, 4, 3, 2, 1);
+
+    let mut s1_v = _mm_set1_epi16(s1 as i16);
+    let mut s2_v = _mm_set1_epi16(s2 as i16);
+
+    let mut n = 0;
+    while n + 8 <= len {
+      let ptr = data.as_ptr().add(n);
+      let old_v = _mm_unpacklo_epi8(_mm_loadl_epi64(ptr as *const _), zero_v);
+      let new_v = _mm_unpacklo_epi8(_mm_loadl_epi64(ptr.add(window) as *const _), zero_v);
+
+      // Changes since the last window of the previous vector. The offset cancels out of
+      // `s1`, `s2` gains every `s1` and loses `window` times the old byte.
+      let removed_v = _mm_add_epi16(_mm_mullo_epi16(old_v, weight_v), bias_v);
+      let d1_v = prefix_sum(_mm_sub_epi16(new_v, old_v));
+      let d2_v = prefix_sum(_mm_sub_epi16(d1_v, removed_v));
+
+      let sums1_v = _mm_add_epi16(s1_v, d1_v);
+      let sums2_v =
+        _mm_add_epi16(_mm_add_epi16(s2_v, _mm_mullo_epi16(s1_v, lanes_v)), d2_v);
+
+      let hits_v = _mm_and_si128(
+        _mm_cmpeq_epi16(_mm_and_si128(sums1_v, mask_s1_v), mask_s1_v),
+        _mm_cmpeq_epi16(_mm_and_si128(sums2_v, mask_s2_v), mask_s2_v),
+      );
+      let hits = _mm_movemask_epi8(hits_v);
+      if hits != 0 {
+        return Some(n + hits.trailing_zeros() as usize / 2 + 1);
+      }
+
+      // Only these adds depend on the previous vector.
+      let carry1_v = broadcast_last(d1_v);
+      let carry2_v = broadcast_last(d2_v);
+      s2_v = _mm_add_epi16(_mm_add_epi16(s2_v, _mm_slli_epi16(s1_v, 3)), carry2_v);
+      s1_v = _mm_add_epi16(s1_v, carry1_v);
+      n += 8;
+    }
+
+    let s1 = _mm_cvtsi128_si32(s1_v) as u16;
+    let s2 = _mm_cvtsi128_si32(s2_v) as u16;
+
+    scalar::scan(s1, s2, &data[n..], window, offset, mask).map(|m| n + m)
+  }
+
+  /// Returns the inclusive prefix sums of the 16-bit lanes of `v`.
+  #[inline(always)]
+  unsafe fn prefix_sum(v: __m128i) -> __m128i {
+    let v = _mm_add_epi16(v, _mm_slli_si128(v, 2));
+    let v = _mm_add_epi16(v, _mm_slli_si128(v, 4));
+
+    _mm_add_epi16(v, _mm_slli_si128(v, 8))
+  }
+
+  /// Copies the last 16-bit lane of `v` to every lane.
+  #[inline(always)]
+  unsafe fn broadcast_last(v: __m128i) -> __m128i {
+    let v = _mm_shufflehi_epi16(v, crate::imp::_MM_SHUFFLE(3, 3, 3, 3));
+
+    _mm_shuffle_epi32(v, crate::imp::_MM_SHUFFLE(3, 3, 3, 3))
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
     let mut a = a as u32;
     let mut b = b as u32;
 
-    let chunks = data.chunks_exact(CHUNK_SIZE);
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
     let remainder = chunks.remainder();
+    let modulus = modulus.value();
     for chunk in chunks {
-      update_chunk_block(&mut a, &mut b, chunk);
+      update_chunk_block(&mut a, &mut b, chunk, modulus);
     }
 
-    update_block(&mut a, &mut b, remainder);
+    update_block(&mut a, &mut b, remainder, modulus);
 
     (a as u16, b as u16)
   }
 
-  unsafe fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  unsafe fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert_eq!(
       chunk.len(),
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
     reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
-  unsafe fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  unsafe fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert!(
-      chunk.len() <= CHUNK_SIZE,
+      chunk.len() <= chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected <= {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
-    for byte in reduce_add_blocks(a, b, chunk) {
-      *a += *byte as u32;
-      *b += *a;
-    }
+    reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline(always)]
-  unsafe fn reduce_add_blocks<'a>(a: &mut u32, b: &mut u32, chunk: &'a [u8]) -> &'a [u8] {
-    if chunk.len() < BLOCK_SIZE {
-      return chunk;
+  unsafe fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
     }
 
-    let blocks = chunk.chunks_exact(BLOCK_SIZE);
-    let blocks_remainder = blocks.remainder();
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
 
     let zero_v = _mm_setzero_si128();
     let weight_hi_v = get_weight_hi();
     let weight_lo_v = get_weight_lo();
 
-    let mut p_v = _mm_set_epi32(0, 0, 0, (*a * blocks.len() as u32) as _);
+    let mut p_v = _mm_setzero_si128();
     let mut a_v = _mm_setzero_si128();
-    let mut b_v = _mm_set_epi32(0, 0, 0, *b as _);
+    let mut b_v = _mm_set_epi32(0, 0, 0, (*b + *a * chunk.len() as u32) as _);
+
+    if head != 0 {
+      let weights = HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr() as *const _;
+      let head_hi_v = _mm_loadu_si128(weights);
+      let head_lo_v = _mm_loadu_si128(weights.add(1));
+
+      let block_ptr = chunk.as_ptr() as *const _;
+      let left_v = _mm_loadu_si128(block_ptr);
+      let right_v = _mm_loadu_si128(block_ptr.add(1));
+      let left_v = _mm_andnot_si128(_mm_cmpeq_epi8(head_hi_v, zero_v), left_v);
+      let right_v = _mm_andnot_si128(_mm_cmpeq_epi8(head_lo_v, zero_v), right_v);
+
+      a_v = _mm_add_epi32(_mm_sad_epu8(left_v, zero_v), _mm_sad_epu8(right_v, zero_v));
+      b_v = _mm_add_epi32(b_v, maddubs(left_v, head_hi_v));
+      b_v = _mm_add_epi32(b_v, maddubs(right_v, head_lo_v));
+    }
 
     for block in blocks {
       let block_ptr = block.as_ptr() as *const _;
@@ -138,8 +322,6 @@ mod imp {
 
     *a += reduce_add(a_v);
     *b = reduce_add(b_v);
-
-    blocks_remainder
   }
 
   #[inline(always)]
@@ -183,6 +365,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
   use rand::{Rng, SeedableRng, rngs::SmallRng};
+  use crate::imp::{scalar, Modulus};
 
   #[test]
   fn zeroes() {
@@ -221,6 +404,45 @@ mod tests {
     assert_sum_eq(&random[..1024 * 10]);
   }
 
+  #[test]
+  fn modulus() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    if super::get_blocks_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  #[test]
+  fn scan() {
+    if super::get_scan_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 4];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &mask in &[0, 0x0000_00ff, 0x0001_0fff, 0x7fff_ffff] {
+      for start in 0..32 {
+        assert_scan_eq(&random[start..], mask);
+      }
+    }
+    assert_scan_eq(&[0xff; 1024], 0);
+    assert_scan_eq(&random[..70], 0x7fff_ffff);
+  }
+
   /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
   #[test]
   fn wiki() {
@@ -236,4 +458,33 @@ mod tests {
       assert_eq!(left, right, "len({})", data.len());
     }
   }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+
+  fn assert_scan_eq(data: &[u8], mask: u32) {
+    if let Some(scan) = super::get_scan_imp() {
+      let left = scan(0x1234, 0xfedc, data, 64, 31, mask);
+      let right = scalar::scan(0x1234, 0xfedc, data, 64, 31, mask);
+
+      assert_eq!(left, right, "len({}), mask({:#x})", data.len(), mask);
+    }
+  }
 }
@@ -1,15 +1,31 @@
-use super::Adler32Imp;
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp};
+
+#[cfg(all(
+  any(target_arch = "x86", target_arch = "x86_64"),
+  any(feature = "std", target_feature = "ssse3")
+))]
+pub use self::imp::Ssse3;
 
 /// Resolves update implementation if CPU supports ssse3 instructions.
 pub fn get_imp() -> Option<Adler32Imp> {
-  get_imp_inner()
+  get_imp_inner().map(|(update, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports ssse3 instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports ssse3 instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks)| update_blocks)
 }
 
 #[inline]
 #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
   if std::is_x86_feature_detected!("ssse3") {
-    Some(imp::update)
+    Some((imp::update, imp::update_mod, imp::update_blocks))
   } else {
     None
   }
@@ -20,8 +36,8 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   target_feature = "ssse3",
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
-  Some(imp::update)
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks))
 }
 
 #[inline]
@@ -29,7 +45,7 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   not(target_feature = "ssse3"),
   not(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp)> {
   None
 }
 
@@ -38,86 +54,159 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   any(feature = "std", target_feature = "ssse3")
 ))]
 mod imp {
-  const MOD: u32 = 65521;
-  const NMAX: usize = 5552;
+  use crate::backend::Backend;
+  use crate::imp::{chunk_size, Modulus, HEAD_LEN, HEAD_WEIGHTS};
+
   const BLOCK_SIZE: usize = 32;
-  const CHUNK_SIZE: usize = NMAX / BLOCK_SIZE * BLOCK_SIZE;
 
   #[cfg(target_arch = "x86")]
   use core::arch::x86::*;
   #[cfg(target_arch = "x86_64")]
   use core::arch::x86_64::*;
 
+  #[inline(always)]
   pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
     unsafe { update_imp(a, b, data) }
   }
 
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    unsafe { update_mod_imp(a, b, data, modulus) }
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    unsafe { update_blocks_imp(data, block_size, out) }
+  }
+
+  /// Statically dispatched ssse3 backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Ssse3(());
+
+  impl Backend for Ssse3 {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Ssse3(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
   #[inline]
   #[target_feature(enable = "ssse3")]
   unsafe fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "ssse3")]
+  unsafe fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "ssse3")]
+  unsafe fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  #[inline(always)]
+  unsafe fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
     let mut a = a as u32;
     let mut b = b as u32;
 
-    let chunks = data.chunks_exact(CHUNK_SIZE);
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
     let remainder = chunks.remainder();
+    let modulus = modulus.value();
     for chunk in chunks {
-      update_chunk_block(&mut a, &mut b, chunk);
+      update_chunk_block(&mut a, &mut b, chunk, modulus);
     }
 
-    update_block(&mut a, &mut b, remainder);
+    update_block(&mut a, &mut b, remainder, modulus);
 
     (a as u16, b as u16)
   }
 
-  unsafe fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  #[target_feature(enable = "ssse3")]
+  unsafe fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert_eq!(
       chunk.len(),
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
     reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
-  unsafe fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  #[target_feature(enable = "ssse3")]
+  unsafe fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert!(
-      chunk.len() <= CHUNK_SIZE,
+      chunk.len() <= chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected <= {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
-    for byte in reduce_add_blocks(a, b, chunk) {
-      *a += *byte as u32;
-      *b += *a;
-    }
+    reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline(always)]
-  unsafe fn reduce_add_blocks<'a>(a: &mut u32, b: &mut u32, chunk: &'a [u8]) -> &'a [u8] {
-    if chunk.len() < BLOCK_SIZE {
-      return chunk;
+  unsafe fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
     }
 
-    let blocks = chunk.chunks_exact(BLOCK_SIZE);
-    let blocks_remainder = blocks.remainder();
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
 
     let one_v = _mm_set1_epi16(1);
     let zero_v = _mm_set1_epi16(0);
     let weight_hi_v = get_weight_hi();
     let weight_lo_v = get_weight_lo();
 
-    let mut p_v = _mm_set_epi32(0, 0, 0, (*a * blocks.len() as u32) as _);
+    let mut p_v = _mm_set_epi32(0, 0, 0, 0);
     let mut a_v = _mm_set_epi32(0, 0, 0, 0);
-    let mut b_v = _mm_set_epi32(0, 0, 0, *b as _);
+    let mut b_v = _mm_set_epi32(0, 0, 0, (*b + *a * chunk.len() as u32) as _);
+
+    if head != 0 {
+      let weights = HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr() as *const _;
+      let head_hi_v = _mm_loadu_si128(weights);
+      let head_lo_v = _mm_loadu_si128(weights.add(1));
+
+      let block_ptr = chunk.as_ptr() as *const _;
+      let left_v = _mm_loadu_si128(block_ptr);
+      let right_v = _mm_loadu_si128(block_ptr.add(1));
+      let left_v = _mm_andnot_si128(_mm_cmpeq_epi8(head_hi_v, zero_v), left_v);
+      let right_v = _mm_andnot_si128(_mm_cmpeq_epi8(head_lo_v, zero_v), right_v);
+
+      a_v = _mm_add_epi32(_mm_sad_epu8(left_v, zero_v), _mm_sad_epu8(right_v, zero_v));
+      let mad = _mm_add_epi16(
+        _mm_maddubs_epi16(left_v, head_hi_v),
+        _mm_maddubs_epi16(right_v, head_lo_v),
+      );
+      b_v = _mm_add_epi32(b_v, _mm_madd_epi16(mad, one_v));
+    }
 
     for block in blocks {
       let block_ptr = block.as_ptr() as *const _;
@@ -139,8 +228,6 @@ mod imp {
 
     *a += reduce_add(a_v);
     *b = reduce_add(b_v);
-
-    blocks_remainder
   }
 
   #[inline(always)]
@@ -169,6 +256,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
   use rand::{Rng, SeedableRng, rngs::SmallRng};
+  use crate::imp::{scalar, Modulus};
 
   #[test]
   fn zeroes() {
@@ -207,6 +295,30 @@ mod tests {
     assert_sum_eq(&random[..1024 * 10]);
   }
 
+  #[test]
+  fn modulus() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    if super::get_blocks_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
   /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
   #[test]
   fn wiki() {
@@ -222,4 +334,24 @@ mod tests {
       assert_eq!(left, right, "len({})", data.len());
     }
   }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
 }
@@ -1,8 +1,32 @@
-use super::Adler32Imp;
+use super::{Adler32BlocksImp, Adler32Imp, AdlerModImp, ScanImp};
+
+#[cfg(all(
+  target_feature = "simd128",
+  any(
+    target_arch = "wasm32",
+    all(feature = "nightly", target_arch = "wasm64")
+  )
+))]
+pub use self::imp::Wasm;
 
 /// Resolves update implementation if CPU supports simd128 instructions.
 pub fn get_imp() -> Option<Adler32Imp> {
-  get_imp_inner()
+  get_imp_inner().map(|(update, _, _, _)| update)
+}
+
+/// Resolves modular update implementation if CPU supports simd128 instructions.
+pub fn get_mod_imp() -> Option<AdlerModImp> {
+  get_imp_inner().map(|(_, update_mod, _, _)| update_mod)
+}
+
+/// Resolves block checksum implementation if CPU supports simd128 instructions.
+pub fn get_blocks_imp() -> Option<Adler32BlocksImp> {
+  get_imp_inner().map(|(_, _, update_blocks, _)| update_blocks)
+}
+
+/// Resolves window scan implementation if CPU supports simd128 instructions.
+pub fn get_scan_imp() -> Option<ScanImp> {
+  get_imp_inner().map(|(_, _, _, scan)| scan)
 }
 
 #[inline]
@@ -13,8 +37,8 @@ pub fn get_imp() -> Option<Adler32Imp> {
     all(feature = "nightly", target_arch = "wasm64")
   )
 ))]
-fn get_imp_inner() -> Option<Adler32Imp> {
-  Some(imp::update)
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
+  Some((imp::update, imp::update_mod, imp::update_blocks, imp::scan))
 }
 
 #[inline]
@@ -25,7 +49,7 @@ fn get_imp_inner() -> Option<Adler32Imp> {
     all(feature = "nightly", target_arch = "wasm64")
   )
 )))]
-fn get_imp_inner() -> Option<Adler32Imp> {
+fn get_imp_inner() -> Option<(Adler32Imp, AdlerModImp, Adler32BlocksImp, ScanImp)> {
   None
 }
 
@@ -37,84 +61,235 @@ fn get_imp_inner() -> Option<Adler32Imp> {
   )
 ))]
 mod imp {
-  const MOD: u32 = 65521;
-  const NMAX: usize = 5552;
+  use crate::backend::Backend;
+  use crate::imp::{chunk_size, scalar, Modulus, HEAD_LEN, HEAD_WEIGHTS};
+
   const BLOCK_SIZE: usize = 32;
-  const CHUNK_SIZE: usize = NMAX / BLOCK_SIZE * BLOCK_SIZE;
 
   #[cfg(target_arch = "wasm32")]
   use core::arch::wasm32::*;
   #[cfg(target_arch = "wasm64")]
   use core::arch::wasm64::*;
 
+  #[inline(always)]
   pub fn update(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
     update_imp(a, b, data)
   }
 
+  pub fn update_mod(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_mod_imp(a, b, data, modulus)
+  }
+
+  pub fn update_blocks(data: &[u8], block_size: usize, out: &mut [u32]) {
+    update_blocks_imp(data, block_size, out)
+  }
+
+  pub fn scan(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    scan_imp(s1, s2, data, window, offset, mask)
+  }
+
+  /// Statically dispatched wasm simd128 backend, see [`Backend`].
+  #[derive(Clone, Copy, Debug)]
+  pub struct Wasm(());
+
+  impl Backend for Wasm {
+    #[inline]
+    fn detect() -> Option<Self> {
+      super::get_imp().map(|_| Wasm(()))
+    }
+
+    #[inline(always)]
+    fn update(self, a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+      update(a, b, data)
+    }
+  }
+
   #[inline]
   #[target_feature(enable = "simd128")]
   fn update_imp(a: u16, b: u16, data: &[u8]) -> (u16, u16) {
+    update_chunks(a, b, data, Modulus::ADLER32)
+  }
+
+  #[inline]
+  #[target_feature(enable = "simd128")]
+  fn update_mod_imp(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
+    update_chunks(a, b, data, modulus)
+  }
+
+  #[inline]
+  #[target_feature(enable = "simd128")]
+  fn update_blocks_imp(data: &[u8], block_size: usize, out: &mut [u32]) {
+    for (block, out) in data.chunks(block_size).zip(out) {
+      let (a, b) = update_chunks(1, 0, block, Modulus::ADLER32);
+
+      *out = (u32::from(b) << 16) | u32::from(a);
+    }
+  }
+
+  /// Rolls the sums over eight windows at a time, see the sse2 implementation.
+  #[inline]
+  #[target_feature(enable = "simd128")]
+  fn scan_imp(
+    s1: u16,
+    s2: u16,
+    data: &[u8],
+    window: usize,
+    offset: u16,
+    mask: u32,
+  ) -> Option<usize> {
+    let len = data.len().saturating_sub(window);
+
+    let weight_v = u16x8_splat(window as u16);
+    let bias_v = u16x8_splat((window as u16).wrapping_mul(offset));
+    let mask_s1_v = u16x8_splat((mask >> 16) as u16);
+    let mask_s2_v = u16x8_splat(mask as u16);
+
+    let lanes_v = u16x8(1, 2, 3, 4, 5, 6, 7, 8);
+
+    let mut s1_v = u16x8_splat(s1);
+    let mut s2_v = u16x8_splat(s2);
+
+    let mut n = 0;
+    while n + 8 <= len {
+      let (old_v, new_v) = unsafe {
+        let ptr = data.as_ptr().add(n);
+        (u16x8_load_extend_u8x8(ptr), u16x8_load_extend_u8x8(ptr.add(window)))
+      };
+
+      let removed_v = u16x8_add(u16x8_mul(old_v, weight_v), bias_v);
+      let d1_v = prefix_sum(u16x8_sub(new_v, old_v));
+      let d2_v = prefix_sum(u16x8_sub(d1_v, removed_v));
+
+      let sums1_v = u16x8_add(s1_v, d1_v);
+      let sums2_v = u16x8_add(u16x8_add(s2_v, u16x8_mul(s1_v, lanes_v)), d2_v);
+
+      let hits_v = v128_and(
+        u16x8_eq(v128_and(sums1_v, mask_s1_v), mask_s1_v),
+        u16x8_eq(v128_and(sums2_v, mask_s2_v), mask_s2_v),
+      );
+      let hits = u16x8_bitmask(hits_v);
+      if hits != 0 {
+        return Some(n + hits.trailing_zeros() as usize + 1);
+      }
+
+      let carry1_v = u16x8_splat(u16x8_extract_lane::<7>(d1_v));
+      let carry2_v = u16x8_splat(u16x8_extract_lane::<7>(d2_v));
+      s2_v = u16x8_add(u16x8_add(s2_v, u16x8_shl(s1_v, 3)), carry2_v);
+      s1_v = u16x8_add(s1_v, carry1_v);
+      n += 8;
+    }
+
+    let s1 = u16x8_extract_lane::<0>(s1_v);
+    let s2 = u16x8_extract_lane::<0>(s2_v);
+
+    scalar::scan(s1, s2, &data[n..], window, offset, mask).map(|m| n + m)
+  }
+
+  /// Returns the inclusive prefix sums of the 16-bit lanes of `v`.
+  #[inline(always)]
+  fn prefix_sum(v: v128) -> v128 {
+    let zero_v = u16x8_splat(0);
+    let v = u16x8_add(v, u16x8_shuffle::<0, 8, 9, 10, 11, 12, 13, 14>(zero_v, v));
+    let v = u16x8_add(v, u16x8_shuffle::<0, 0, 8, 9, 10, 11, 12, 13>(zero_v, v));
+
+    u16x8_add(v, u16x8_shuffle::<0, 0, 0, 0, 8, 9, 10, 11>(zero_v, v))
+  }
+
+  #[inline(always)]
+  fn update_chunks(a: u16, b: u16, data: &[u8], modulus: Modulus) -> (u16, u16) {
     let mut a = a as u32;
     let mut b = b as u32;
 
-    let chunks = data.chunks_exact(CHUNK_SIZE);
+    let chunks = data.chunks_exact(modulus.chunk_size(BLOCK_SIZE));
     let remainder = chunks.remainder();
+    let modulus = modulus.value();
     for chunk in chunks {
-      update_chunk_block(&mut a, &mut b, chunk);
+      update_chunk_block(&mut a, &mut b, chunk, modulus);
     }
 
-    update_block(&mut a, &mut b, remainder);
+    update_block(&mut a, &mut b, remainder, modulus);
 
     (a as u16, b as u16)
   }
 
-  fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  fn update_chunk_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert_eq!(
       chunk.len(),
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
     reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
-  fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8]) {
+  fn update_block(a: &mut u32, b: &mut u32, chunk: &[u8], modulus: u32) {
     debug_assert!(
-      chunk.len() <= CHUNK_SIZE,
+      chunk.len() <= chunk_size(modulus, BLOCK_SIZE),
       "Unexpected chunk size (expected <= {}, got {})",
-      CHUNK_SIZE,
+      chunk_size(modulus, BLOCK_SIZE),
       chunk.len()
     );
 
-    for byte in reduce_add_blocks(a, b, chunk) {
-      *a += *byte as u32;
-      *b += *a;
-    }
+    reduce_add_blocks(a, b, chunk);
 
-    *a %= MOD;
-    *b %= MOD;
+    *a %= modulus;
+    *b %= modulus;
   }
 
   #[inline(always)]
-  fn reduce_add_blocks<'a>(a: &mut u32, b: &mut u32, chunk: &'a [u8]) -> &'a [u8] {
-    if chunk.len() < BLOCK_SIZE {
-      return chunk;
+  fn reduce_add_blocks(a: &mut u32, b: &mut u32, mut chunk: &[u8]) {
+    // A partial block at the start is hashed as a masked vector if it's long enough to
+    // beat hashing it one byte at a time.
+    let mut head = chunk.len() % BLOCK_SIZE;
+    if head < HEAD_LEN || chunk.len() < BLOCK_SIZE {
+      for &byte in &chunk[..head] {
+        *a += u32::from(byte);
+        *b += *a;
+      }
+
+      chunk = &chunk[head..];
+      head = 0;
     }
 
-    let blocks = chunk.chunks_exact(BLOCK_SIZE);
-    let blocks_remainder = blocks.remainder();
+    let blocks = chunk[head..].chunks_exact(BLOCK_SIZE);
 
     let weight_hi_v = get_weight_hi();
     let weight_lo_v = get_weight_lo();
 
-    let mut p_v = u32x4(*a * blocks.len() as u32, 0, 0, 0);
+    let mut p_v = u32x4(0, 0, 0, 0);
     let mut a_v = u32x4(0, 0, 0, 0);
-    let mut b_v = u32x4(*b, 0, 0, 0);
+    let mut b_v = u32x4(*b + *a * chunk.len() as u32, 0, 0, 0);
+    let weight_sum = blocks.len() * (BLOCK_SIZE * (BLOCK_SIZE + 1) / 2);
+    let bias = (weight_sum + head * (head + 1) / 2) as u32 * DOT_BIAS;
+
+    if head != 0 {
+      let weights = HEAD_WEIGHTS[BLOCK_SIZE - head..].as_ptr() as *const v128;
+      let head_lo_v = unsafe { weights.read_unaligned() };
+      let head_hi_v = unsafe { weights.add(1).read_unaligned() };
+
+      let block_ptr = chunk.as_ptr() as *const v128;
+      let v_lo = unsafe { block_ptr.read_unaligned() };
+      let v_hi = unsafe { block_ptr.add(1).read_unaligned() };
+      let v_lo = v128_and(v_lo, u8x16_ne(head_lo_v, u8x16_splat(0)));
+      let v_hi = v128_and(v_hi, u8x16_ne(head_hi_v, u8x16_splat(0)));
+
+      a_v = u32x4_extadd_quarters_u8x16(v_lo);
+      a_v = u32x4_add(a_v, u32x4_extadd_quarters_u8x16(v_hi));
+      b_v = i32x4_dot_i8x16_add(v_lo, head_lo_v, b_v);
+      b_v = i32x4_dot_i8x16_add(v_hi, head_hi_v, b_v);
+    }
 
     for block in blocks {
       let block_ptr = block.as_ptr() as *const v128;
@@ -124,23 +299,47 @@ mod imp {
       p_v = u32x4_add(p_v, a_v);
 
       a_v = u32x4_add(a_v, u32x4_extadd_quarters_u8x16(v_lo));
-      let mad = i32x4_dot_i8x16(v_lo, weight_lo_v);
-      b_v = u32x4_add(b_v, mad);
+      b_v = i32x4_dot_i8x16_add(v_lo, weight_lo_v, b_v);
 
       a_v = u32x4_add(a_v, u32x4_extadd_quarters_u8x16(v_hi));
-      let mad = i32x4_dot_i8x16(v_hi, weight_hi_v);
-      b_v = u32x4_add(b_v, mad);
+      b_v = i32x4_dot_i8x16_add(v_hi, weight_hi_v, b_v);
     }
 
     b_v = u32x4_add(b_v, u32x4_shl(p_v, 5));
 
     *a += reduce_add(a_v);
-    *b = reduce_add(b_v);
+    *b = reduce_add(b_v).wrapping_add(bias);
+  }
+
+  /// Amount [`i32x4_dot_i8x16_add`] falls short of the weighted sum of the bytes by, per
+  /// unit of weight.
+  #[cfg(not(target_feature = "relaxed-simd"))]
+  const DOT_BIAS: u32 = 0;
+
+  /// Amount [`i32x4_dot_i8x16_add`] falls short of the weighted sum of the bytes by, per
+  /// unit of weight.
+  #[cfg(target_feature = "relaxed-simd")]
+  const DOT_BIAS: u32 = 128;
+
+  #[inline(always)]
+  #[cfg(not(target_feature = "relaxed-simd"))]
+  fn i32x4_dot_i8x16_add(a: v128, b: v128, c: v128) -> v128 {
+    u32x4_add(c, i32x4_dot_i8x16(a, b))
+  }
 
-    blocks_remainder
+  /// Adds the dot product of the bytes `a` and weights `b` to `c`.
+  ///
+  /// The relaxed dot product treats `a` as signed, so bytes are biased into `-128..128`
+  /// by flipping the top bit and [`DOT_BIAS`] is added back per unit of weight. Weights
+  /// fit in seven bits, where every implementation agrees on the result.
+  #[inline(always)]
+  #[cfg(target_feature = "relaxed-simd")]
+  fn i32x4_dot_i8x16_add(a: v128, b: v128, c: v128) -> v128 {
+    i32x4_relaxed_dot_i8x16_i7x16_add(v128_xor(a, u8x16_splat(0x80)), b, c)
   }
 
   #[inline(always)]
+  #[cfg(not(target_feature = "relaxed-simd"))]
   fn i32x4_dot_i8x16(a: v128, b: v128) -> v128 {
     let a_lo = u16x8_extend_low_u8x16(a);
     let a_hi = u16x8_extend_high_u8x16(a);
@@ -185,6 +384,7 @@ mod imp {
 #[cfg(test)]
 mod tests {
   use rand::{Rng, SeedableRng, rngs::SmallRng};
+  use crate::imp::{scalar, Modulus};
 
   #[test]
   fn zeroes() {
@@ -218,6 +418,45 @@ mod tests {
     assert_sum_eq(&random[..512 * 1024]);
   }
 
+  #[test]
+  fn modulus() {
+    if super::get_mod_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 512 * 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_mod_sum_eq(&[0xff; 1024 * 16], 65536);
+    assert_mod_sum_eq(&random[..1024 - 5], 65536);
+    assert_mod_sum_eq(&random[..], 65536);
+    assert_mod_sum_eq(&random[..], 65521);
+    assert_mod_sum_eq(&random[..], 251);
+  }
+
+  #[test]
+  fn blocks() {
+    if super::get_blocks_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 10];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_blocks_eq(&random[..], 4096);
+    assert_blocks_eq(&random[..1024 - 5], 100);
+    assert_blocks_eq(&[0xff; 1024 * 16], 5553);
+  }
+
+  #[test]
+  fn scan() {
+    if super::get_scan_imp().is_none() { return; } // don't do any work if we're not on this target
+    let mut random = [0; 1024 * 4];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &mask in &[0, 0x0000_00ff, 0x0001_0fff, 0x7fff_ffff] {
+      for start in 0..32 {
+        assert_scan_eq(&random[start..], mask);
+      }
+    }
+    assert_scan_eq(&[0xff; 1024], 0);
+    assert_scan_eq(&random[..70], 0x7fff_ffff);
+  }
+
   /// Example calculation from https://en.wikipedia.org/wiki/Adler-32.
   #[test]
   fn wiki() {
@@ -233,4 +472,33 @@ mod tests {
       assert_eq!(left, right, "len({})", data.len());
     }
   }
+
+  fn assert_mod_sum_eq(data: &[u8], modulus: u32) {
+    if let Some(update) = super::get_mod_imp() {
+      let left = update(1, 0, data, Modulus::new(modulus));
+      let right = scalar::update_mod(1, 0, data, Modulus::new(modulus));
+
+      assert_eq!(left, right, "len({}), modulus({})", data.len(), modulus);
+    }
+  }
+
+  fn assert_blocks_eq(data: &[u8], block_size: usize) {
+    if let Some(update_blocks) = super::get_blocks_imp() {
+      let mut out = [0; 128];
+      update_blocks(data, block_size, &mut out);
+
+      for (i, block) in data.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block({})", i);
+      }
+    }
+  }
+
+  fn assert_scan_eq(data: &[u8], mask: u32) {
+    if let Some(scan) = super::get_scan_imp() {
+      let left = scan(0x1234, 0xfedc, data, 64, 31, mask);
+      let right = scalar::scan(0x1234, 0xfedc, data, 64, 31, mask);
+
+      assert_eq!(left, right, "len({}), mask({:#x})", data.len(), mask);
+    }
+  }
 }
@@ -40,12 +40,23 @@
 //! detection support.
 //! * `nightly`
 //!
-//! Enables nightly features required for avx512 support.
+//! Enables nightly features required for avx512, LoongArch, PowerPC and s390x support.
 //!
 //! * `const-generics` - Enabled by default
 //!
 //! Enables const-generics support allowing for user-defined array hashing by value.  See
-//! [`Adler32Hash`] for details.
+//! [`Adler32Hash`] for details. Also enables [`AdlerMod`] and [`AdlerMod64`] for Adler-style
+//! sums over other moduli, `Adler32` then becomes an alias of `AdlerMod<65521>`.
+//!
+//! * `cli`
+//!
+//! Builds the `adler32sum` binary, a `sha256sum` style tool printing and checking Adler-32
+//! checksums. Needs rustc `1.63` or newer.
+//!
+//! * `capi`
+//!
+//! Exports zlib compatible `simd_adler32`, `simd_adler32_z`, `simd_adler32_combine` and
+//! `simd_adler32_combine64` C functions, see [`capi`]. Needs rustc `1.64` or newer.
 //!
 //! ## Support
 //!
@@ -59,6 +70,13 @@
 //! | ✅   | `x86`, `x86_64`  | sse2    |
 //! | 🚧   | `arm`, `aarch64` | neon    |
 //! |      | `wasm32`         | simd128 |
+//! |      | `wasm32`         | relaxed-simd |
+//! | ✅   | `riscv64`        | v       |
+//! | ✅   | `loongarch64`    | lasx    |
+//! | ✅   | `loongarch64`    | lsx     |
+//! | ✅   | `powerpc64`      | vsx     |
+//! | ✅   | `s390x`          | vector  |
+//! | 🚧   | any              | portable `core::simd` |
 //!
 //! **MSRV** `1.36.0`\*\*
 //!
@@ -76,6 +94,8 @@
 //! for more information.
 //!
 //! Feature detection tries to use the fastest supported feature first.
+//!
+//! To pick the implementation at compile time instead, see [`backend::Adler32With`].
 #![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(
   all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")),
@@ -85,6 +105,20 @@
   all(feature = "nightly", target_arch = "arm"),
   feature(stdarch_arm_neon_intrinsics)
 )]
+#![cfg_attr(
+  all(feature = "nightly", target_arch = "loongarch64"),
+  feature(stdarch_loongarch)
+)]
+#![cfg_attr(
+  all(feature = "nightly", target_arch = "powerpc64"),
+  feature(stdarch_powerpc, powerpc_target_feature)
+)]
+#![cfg_attr(
+  all(feature = "std", feature = "nightly", target_arch = "powerpc64"),
+  feature(stdarch_powerpc_feature_detection)
+)]
+#![cfg_attr(all(feature = "nightly", target_arch = "s390x"), feature(stdarch_s390x))]
+#![cfg_attr(feature = "nightly", feature(portable_simd))]
 #![cfg_attr(
   all(
     feature = "nightly",
@@ -94,15 +128,43 @@
   feature(simd_wasm64)
 )]
 
+pub mod backend;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod chunker;
+#[cfg(feature = "std")]
+pub mod delta;
+#[cfg(feature = "std")]
+pub mod diff;
+pub mod fletcher;
+pub mod fletcher4;
+pub mod framing;
 #[doc(hidden)]
 pub mod hash;
 #[doc(hidden)]
 pub mod imp;
+#[cfg(feature = "std")]
+pub mod manifest;
+pub mod rsync;
+#[cfg(feature = "std")]
+pub mod search;
+pub mod zlib;
+
+#[cfg(feature = "const-generics")]
+mod modular;
 
-pub use hash::*;
+#[cfg(not(feature = "const-generics"))]
 use imp::{get_imp, Adler32Imp};
+#[cfg(feature = "const-generics")]
+pub use modular::{Adler64, AdlerMod, AdlerMod64};
+pub use rsync::{Rollsum, RsyncChecksum};
+
+/// An adler32 hash generator type.
+#[cfg(feature = "const-generics")]
+pub type Adler32 = AdlerMod<65521>;
 
 /// An adler32 hash generator type.
+#[cfg(not(feature = "const-generics"))]
 #[derive(Clone)]
 pub struct Adler32 {
   a: u16,
@@ -110,6 +172,7 @@ pub struct Adler32 {
   update: Adler32Imp,
 }
 
+#[cfg(not(feature = "const-generics"))]
 impl Adler32 {
   /// Constructs a new `Adler32`.
   ///
@@ -147,7 +210,11 @@ impl Adler32 {
 
   /// Computes hash for supplied data and stores results in internal state.
   pub fn write(&mut self, data: &[u8]) {
-    let (a, b) = (self.update)(self.a, self.b, data);
+    let (a, b) = if data.len() < imp::SHORT_LEN {
+      imp::scalar::update_short(self.a, self.b, data, imp::MOD)
+    } else {
+      (self.update)(self.a, self.b, data)
+    };
 
     self.a = a;
     self.b = b;
@@ -185,12 +252,54 @@ pub fn adler32<H: Adler32Hash>(hash: &H) -> u32 {
   hash.hash()
 }
 
+/// Compute the Adler-32 of every `block_size` byte block of `data` into `out`.
+///
+/// The last block may be short. Equivalent to calling [`adler32`] on every block of
+/// `data.chunks(block_size)` but resolves the implementation once for all blocks.
+///
+/// # Panics
+/// Panics if `block_size` is zero or `out` is shorter than the number of blocks.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::{adler32, block_checksums};
+///
+/// let data = [0x2a; 10000];
+/// let mut out = [0; 3];
+/// block_checksums(&data, 4096, &mut out);
+///
+/// assert_eq!(out[2], adler32(&&data[8192..]));
+/// ```
+pub fn block_checksums(data: &[u8], block_size: usize, out: &mut [u32]) {
+  assert!(block_size > 0, "Block size must not be zero");
+
+  let blocks = data.chunks(block_size).len();
+  assert!(out.len() >= blocks, "Output is shorter than the number of blocks");
+
+  imp::get_blocks_imp()(data, block_size, &mut out[..blocks]);
+}
+
+/// Compute the Adler-32 of two concatenated inputs from their checksums, `len` is the
+/// length of the second input.
+#[cfg(any(feature = "std", feature = "capi"))]
+pub(crate) fn adler32_combine(first: u32, second: u32, len: u64) -> u32 {
+  let rem = (len % u64::from(imp::MOD)) as u32;
+  let (first_a, first_b) = ((first & 0xffff) % imp::MOD, (first >> 16) % imp::MOD);
+  let (second_a, second_b) = ((second & 0xffff) % imp::MOD, (second >> 16) % imp::MOD);
+
+  let a = (first_a + second_a + imp::MOD - 1) % imp::MOD;
+  let b = (rem * first_a % imp::MOD + first_b + second_b + imp::MOD - rem) % imp::MOD;
+
+  (b << 16) | a
+}
+
 /// A Adler-32 hash-able type.
 pub trait Adler32Hash {
   /// Feeds this value into `Adler32`.
   fn hash(&self) -> u32;
 }
 
+#[cfg(not(feature = "const-generics"))]
 impl Default for Adler32 {
   fn default() -> Self {
     Self {
@@ -306,6 +415,57 @@ pub mod bufread {
 
 #[cfg(test)]
 mod tests {
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn test_block_checksums() {
+    let mut random = [0; 1024 * 20];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &block_size in &[1, 100, 4096, 5553, 1024 * 20, 1024 * 32] {
+      let mut out = [0; 1024 * 20];
+      super::block_checksums(&random, block_size, &mut out);
+
+      for (i, block) in random.chunks(block_size).enumerate() {
+        assert_eq!(out[i], adler2::adler32_slice(block), "block_size({})", block_size);
+      }
+    }
+  }
+
+  #[test]
+  fn test_short() {
+    let mut random = [0; 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut hash = super::Adler32::new();
+    for len in 0..random.len() {
+      let data = &random[..len];
+      assert_eq!(super::adler32(&data), adler2::adler32_slice(data), "len({})", len);
+
+      hash.write(&random[len..len + 1]);
+      let expected = adler2::adler32_slice(&random[..len + 1]);
+      assert_eq!(hash.finish(), expected, "len({})", len);
+    }
+  }
+
+  #[test]
+  #[cfg(any(feature = "std", feature = "capi"))]
+  fn test_adler32_combine() {
+    let mut random = [0; 1024 * 20];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &split in &[0, 1, 100, 5552, 1024 * 20] {
+      let (first, second) = random.split_at(split);
+      let combined = super::adler32_combine(
+        adler2::adler32_slice(first),
+        adler2::adler32_slice(second),
+        second.len() as u64,
+      );
+
+      assert_eq!(combined, adler2::adler32_slice(&random), "split({})", split);
+    }
+  }
+
   #[test]
   fn test_from_checksum() {
     let buf = b"rust is pretty cool man";
@@ -0,0 +1,674 @@
+//! Checksum list files and sidecars.
+//!
+//! Lists use the line formats of GNU coreutils (`11e60398  file`) and BSD tags
+//! (`ADLER32 (file) = 11e60398`). Names containing a backslash or newline are escaped
+//! the way coreutils does, with a leading backslash on the line. Paths are kept as raw
+//! bytes on unix so names that are not UTF-8 survive a round trip.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::manifest::{Manifest, Style};
+//!
+//! let mut manifest = Manifest::new();
+//! manifest.push("a.txt", 0x11e6_0398);
+//!
+//! let mut list = Vec::new();
+//! manifest.write_to(&mut list, Style::Bsd).unwrap();
+//! assert_eq!(list, b"ADLER32 (a.txt) = 11e60398\n");
+//!
+//! assert_eq!(Manifest::read_from(&mut &list[..]).unwrap(), manifest);
+//! ```
+use crate::read::adler32;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Extension of sidecar files.
+pub const SIDECAR_EXTENSION: &str = "adler32";
+
+/// Line format of a checksum list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+  /// GNU coreutils format, `11e60398  file`.
+  Gnu,
+  /// BSD tag format, `ADLER32 (file) = 11e60398`.
+  Bsd,
+}
+
+/// A checksum list entry.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entry {
+  /// Adler-32 checksum of the file.
+  pub checksum: u32,
+  /// Path of the file.
+  pub path: PathBuf,
+}
+
+impl Entry {
+  /// Constructs a new `Entry`.
+  pub fn new<P: Into<PathBuf>>(path: P, checksum: u32) -> Self {
+    Self {
+      checksum,
+      path: path.into(),
+    }
+  }
+
+  /// Parses a line in either style without its terminator, returns `None` if it is not
+  /// a checksum line.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::manifest::Entry;
+  ///
+  /// let entry = Entry::parse(b"11e60398 *file").unwrap();
+  /// assert_eq!(entry, Entry::new("file", 0x11e6_0398));
+  /// ```
+  pub fn parse(line: &[u8]) -> Option<Self> {
+    let line = strip_suffix(line, b"\r");
+    let (escaped, line) = match line.split_first() {
+      Some((b'\\', line)) => (true, line),
+      _ => (false, line),
+    };
+
+    let (hex, name) = if line.starts_with(b"ADLER32 (") {
+      let rest = &line[9..];
+      let split = rest.windows(4).rposition(|window| window == b") = ")?;
+
+      (&rest[split + 4..], &rest[..split])
+    } else {
+      if line.len() < 11 || line[8] != b' ' || (line[9] != b' ' && line[9] != b'*') {
+        return None;
+      }
+
+      (&line[..8], &line[10..])
+    };
+
+    let checksum = parse_hex(hex)?;
+    if name.is_empty() {
+      return None;
+    }
+
+    let name = if escaped {
+      unescape(name)?
+    } else {
+      name.to_vec()
+    };
+
+    Some(Self::new(path_from_bytes(name), checksum))
+  }
+
+  /// Formats the entry as a line in `style` without its terminator.
+  ///
+  /// Names are escaped if `escape` is set, lists terminated by NUL are not escaped.
+  pub fn to_line(&self, style: Style, escape: bool) -> Vec<u8> {
+    let name = path_bytes(&self.path);
+    let escaped = if escape { escape_name(&name) } else { None };
+
+    let mut line = Vec::with_capacity(name.len() + 24);
+    if escaped.is_some() {
+      line.push(b'\\');
+    }
+
+    let name = match escaped {
+      Some(ref escaped) => &escaped[..],
+      None => &name[..],
+    };
+    let hex = format!("{:08x}", self.checksum);
+
+    match style {
+      Style::Gnu => {
+        line.extend_from_slice(hex.as_bytes());
+        line.extend_from_slice(b"  ");
+        line.extend_from_slice(name);
+      }
+      Style::Bsd => {
+        line.extend_from_slice(b"ADLER32 (");
+        line.extend_from_slice(name);
+        line.extend_from_slice(b") = ");
+        line.extend_from_slice(hex.as_bytes());
+      }
+    }
+
+    line
+  }
+}
+
+/// A checksum list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+  entries: Vec<Entry>,
+}
+
+impl Manifest {
+  /// Constructs a new empty `Manifest`.
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Computes the manifest of every file below `root`, with paths relative to `root` in
+  /// sorted order.
+  ///
+  /// Symbolic links to files are followed, symbolic links to directories are not.
+  pub fn from_dir<P: AsRef<Path>>(root: P) -> Result<Self> {
+    let root = root.as_ref();
+    let mut entries = Vec::new();
+
+    for path in walk(root)? {
+      let checksum = adler32(&mut File::open(root.join(&path))?)?;
+      entries.push(Entry::new(path, checksum));
+    }
+
+    Ok(Self { entries })
+  }
+
+  /// Returns the entries.
+  pub fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  /// Appends an entry.
+  pub fn push<P: Into<PathBuf>>(&mut self, path: P, checksum: u32) {
+    self.entries.push(Entry::new(path, checksum));
+  }
+
+  /// Parses a newline terminated list in either style, mixed styles and blank lines are
+  /// accepted.
+  pub fn parse(data: &[u8]) -> Result<Self> {
+    let mut entries = Vec::new();
+
+    for (i, line) in data.split(|&byte| byte == b'\n').enumerate() {
+      if strip_suffix(line, b"\r").is_empty() {
+        continue;
+      }
+
+      match Entry::parse(line) {
+        Some(entry) => entries.push(entry),
+        None => {
+          let message = format!("Improperly formatted checksum line {}", i + 1);
+          return Err(Error::new(ErrorKind::InvalidData, message));
+        }
+      }
+    }
+
+    Ok(Self { entries })
+  }
+
+  /// Reads a newline terminated list from `reader`, see [`parse`](Self::parse).
+  pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    Self::parse(&data)
+  }
+
+  /// Writes the list into `writer` in `style`, one line per entry.
+  pub fn write_to<W: Write>(&self, writer: &mut W, style: Style) -> Result<()> {
+    for entry in &self.entries {
+      let mut line = entry.to_line(style, true);
+      line.push(b'\n');
+
+      writer.write_all(&line)?;
+    }
+
+    Ok(())
+  }
+
+  /// Verifies the files below `root` against the manifest.
+  ///
+  /// Listed files that do not exist are missing, files below `root` that are not listed
+  /// are extra. Other errors opening or reading a file are returned.
+  ///
+  /// Returns an [`InvalidData`](ErrorKind::InvalidData) error before reading any file if
+  /// a listed path is absolute or contains `..`, it could name a file outside `root`.
+  pub fn verify_dir<P: AsRef<Path>>(&self, root: P) -> Result<Report> {
+    let root = root.as_ref();
+    let mut report = Report::default();
+
+    for entry in &self.entries {
+      let escapes = entry.path.components().any(|component| match component {
+        Component::Prefix(_) | Component::RootDir | Component::ParentDir => true,
+        Component::CurDir | Component::Normal(_) => false,
+      });
+
+      if escapes {
+        let message = format!("Path outside the root: {}", entry.path.display());
+        return Err(Error::new(ErrorKind::InvalidData, message));
+      }
+    }
+
+    for entry in &self.entries {
+      let mut file = match File::open(root.join(&entry.path)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+          report.missing.push(entry.path.clone());
+          continue;
+        }
+        Err(err) => return Err(err),
+      };
+
+      let actual = adler32(&mut file)?;
+      if actual != entry.checksum {
+        report.mismatched.push(Mismatch {
+          path: entry.path.clone(),
+          expected: entry.checksum,
+          actual,
+        });
+      }
+    }
+
+    let listed: HashSet<_> = self.entries.iter().map(|entry| &entry.path).collect();
+    report.extra = walk(root)?
+      .into_iter()
+      .filter(|path| !listed.contains(path))
+      .collect();
+
+    Ok(report)
+  }
+}
+
+/// Result of verifying a directory tree, see [`Manifest::verify_dir`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+  /// Listed files whose checksum differs.
+  pub mismatched: Vec<Mismatch>,
+  /// Listed files that do not exist.
+  pub missing: Vec<PathBuf>,
+  /// Files that are not listed.
+  pub extra: Vec<PathBuf>,
+}
+
+impl Report {
+  /// Returns `true` if the tree matches the manifest exactly.
+  pub fn is_ok(&self) -> bool {
+    self.mismatched.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+  }
+}
+
+/// A listed file whose checksum differs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+  /// Path of the file.
+  pub path: PathBuf,
+  /// Checksum in the manifest.
+  pub expected: u32,
+  /// Checksum of the file.
+  pub actual: u32,
+}
+
+/// Returns the sidecar path of `path`, the path with `.adler32` appended.
+///
+/// # Examples
+/// ```rust
+/// use std::path::Path;
+/// use simd_adler32::manifest::sidecar_path;
+///
+/// assert_eq!(sidecar_path("blob.tar.gz"), Path::new("blob.tar.gz.adler32"));
+/// ```
+pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+  let mut sidecar = OsString::from(path.as_ref());
+  sidecar.push(".");
+  sidecar.push(SIDECAR_EXTENSION);
+
+  sidecar.into()
+}
+
+/// Hashes the file at `path` and writes its sidecar in GNU style, returns the checksum.
+pub fn write_sidecar<P: AsRef<Path>>(path: P) -> Result<u32> {
+  let path = path.as_ref();
+  let checksum = adler32(&mut File::open(path)?)?;
+
+  let name = path.file_name().map(Path::new).unwrap_or(path);
+  let mut line = Entry::new(name, checksum).to_line(Style::Gnu, true);
+  line.push(b'\n');
+
+  fs::write(sidecar_path(path), line)?;
+  Ok(checksum)
+}
+
+/// Reads the checksum from the sidecar of `path`.
+///
+/// The sidecar holds a line in either style or just the hexadecimal checksum.
+pub fn read_sidecar<P: AsRef<Path>>(path: P) -> Result<u32> {
+  let data = fs::read(sidecar_path(path))?;
+  let line = data.split(|&byte| byte == b'\n').next().unwrap_or(&[]);
+
+  Entry::parse(line)
+    .map(|entry| entry.checksum)
+    .or_else(|| parse_hex(strip_suffix(line, b"\r")))
+    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid sidecar file"))
+}
+
+/// Returns `true` if the file at `path` matches its sidecar.
+pub fn verify_sidecar<P: AsRef<Path>>(path: P) -> Result<bool> {
+  let path = path.as_ref();
+  let expected = read_sidecar(path)?;
+
+  Ok(adler32(&mut File::open(path)?)? == expected)
+}
+
+/// Returns the bytes of `path`, lossily converted on platforms without byte paths.
+#[cfg(unix)]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+  use std::os::unix::ffi::OsStrExt;
+
+  Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+/// Returns the bytes of `path`, lossily converted on platforms without byte paths.
+#[cfg(not(unix))]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+  match path.to_string_lossy() {
+    Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+    Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+  }
+}
+
+/// Returns the path with `bytes`, lossily converted on platforms without byte paths.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+  use std::os::unix::ffi::OsStringExt;
+
+  OsString::from_vec(bytes).into()
+}
+
+/// Returns the path with `bytes`, lossily converted on platforms without byte paths.
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+  String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// Escapes backslashes and newlines in `name`, returns `None` if it needs no escaping.
+pub fn escape_name(name: &[u8]) -> Option<Vec<u8>> {
+  if !name.iter().any(|&byte| byte == b'\\' || byte == b'\n') {
+    return None;
+  }
+
+  let mut escaped = Vec::with_capacity(name.len() + 2);
+  for &byte in name {
+    match byte {
+      b'\\' => escaped.extend_from_slice(b"\\\\"),
+      b'\n' => escaped.extend_from_slice(b"\\n"),
+      _ => escaped.push(byte),
+    }
+  }
+
+  Some(escaped)
+}
+
+fn unescape(name: &[u8]) -> Option<Vec<u8>> {
+  let mut unescaped = Vec::with_capacity(name.len());
+  let mut bytes = name.iter();
+
+  while let Some(&byte) = bytes.next() {
+    unescaped.push(match byte {
+      b'\\' => match bytes.next()? {
+        b'\\' => b'\\',
+        b'n' => b'\n',
+        _ => return None,
+      },
+      _ => byte,
+    });
+  }
+
+  Some(unescaped)
+}
+
+fn parse_hex(hex: &[u8]) -> Option<u32> {
+  if hex.len() != 8 {
+    return None;
+  }
+
+  hex.iter().try_fold(0u32, |checksum, &byte| {
+    let digit = (byte as char).to_digit(16)?;
+    Some((checksum << 4) | digit)
+  })
+}
+
+fn strip_suffix<'a>(data: &'a [u8], suffix: &[u8]) -> &'a [u8] {
+  if data.ends_with(suffix) {
+    &data[..data.len() - suffix.len()]
+  } else {
+    data
+  }
+}
+
+/// Returns the paths of the files below `root` relative to `root`, sorted.
+fn walk(root: &Path) -> Result<Vec<PathBuf>> {
+  let mut files = Vec::new();
+  let mut dirs = vec![PathBuf::new()];
+
+  while let Some(dir) = dirs.pop() {
+    for entry in fs::read_dir(root.join(&dir))? {
+      let entry = entry?;
+      let path = dir.join(entry.file_name());
+      let file_type = entry.file_type()?;
+
+      if file_type.is_dir() {
+        dirs.push(path);
+      } else if file_type.is_file()
+        || (file_type.is_symlink() && fs::metadata(entry.path())?.is_file())
+      {
+        files.push(path);
+      }
+    }
+  }
+
+  files.sort_by(|a, b| path_bytes(a).cmp(&path_bytes(b)));
+  Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    read_sidecar, sidecar_path, verify_sidecar, write_sidecar, Entry, Manifest, Mismatch,
+    Style,
+  };
+  use std::fs;
+  use std::path::{Path, PathBuf};
+
+  /// Temporary directory removed on drop.
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let dir = std::env::temp_dir().join(format!(
+        "simd-adler32-manifest-{}-{}",
+        std::process::id(),
+        name
+      ));
+
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(&dir).unwrap();
+      TempDir(dir)
+    }
+
+    fn file<P: AsRef<Path>>(&self, path: P, data: &[u8]) -> PathBuf {
+      let path = self.0.join(path);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(&path, data).unwrap();
+      path
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  #[test]
+  fn lines() {
+    let entry = Entry::new("file name", 0x11e6_0398);
+
+    assert_eq!(entry.to_line(Style::Gnu, true), b"11e60398  file name");
+    assert_eq!(
+      entry.to_line(Style::Bsd, true),
+      &b"ADLER32 (file name) = 11e60398"[..]
+    );
+    assert_eq!(Entry::parse(b"11e60398  file name"), Some(entry.clone()));
+    assert_eq!(Entry::parse(b"11E60398 *file name\r"), Some(entry.clone()));
+    assert_eq!(Entry::parse(b"ADLER32 (file name) = 11e60398"), Some(entry));
+
+    assert_eq!(Entry::parse(b"11e6039  file"), None);
+    assert_eq!(Entry::parse(b"11e6039x  file"), None);
+    assert_eq!(Entry::parse(b"+1e60398  file"), None);
+    assert_eq!(Entry::parse(b"11e60398  "), None);
+    assert_eq!(Entry::parse(b"ADLER32 () = 11e60398"), None);
+    assert_eq!(Entry::parse(b"\\11e60398  a\\b"), None);
+  }
+
+  #[test]
+  fn escaped() {
+    let entry = Entry::new("a\\b\nc", 1);
+
+    assert_eq!(
+      entry.to_line(Style::Gnu, true),
+      &b"\\00000001  a\\\\b\\nc"[..]
+    );
+    assert_eq!(entry.to_line(Style::Gnu, false), &b"00000001  a\\b\nc"[..]);
+    assert_eq!(
+      entry.to_line(Style::Bsd, true),
+      &b"\\ADLER32 (a\\\\b\\nc) = 00000001"[..]
+    );
+
+    for &style in &[Style::Gnu, Style::Bsd] {
+      assert_eq!(
+        Entry::parse(&entry.to_line(style, true)),
+        Some(entry.clone())
+      );
+    }
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn non_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let entry = Entry::new(OsStr::from_bytes(b"caf\xe9"), 1);
+    let line = entry.to_line(Style::Gnu, true);
+
+    assert_eq!(line, &b"00000001  caf\xe9"[..]);
+    assert_eq!(Entry::parse(&line), Some(entry));
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut manifest = Manifest::new();
+    manifest.push("a", 1);
+    manifest.push("dir/b", 0xffff_fff0);
+    manifest.push("new\nline", 2);
+
+    for &style in &[Style::Gnu, Style::Bsd] {
+      let mut list = Vec::new();
+      manifest.write_to(&mut list, style).unwrap();
+
+      assert_eq!(Manifest::read_from(&mut &list[..]).unwrap(), manifest);
+    }
+
+    let err = Manifest::parse(b"00000001  a\n\nnot a line\n").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn verify_dir() {
+    let dir = TempDir::new("verify");
+    dir.file("a", b"Wikipedia");
+    dir.file("sub/b", b"data");
+    dir.file("sub/deeper/c", b"");
+
+    let manifest = Manifest::from_dir(&dir.0).unwrap();
+    let paths: Vec<_> = manifest.entries().iter().map(|e| e.path.clone()).collect();
+
+    assert_eq!(
+      paths,
+      vec![
+        PathBuf::from("a"),
+        PathBuf::from("sub/b"),
+        PathBuf::from("sub/deeper/c")
+      ]
+    );
+    assert_eq!(manifest.entries()[0].checksum, 0x11e6_0398);
+    assert!(manifest.verify_dir(&dir.0).unwrap().is_ok());
+
+    dir.file("sub/b", b"changed");
+    dir.file("extra", b"");
+    fs::remove_file(dir.0.join("sub/deeper/c")).unwrap();
+
+    let report = manifest.verify_dir(&dir.0).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(
+      report.mismatched,
+      vec![Mismatch {
+        path: PathBuf::from("sub/b"),
+        expected: adler2::adler32_slice(b"data"),
+        actual: adler2::adler32_slice(b"changed"),
+      }]
+    );
+    assert_eq!(report.missing, vec![PathBuf::from("sub/deeper/c")]);
+    assert_eq!(report.extra, vec![PathBuf::from("extra")]);
+  }
+
+  #[test]
+  fn verify_dir_outside_root() {
+    let dir = TempDir::new("outside");
+    dir.file("inner/a", b"data");
+
+    let mut manifest = Manifest::new();
+    manifest.push("a", adler2::adler32_slice(b"data"));
+    assert!(manifest.verify_dir(dir.0.join("inner")).unwrap().is_ok());
+
+    for path in &["../a", "b/../../a", "/etc/passwd"] {
+      let mut manifest = manifest.clone();
+      manifest.push(*path, 1);
+
+      let err = manifest.verify_dir(dir.0.join("inner")).unwrap_err();
+      assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{}", path);
+    }
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn verify_dir_non_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TempDir::new("non-utf8");
+    let name = Path::new(OsStr::from_bytes(b"caf\xe9"));
+    dir.file(name, b"data");
+
+    let mut list = Vec::new();
+    let manifest = Manifest::from_dir(&dir.0).unwrap();
+    manifest.write_to(&mut list, Style::Gnu).unwrap();
+
+    let manifest = Manifest::read_from(&mut &list[..]).unwrap();
+    assert_eq!(manifest.entries()[0].path, name);
+    assert!(manifest.verify_dir(&dir.0).unwrap().is_ok());
+  }
+
+  #[test]
+  fn sidecars() {
+    let dir = TempDir::new("sidecars");
+    let blob = dir.file("blob.bin", b"Wikipedia");
+
+    assert_eq!(write_sidecar(&blob).unwrap(), 0x11e6_0398);
+    assert_eq!(
+      fs::read(sidecar_path(&blob)).unwrap(),
+      b"11e60398  blob.bin\n"
+    );
+    assert_eq!(read_sidecar(&blob).unwrap(), 0x11e6_0398);
+    assert!(verify_sidecar(&blob).unwrap());
+
+    fs::write(sidecar_path(&blob), "11E60398\n").unwrap();
+    assert!(verify_sidecar(&blob).unwrap());
+
+    fs::write(&blob, b"changed").unwrap();
+    assert!(!verify_sidecar(&blob).unwrap());
+
+    fs::write(sidecar_path(&blob), "garbage").unwrap();
+    assert!(read_sidecar(&blob).is_err());
+  }
+}
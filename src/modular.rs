@@ -0,0 +1,352 @@
+//! Adler-style checksums over arbitrary moduli.
+use crate::imp::{
+  get_mod_imp, scalar, update_adler32, AdlerModImp, Modulus, MOD, NMAX, SHORT_LEN,
+};
+
+/// Inverse of `MOD` modulo `2^16`.
+const MOD_INV: u32 = 4369;
+
+/// Modulus of the low half of the 32-bit sums, see [`AdlerMod64::write`].
+const LO_MODULUS: Modulus = Modulus::new(65536);
+
+/// An Adler-style hash generator type with 16-bit sums reduced by modulus `M`.
+///
+/// `M` must be within `1..=65536`, [`Adler32`](crate::Adler32) is `AdlerMod<65521>`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::AdlerMod;
+///
+/// let mut hash = AdlerMod::<65519>::new();
+/// hash.write(b"Adler-32");
+///
+/// println!("{:#x}", hash.finish());
+/// ```
+#[derive(Clone)]
+pub struct AdlerMod<const M: u32> {
+  a: u16,
+  b: u16,
+  update: AdlerModImp,
+}
+
+impl<const M: u32> AdlerMod<M> {
+  /// `M` and its chunk size, computed once per modulus.
+  const MODULUS: Modulus = Modulus::new(M);
+
+  /// Constructs a new `AdlerMod`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::AdlerMod;
+  ///
+  /// let mut hash = AdlerMod::<65519>::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Constructs a new `AdlerMod` using existing checksum.
+  ///
+  /// # Panics
+  /// If `M` is not within `1..=65536`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::AdlerMod;
+  ///
+  /// let mut hash = AdlerMod::<65519>::from_checksum(0xdead_beaf);
+  /// ```
+  pub fn from_checksum(checksum: u32) -> Self {
+    assert!(M != 0 && M <= 65536, "Modulus must be within 1..=65536");
+
+    Self {
+      a: checksum as u16,
+      b: (checksum >> 16) as u16,
+      update: if M == MOD {
+        update_adler32
+      } else {
+        get_mod_imp()
+      },
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  ///
+  /// The Adler-32 modulus is dispatched to the same implementation as
+  /// [`adler32`](crate::adler32).
+  pub fn write(&mut self, data: &[u8]) {
+    let (a, b) = if data.len() < SHORT_LEN {
+      scalar::update_short(self.a, self.b, data, M)
+    } else {
+      (self.update)(self.a, self.b, data, Self::MODULUS)
+    };
+
+    self.a = a;
+    self.b = b;
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> u32 {
+    (u32::from(self.b) << 16) | u32::from(self.a)
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.a = 1;
+    self.b = 0;
+  }
+}
+
+impl<const M: u32> Default for AdlerMod<M> {
+  fn default() -> Self {
+    Self::from_checksum(1)
+  }
+}
+
+/// An Adler-style hash generator type with 32-bit sums reduced by modulus `M`.
+///
+/// `M` must not be zero, [`Adler64`] uses the largest prime below `2^32`.
+#[derive(Clone)]
+pub struct AdlerMod64<const M: u32> {
+  a: u32,
+  b: u32,
+  update: AdlerModImp,
+}
+
+/// A 64-bit Adler hash generator type using modulus `4294967291`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::Adler64;
+///
+/// let mut hash = Adler64::new();
+/// hash.write(b"Wikipedia");
+///
+/// assert_eq!(hash.finish(), 0x0000_11e6_0000_0398);
+/// ```
+pub type Adler64 = AdlerMod64<4294967291>;
+
+impl<const M: u32> AdlerMod64<M> {
+  /// Constructs a new `AdlerMod64`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::Adler64;
+  ///
+  /// let mut hash = Adler64::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Constructs a new `AdlerMod64` using existing checksum.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::Adler64;
+  ///
+  /// let mut hash = Adler64::from_checksum(0x0000_11e6_0000_0398);
+  /// ```
+  pub fn from_checksum(checksum: u64) -> Self {
+    assert!(M != 0, "Modulus must not be zero");
+
+    Self {
+      a: checksum as u32,
+      b: (checksum >> 32) as u32,
+      update: get_mod_imp(),
+    }
+  }
+
+  /// Computes hash for supplied data and stores results in internal state.
+  ///
+  /// Data is summed in `NMAX` byte segments by the 16-bit kernels, once modulo `2^16` and
+  /// once modulo `65521`. The segment sums are below the product of both moduli so they
+  /// are recovered exactly and then folded into the 32-bit state.
+  pub fn write(&mut self, data: &[u8]) {
+    let modulus = u64::from(M);
+    let mut a = u64::from(self.a);
+    let mut b = u64::from(self.b);
+
+    for segment in data.chunks(NMAX) {
+      let (lo_a, lo_b) = (self.update)(0, 0, segment, LO_MODULUS);
+      let (hi_a, hi_b) = (self.update)(0, 0, segment, Modulus::ADLER32);
+
+      let n = segment.len() as u64;
+      b = (b + a * n + crt(lo_b, hi_b)) % modulus;
+      a = (a + crt(lo_a, hi_a)) % modulus;
+    }
+
+    self.a = a as u32;
+    self.b = b as u32;
+  }
+
+  /// Returns the hash value for the values written so far.
+  ///
+  /// Despite its name, the method does not reset the hasher’s internal state. Additional
+  /// writes will continue from the current value. If you need to start a fresh hash
+  /// value, you will have to use `reset`.
+  pub fn finish(&self) -> u64 {
+    (u64::from(self.b) << 32) | u64::from(self.a)
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.a = 1;
+    self.b = 0;
+  }
+}
+
+impl<const M: u32> Default for AdlerMod64<M> {
+  fn default() -> Self {
+    Self::from_checksum(1)
+  }
+}
+
+/// Returns the value below `2^16 * MOD` congruent to `lo` modulo `2^16` and to `hi`
+/// modulo `MOD`.
+#[inline(always)]
+fn crt(lo: u16, hi: u16) -> u64 {
+  let t = u32::from(lo)
+    .wrapping_sub(u32::from(hi))
+    .wrapping_mul(MOD_INV)
+    & 0xffff;
+
+  u64::from(hi) + u64::from(MOD) * u64::from(t)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Adler64, AdlerMod, AdlerMod64};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn adler32() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &len in &[0, 1, 100, 5552, 5553, 1024 * 100] {
+      let mut hash = AdlerMod::<65521>::new();
+      hash.write(&random[..len]);
+
+      assert_eq!(
+        hash.finish(),
+        adler2::adler32_slice(&random[..len]),
+        "len({})",
+        len
+      );
+    }
+  }
+
+  #[test]
+  fn layout() {
+    use core::mem::size_of;
+
+    assert_eq!(size_of::<AdlerMod<65521>>(), size_of::<(u16, u16, fn())>());
+  }
+
+  #[test]
+  fn moduli() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &len in &[0, 1, 100, 5803, 5804, 1024 * 100] {
+      let data = &random[..len];
+
+      assert_eq!(checksum::<1>(data), reference(data, 1), "len({})", len);
+      assert_eq!(checksum::<251>(data), reference(data, 251), "len({})", len);
+      assert_eq!(
+        checksum::<65519>(data),
+        reference(data, 65519),
+        "len({})",
+        len
+      );
+      assert_eq!(
+        checksum::<65536>(data),
+        reference(data, 65536),
+        "len({})",
+        len
+      );
+    }
+  }
+
+  #[test]
+  fn adler64() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &len in &[0, 1, 100, 5552, 5553, 1024 * 100] {
+      let data = &random[..len];
+
+      assert_eq!(
+        checksum64::<4294967291>(data),
+        reference64(data, 4294967291)
+      );
+      assert_eq!(checksum64::<65521>(data), reference64(data, 65521));
+      assert_eq!(checksum64::<7>(data), reference64(data, 7));
+    }
+
+    assert_eq!(
+      checksum64::<4294967291>(&[0xff; 1024 * 100]),
+      reference64(&[0xff; 1024 * 100], 4294967291)
+    );
+  }
+
+  #[test]
+  fn streaming() {
+    let mut random = [0; 1024 * 20];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut hash = Adler64::new();
+    for chunk in random.chunks(1000) {
+      hash.write(chunk);
+    }
+
+    assert_eq!(hash.finish(), reference64(&random, 4294967291));
+  }
+
+  #[test]
+  fn from_checksum() {
+    let mut hash = Adler64::from_checksum(reference64(b"abcd", 4294967291));
+    hash.write(b"efgh");
+
+    assert_eq!(hash.finish(), reference64(b"abcdefgh", 4294967291));
+  }
+
+  fn checksum<const M: u32>(data: &[u8]) -> u32 {
+    let mut hash = AdlerMod::<M>::new();
+    hash.write(data);
+    hash.finish()
+  }
+
+  fn checksum64<const M: u32>(data: &[u8]) -> u64 {
+    let mut hash = AdlerMod64::<M>::new();
+    hash.write(data);
+    hash.finish()
+  }
+
+  fn reference(data: &[u8], modulus: u32) -> u32 {
+    let (mut a, mut b) = (1 % modulus, 0);
+
+    for byte in data {
+      a = (a + u32::from(*byte)) % modulus;
+      b = (b + a) % modulus;
+    }
+
+    (b << 16) | a
+  }
+
+  fn reference64(data: &[u8], modulus: u64) -> u64 {
+    let (mut a, mut b) = (1 % modulus, 0);
+
+    for byte in data {
+      a = (a + u64::from(*byte)) % modulus;
+      b = (b + a) % modulus;
+    }
+
+    (b << 32) | a
+  }
+}
@@ -0,0 +1,472 @@
+//! rsync and librsync weak checksums.
+//!
+//! Both checksums are Adler-32 variants summing bytes modulo `2^16` with a character
+//! offset added to every byte, rather than modulo the Adler-32 prime. They share the
+//! SIMD update implementations used by [`Adler32`](crate::Adler32).
+//!
+//! * [`RsyncChecksum`] matches rsync's `get_checksum1`, which sums bytes as *signed*
+//!   chars with a `CHAR_OFFSET` of `0`.
+//! * [`Rollsum`] matches librsync's `rollsum`, which sums bytes as unsigned chars with
+//!   a `RS_CHAR_OFFSET` of `31`.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::rsync::{rollsum, Rollsum};
+//!
+//! let mut sum = Rollsum::new();
+//! sum.write(b"rust is pretty cool, man");
+//!
+//! assert_eq!(sum.finish(), rollsum(b"rust is pretty cool, man"));
+//! ```
+use crate::imp::{get_mod_imp, AdlerModImp, Modulus};
+
+/// rsync's `CHAR_OFFSET`.
+const CHAR_OFFSET: u16 = 0;
+
+/// librsync's `RS_CHAR_OFFSET`.
+pub(crate) const RS_CHAR_OFFSET: u16 = 31;
+
+/// Sums are reduced modulo `2^16`.
+const MOD: Modulus = Modulus::new(65536);
+
+/// Size of the scratch buffer used to convert signed bytes.
+const BUF_SIZE: usize = 4096;
+
+/// Compute the rsync weak checksum of `data`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::rsync::rsync_checksum;
+///
+/// let hash = rsync_checksum(b"Wikipedia");
+/// println!("{}", hash); // 299697047
+/// ```
+pub fn rsync_checksum(data: &[u8]) -> u32 {
+  let mut hash = RsyncChecksum::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// Compute the librsync rollsum of `data`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::rsync::rollsum;
+///
+/// let hash = rollsum(b"Wikipedia");
+/// println!("{}", hash); // 391120046
+/// ```
+pub fn rollsum(data: &[u8]) -> u32 {
+  let mut hash = Rollsum::new();
+
+  hash.write(data);
+  hash.finish()
+}
+
+/// rsync weak checksum generator type.
+///
+/// Matches `get_checksum1` and the rolling update in rsync's `match.c`, including the
+/// treatment of bytes as signed chars.
+#[derive(Clone)]
+pub struct RsyncChecksum {
+  sums: Sums,
+}
+
+impl RsyncChecksum {
+  /// Constructs a new `RsyncChecksum`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::RsyncChecksum;
+  ///
+  /// let mut sum = RsyncChecksum::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Computes checksum for supplied data and stores results in internal state.
+  pub fn write(&mut self, data: &[u8]) {
+    let mut buf = [0; BUF_SIZE];
+
+    // Signed bytes are offset by `128` so the unsigned update implementations can be
+    // used, `b as i8 == (b ^ 0x80) - 128`.
+    for chunk in data.chunks(BUF_SIZE) {
+      let buf = &mut buf[..chunk.len()];
+      for (dst, src) in buf.iter_mut().zip(chunk) {
+        *dst = src ^ 0x80;
+      }
+
+      self.sums.write(buf, CHAR_OFFSET.wrapping_sub(128));
+    }
+  }
+
+  /// Removes `old` from the start of the window and appends `new` to the end.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::RsyncChecksum;
+  ///
+  /// let mut rolling = RsyncChecksum::new();
+  /// rolling.write(b"abcd");
+  /// rolling.rotate(b'a', b'e');
+  ///
+  /// let mut sum = RsyncChecksum::new();
+  /// sum.write(b"bcde");
+  ///
+  /// assert_eq!(rolling.finish(), sum.finish());
+  /// ```
+  pub fn rotate(&mut self, old: u8, new: u8) {
+    self.sums.rotate(Self::value(old), Self::value(new));
+  }
+
+  /// Appends `byte` to the end of the window.
+  pub fn roll_in(&mut self, byte: u8) {
+    self.sums.roll_in(Self::value(byte));
+  }
+
+  /// Removes `byte` from the start of the window.
+  pub fn roll_out(&mut self, byte: u8) {
+    self.sums.roll_out(Self::value(byte));
+  }
+
+  /// Returns the number of bytes in the window.
+  pub fn count(&self) -> usize {
+    self.sums.count
+  }
+
+  /// Returns the checksum value for the values written so far.
+  ///
+  /// Despite its name, the method does not reset the internal state. Additional writes
+  /// will continue from the current value. If you need to start a fresh checksum value,
+  /// you will have to use `reset`.
+  pub fn finish(&self) -> u32 {
+    self.sums.finish()
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.sums.reset();
+  }
+
+  #[inline]
+  fn value(byte: u8) -> u16 {
+    (byte as i8 as u16).wrapping_add(CHAR_OFFSET)
+  }
+}
+
+impl Default for RsyncChecksum {
+  fn default() -> Self {
+    Self { sums: Sums::new() }
+  }
+}
+
+/// librsync rollsum generator type.
+///
+/// Matches `RollsumUpdate`, `RollsumRotate`, `RollsumRollin` and `RollsumRollout` from
+/// librsync's `rollsum.h`.
+#[derive(Clone)]
+pub struct Rollsum {
+  sums: Sums,
+}
+
+impl Rollsum {
+  /// Constructs a new `Rollsum`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::Rollsum;
+  ///
+  /// let mut sum = Rollsum::new();
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Computes checksum for supplied data and stores results in internal state.
+  pub fn write(&mut self, data: &[u8]) {
+    self.sums.write(data, RS_CHAR_OFFSET);
+  }
+
+  /// Removes `old` from the start of the window and appends `new` to the end.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::Rollsum;
+  ///
+  /// let mut rolling = Rollsum::new();
+  /// rolling.write(b"abcd");
+  /// rolling.rotate(b'a', b'e');
+  ///
+  /// let mut sum = Rollsum::new();
+  /// sum.write(b"bcde");
+  ///
+  /// assert_eq!(rolling.finish(), sum.finish());
+  /// ```
+  pub fn rotate(&mut self, old: u8, new: u8) {
+    self.sums.rotate(Self::value(old), Self::value(new));
+  }
+
+  /// Appends `byte` to the end of the window.
+  pub fn roll_in(&mut self, byte: u8) {
+    self.sums.roll_in(Self::value(byte));
+  }
+
+  /// Removes `byte` from the start of the window.
+  pub fn roll_out(&mut self, byte: u8) {
+    self.sums.roll_out(Self::value(byte));
+  }
+
+  /// Returns the number of bytes in the window.
+  pub fn count(&self) -> usize {
+    self.sums.count
+  }
+
+  /// Returns the checksum value for the values written so far.
+  ///
+  /// Despite its name, the method does not reset the internal state. Additional writes
+  /// will continue from the current value. If you need to start a fresh checksum value,
+  /// you will have to use `reset`.
+  pub fn finish(&self) -> u32 {
+    self.sums.finish()
+  }
+
+  /// Resets the internal state.
+  pub fn reset(&mut self) {
+    self.sums.reset();
+  }
+
+  #[inline]
+  fn value(byte: u8) -> u16 {
+    u16::from(byte) + RS_CHAR_OFFSET
+  }
+}
+
+impl Default for Rollsum {
+  fn default() -> Self {
+    Self { sums: Sums::new() }
+  }
+}
+
+/// Running sums shared by rsync and librsync checksums.
+#[derive(Clone)]
+struct Sums {
+  s1: u16,
+  s2: u16,
+  count: usize,
+  update: AdlerModImp,
+}
+
+impl Sums {
+  fn new() -> Self {
+    Self {
+      s1: 0,
+      s2: 0,
+      count: 0,
+      update: get_mod_imp(),
+    }
+  }
+
+  /// Adds `data` with `offset` added to every byte.
+  ///
+  /// The offset is applied after the update implementation runs:
+  /// `s1 += n * offset` and `s2 += offset * n * (n + 1) / 2`.
+  fn write(&mut self, data: &[u8], offset: u16) {
+    let (s1, s2) = (self.update)(self.s1, self.s2, data, MOD);
+    let n = data.len();
+
+    self.s1 = s1.wrapping_add(offset.wrapping_mul(n as u16));
+    self.s2 = s2.wrapping_add(offset.wrapping_mul(triangular(n)));
+    self.count = self.count.wrapping_add(n);
+  }
+
+  #[inline]
+  fn rotate(&mut self, old: u16, new: u16) {
+    self.s1 = self.s1.wrapping_add(new).wrapping_sub(old);
+    self.s2 = self
+      .s2
+      .wrapping_add(self.s1)
+      .wrapping_sub((self.count as u16).wrapping_mul(old));
+  }
+
+  #[inline]
+  fn roll_in(&mut self, new: u16) {
+    self.s1 = self.s1.wrapping_add(new);
+    self.s2 = self.s2.wrapping_add(self.s1);
+    self.count = self.count.wrapping_add(1);
+  }
+
+  #[inline]
+  fn roll_out(&mut self, old: u16) {
+    self.s1 = self.s1.wrapping_sub(old);
+    self.s2 = self.s2.wrapping_sub((self.count as u16).wrapping_mul(old));
+    self.count = self.count.wrapping_sub(1);
+  }
+
+  fn finish(&self) -> u32 {
+    (u32::from(self.s2) << 16) | u32::from(self.s1)
+  }
+
+  fn reset(&mut self) {
+    self.s1 = 0;
+    self.s2 = 0;
+    self.count = 0;
+  }
+}
+
+/// Returns `n * (n + 1) / 2` modulo `2^16`.
+#[inline]
+fn triangular(n: usize) -> u16 {
+  let (x, y) = if n & 1 == 0 {
+    (n / 2, n.wrapping_add(1))
+  } else {
+    (n, (n / 2).wrapping_add(1))
+  };
+
+  (x as u16).wrapping_mul(y as u16)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{rollsum, rsync_checksum, Rollsum, RsyncChecksum};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn zeroes() {
+    assert_sums_eq(&[]);
+    assert_sums_eq(&[0]);
+    assert_sums_eq(&[0; 100]);
+    assert_sums_eq(&[0; 1024 * 64]);
+  }
+
+  #[test]
+  fn ones() {
+    assert_sums_eq(&[0xff]);
+    assert_sums_eq(&[0xff; 100]);
+    assert_sums_eq(&[0xff; 1024 * 64]);
+  }
+
+  #[test]
+  fn random() {
+    let mut random = [0; 1024 * 64];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    assert_sums_eq(&random[..1]);
+    assert_sums_eq(&random[..100]);
+    assert_sums_eq(&random[..1024 - 5]);
+    assert_sums_eq(&random[..5553]);
+    assert_sums_eq(&random[..]);
+  }
+
+  #[test]
+  fn streaming() {
+    let mut random = [0; 1024 * 16];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut rsync = RsyncChecksum::new();
+    let mut librsync = Rollsum::new();
+
+    for chunk in random.chunks(1000) {
+      rsync.write(chunk);
+      librsync.write(chunk);
+    }
+
+    assert_eq!(rsync.finish(), reference_rsync(&random));
+    assert_eq!(librsync.finish(), reference_rollsum(&random));
+    assert_eq!(rsync.count(), random.len());
+    assert_eq!(librsync.count(), random.len());
+  }
+
+  #[test]
+  fn rolling() {
+    let window = 700;
+    let mut random = [0; 1024 * 4];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut rsync = RsyncChecksum::new();
+    let mut librsync = Rollsum::new();
+
+    rsync.write(&random[..window]);
+    librsync.write(&random[..window]);
+
+    for i in window..random.len() {
+      rsync.rotate(random[i - window], random[i]);
+      librsync.rotate(random[i - window], random[i]);
+
+      let expected = &random[i + 1 - window..=i];
+      assert_eq!(rsync.finish(), reference_rsync(expected), "offset({})", i);
+      assert_eq!(
+        librsync.finish(),
+        reference_rollsum(expected),
+        "offset({})",
+        i
+      );
+    }
+
+    for i in random.len() - window..random.len() - 1 {
+      rsync.roll_out(random[i]);
+      librsync.roll_out(random[i]);
+
+      let expected = &random[i + 1..];
+      assert_eq!(rsync.finish(), reference_rsync(expected));
+      assert_eq!(librsync.finish(), reference_rollsum(expected));
+    }
+
+    rsync.roll_in(0x80);
+    librsync.roll_in(0x80);
+
+    let expected = [random[random.len() - 1], 0x80];
+    assert_eq!(rsync.finish(), reference_rsync(&expected));
+    assert_eq!(librsync.finish(), reference_rollsum(&expected));
+  }
+
+  #[test]
+  fn reset() {
+    let mut sum = RsyncChecksum::new();
+    sum.write(b"Wikipedia");
+    sum.reset();
+    sum.write(b"rust");
+
+    assert_eq!(sum.finish(), rsync_checksum(b"rust"));
+  }
+
+  fn assert_sums_eq(data: &[u8]) {
+    assert_eq!(
+      rsync_checksum(data),
+      reference_rsync(data),
+      "len({})",
+      data.len()
+    );
+    assert_eq!(
+      rollsum(data),
+      reference_rollsum(data),
+      "len({})",
+      data.len()
+    );
+  }
+
+  /// Port of `get_checksum1` from rsync's `checksum.c`.
+  fn reference_rsync(data: &[u8]) -> u32 {
+    let (mut s1, mut s2) = (0u32, 0u32);
+
+    for byte in data {
+      s1 = s1.wrapping_add(*byte as i8 as u32);
+      s2 = s2.wrapping_add(s1);
+    }
+
+    (s1 & 0xffff).wrapping_add(s2 << 16)
+  }
+
+  /// Port of `RollsumUpdate` and `RollsumDigest` from librsync's `rollsum.c`.
+  fn reference_rollsum(data: &[u8]) -> u32 {
+    let (mut s1, mut s2) = (0u16, 0u16);
+
+    for byte in data {
+      s1 = s1.wrapping_add(u16::from(*byte) + 31);
+      s2 = s2.wrapping_add(s1);
+    }
+
+    (u32::from(s2) << 16) | u32::from(s1)
+  }
+}
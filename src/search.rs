@@ -0,0 +1,457 @@
+//! Searching data for known block checksums.
+//!
+//! A [`BlockTable`] holds the Adler-32 checksums of fixed size blocks. Scanning rolls an
+//! Adler-32 window of the block size over the data, one byte at a time, and reports every
+//! offset whose window checksum is in the table, as rsync does when matching blocks.
+//!
+//! Checksums are only weak evidence, callers should confirm matches with a strong hash
+//! or by comparing the data.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::search::{BlockTable, Match};
+//! use simd_adler32::block_checksums;
+//!
+//! let known = b"0123456789abcdef";
+//! let mut checksums = [0; 4];
+//! block_checksums(known, 4, &mut checksums);
+//!
+//! let table = BlockTable::new(4, &checksums);
+//! let matches: Vec<_> = table.find(b"xx89abyy").collect();
+//!
+//! assert_eq!(matches, vec![Match { offset: 2, block: 2 }]);
+//! ```
+use crate::imp::MOD;
+use crate::Adler32;
+use std::io::{ErrorKind, Read, Result};
+
+/// Bytes the reader buffer holds past the window before it is compacted.
+const READ_SIZE: usize = 64 * 1024;
+
+/// A window of the searched data matching a known block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+  /// Offset of the window in the searched data.
+  pub offset: u64,
+  /// Index of the matching block in the table.
+  pub block: usize,
+}
+
+/// Lookup table of block checksums.
+///
+/// Checksums are grouped into `2^bits` buckets by their high hashed bits, bucket `i`
+/// spanning `starts[i]..starts[i + 1]` of the sorted checksums. The table takes eight
+/// bytes per block plus four bytes per bucket, with about one bucket per block, and a
+/// miss costs a hash and two loads.
+#[derive(Clone, Debug)]
+pub struct BlockTable {
+  block_size: usize,
+  shift: u32,
+  starts: Vec<u32>,
+  checksums: Vec<u32>,
+  blocks: Vec<u32>,
+}
+
+impl BlockTable {
+  /// Constructs a new `BlockTable` for blocks of `block_size` bytes with the Adler-32
+  /// checksum of block `i` at `checksums[i]`.
+  ///
+  /// # Panics
+  /// Panics if `block_size` is zero.
+  pub fn new(block_size: usize, checksums: &[u32]) -> Self {
+    assert!(block_size > 0, "Block size must not be zero");
+
+    let len = checksums.len().min((1 << 24) - 1) as u32;
+    let bits = (32 - len.leading_zeros()).max(4);
+    let shift = 32 - bits;
+
+    let mut entries: Vec<_> = checksums
+      .iter()
+      .enumerate()
+      .map(|(block, &checksum)| (bucket(checksum, shift), checksum, block as u32))
+      .collect();
+    entries.sort_unstable();
+
+    let mut starts = vec![0; (1 << bits) + 1];
+    for &(bucket, _, _) in &entries {
+      starts[bucket + 1] += 1;
+    }
+    for i in 1..starts.len() {
+      starts[i] += starts[i - 1];
+    }
+
+    Self {
+      block_size,
+      shift,
+      starts,
+      checksums: entries.iter().map(|&(_, checksum, _)| checksum).collect(),
+      blocks: entries.iter().map(|&(_, _, block)| block).collect(),
+    }
+  }
+
+  /// Returns the block size in bytes.
+  pub fn block_size(&self) -> usize {
+    self.block_size
+  }
+
+  /// Returns the indices of the blocks with checksum `checksum` in ascending order.
+  pub fn get(&self, checksum: u32) -> impl Iterator<Item = usize> + '_ {
+    let bucket = bucket(checksum, self.shift);
+    let range = self.starts[bucket] as usize..self.starts[bucket + 1] as usize;
+
+    self.checksums[range.clone()]
+      .iter()
+      .zip(&self.blocks[range])
+      .filter(move |&(&c, _)| c == checksum)
+      .map(|(_, &block)| block as usize)
+  }
+
+  /// Searches `data` for windows matching a block.
+  pub fn find<'a>(&'a self, data: &'a [u8]) -> Matches<'a> {
+    Matches {
+      table: self,
+      data,
+      offset: 0,
+      rolling: None,
+      pending: Pending::default(),
+    }
+  }
+
+  /// Searches the data read from `reader` for windows matching a block.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use std::io::Cursor;
+  /// use simd_adler32::search::{BlockTable, Match};
+  /// use simd_adler32::adler32;
+  ///
+  /// let table = BlockTable::new(4, &[adler32(b"abcd")]);
+  /// let mut reader = Cursor::new(b"xxabcdxx");
+  ///
+  /// let matches: Vec<_> = table.read_find(&mut reader).collect::<Result<_, _>>().unwrap();
+  /// assert_eq!(matches, vec![Match { offset: 2, block: 0 }]);
+  /// ```
+  pub fn read_find<R: Read>(&self, reader: R) -> ReadMatches<'_, R> {
+    ReadMatches {
+      table: self,
+      reader,
+      buf: Vec::new(),
+      base: 0,
+      start: 0,
+      eof: false,
+      rolling: None,
+      pending: Pending::default(),
+    }
+  }
+
+  #[inline(always)]
+  fn candidates(&self, checksum: u32, offset: u64) -> Pending {
+    let bucket = bucket(checksum, self.shift);
+
+    Pending {
+      checksum,
+      offset,
+      next: self.starts[bucket],
+      end: self.starts[bucket + 1],
+    }
+  }
+}
+
+/// Matching entries of a bucket not yet reported.
+#[derive(Clone, Copy, Debug, Default)]
+struct Pending {
+  checksum: u32,
+  offset: u64,
+  next: u32,
+  end: u32,
+}
+
+impl Pending {
+  #[inline(always)]
+  fn next(&mut self, table: &BlockTable) -> Option<Match> {
+    while self.next < self.end {
+      let i = self.next as usize;
+      self.next += 1;
+
+      if table.checksums[i] == self.checksum {
+        return Some(Match {
+          offset: self.offset,
+          block: table.blocks[i] as usize,
+        });
+      }
+    }
+
+    None
+  }
+}
+
+/// Iterator over the matches in a slice, see [`BlockTable::find`].
+#[derive(Clone, Debug)]
+pub struct Matches<'a> {
+  table: &'a BlockTable,
+  data: &'a [u8],
+  offset: usize,
+  rolling: Option<Rolling>,
+  pending: Pending,
+}
+
+impl Iterator for Matches<'_> {
+  type Item = Match;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let block_size = self.table.block_size;
+
+    loop {
+      if let Some(found) = self.pending.next(self.table) {
+        return Some(found);
+      }
+
+      let rolling = match self.rolling.as_mut() {
+        Some(rolling) => {
+          let new = *self.data.get(self.offset + block_size)?;
+
+          rolling.rotate(self.data[self.offset], new);
+          self.offset += 1;
+          rolling
+        }
+        None => {
+          let window = self.data.get(self.offset..self.offset + block_size)?;
+
+          self.rolling.get_or_insert(Rolling::new(window))
+        }
+      };
+
+      self.pending = self.table.candidates(rolling.finish(), self.offset as u64);
+    }
+  }
+}
+
+/// Iterator over the matches in the data read from a reader, see
+/// [`BlockTable::read_find`].
+#[derive(Debug)]
+pub struct ReadMatches<'a, R> {
+  table: &'a BlockTable,
+  reader: R,
+  buf: Vec<u8>,
+  base: u64,
+  start: usize,
+  eof: bool,
+  rolling: Option<Rolling>,
+  pending: Pending,
+}
+
+impl<R: Read> ReadMatches<'_, R> {
+  /// Reads until `len` bytes past the window start are buffered, returns `false` if the
+  /// reader is exhausted first.
+  fn fill(&mut self, len: usize) -> Result<bool> {
+    if self.buf.len() < self.start + len && self.start >= READ_SIZE {
+      self.buf.drain(..self.start);
+      self.base += self.start as u64;
+      self.start = 0;
+    }
+
+    while self.buf.len() < self.start + len && !self.eof {
+      let filled = self.buf.len();
+      self.buf.resize(filled + READ_SIZE, 0);
+
+      match self.reader.read(&mut self.buf[filled..]) {
+        Ok(n) => {
+          self.buf.truncate(filled + n);
+          self.eof = n == 0;
+        }
+        Err(err) => {
+          self.buf.truncate(filled);
+
+          if err.kind() != ErrorKind::Interrupted {
+            return Err(err);
+          }
+        }
+      }
+    }
+
+    Ok(self.buf.len() >= self.start + len)
+  }
+}
+
+impl<R: Read> Iterator for ReadMatches<'_, R> {
+  type Item = Result<Match>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let block_size = self.table.block_size;
+
+    loop {
+      if let Some(found) = self.pending.next(self.table) {
+        return Some(Ok(found));
+      }
+
+      let len = block_size + self.rolling.is_some() as usize;
+      match self.fill(len) {
+        Ok(true) => {}
+        Ok(false) => return None,
+        Err(err) => return Some(Err(err)),
+      }
+
+      let window = &self.buf[self.start..self.start + len];
+      let rolling = match self.rolling.as_mut() {
+        Some(rolling) => {
+          rolling.rotate(window[0], window[block_size]);
+          self.start += 1;
+          rolling
+        }
+        None => self.rolling.get_or_insert(Rolling::new(window)),
+      };
+
+      let offset = self.base + self.start as u64;
+      self.pending = self.table.candidates(rolling.finish(), offset);
+    }
+  }
+}
+
+/// Adler-32 over a fixed size window.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Rolling {
+  a: u32,
+  b: u32,
+  len: u32,
+}
+
+impl Rolling {
+  pub(crate) fn new(window: &[u8]) -> Self {
+    let mut hash = Adler32::new();
+    hash.write(window);
+
+    let checksum = hash.finish();
+
+    Self {
+      a: checksum & 0xffff,
+      b: checksum >> 16,
+      len: (window.len() as u32) % MOD,
+    }
+  }
+
+  /// Removes `old` from the start of the window and appends `new` to the end.
+  #[inline(always)]
+  pub(crate) fn rotate(&mut self, old: u8, new: u8) {
+    let (old, new) = (u32::from(old), u32::from(new));
+
+    self.a = (self.a + MOD - old + new) % MOD;
+    self.b = (self.b + MOD - self.len * old % MOD + self.a + MOD - 1) % MOD;
+  }
+
+  #[inline(always)]
+  pub(crate) fn finish(&self) -> u32 {
+    (self.b << 16) | self.a
+  }
+}
+
+/// Returns the bucket of `checksum`, Fibonacci hashing spreads the clustered `a` and `b`
+/// halves over the high bits.
+#[inline(always)]
+fn bucket(checksum: u32, shift: u32) -> usize {
+  (checksum.wrapping_mul(0x9e37_79b9) >> shift) as usize
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{BlockTable, Match, Rolling};
+  use crate::block_checksums;
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  #[test]
+  fn rolling() {
+    let mut random = [0; 1024];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut rolling = Rolling::new(&random[..100]);
+    for i in 1..random.len() - 100 {
+      rolling.rotate(random[i - 1], random[i + 99]);
+
+      let expected = adler2::adler32_slice(&random[i..i + 100]);
+      assert_eq!(rolling.finish(), expected, "offset({})", i);
+    }
+  }
+
+  #[test]
+  fn get() {
+    let table = BlockTable::new(4, &[7, 3, 7, 1 << 31, 3]);
+
+    assert_eq!(table.get(7).collect::<Vec<_>>(), vec![0, 2]);
+    assert_eq!(table.get(3).collect::<Vec<_>>(), vec![1, 4]);
+    assert_eq!(table.get(1 << 31).collect::<Vec<_>>(), vec![3]);
+    assert_eq!(table.get(4).count(), 0);
+    assert_eq!(BlockTable::new(4, &[]).get(0).count(), 0);
+  }
+
+  #[test]
+  fn find() {
+    let (data, table) = random_table();
+
+    assert_eq!(
+      table.find(&data).collect::<Vec<_>>(),
+      reference(&data, &table)
+    );
+    assert_eq!(table.find(&data[..10]).count(), 0);
+  }
+
+  #[test]
+  fn read_find() {
+    let (data, table) = random_table();
+
+    let matches: Vec<_> = table
+      .read_find(Trickle(&data))
+      .map(Result::unwrap)
+      .collect();
+
+    assert_eq!(matches, reference(&data, &table));
+  }
+
+  /// Returns random data containing known blocks at arbitrary offsets, along with a
+  /// table of those blocks and some duplicates.
+  fn random_table() -> (Vec<u8>, BlockTable) {
+    let mut random = vec![0; 1024 * 256];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let known = &random[..1024 * 8];
+    let mut checksums = [0; 1024 * 8 / 100 + 1];
+    block_checksums(known, 100, &mut checksums);
+    checksums[5] = checksums[17];
+
+    let mut data = random[1024 * 8..].to_vec();
+    for (i, &offset) in [7, 70_000, 150_001, 200_123].iter().enumerate() {
+      let block = &known[i * 1000..i * 1000 + 300];
+      data[offset..offset + block.len()].copy_from_slice(block);
+    }
+
+    (data, BlockTable::new(100, &checksums))
+  }
+
+  fn reference(data: &[u8], table: &BlockTable) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    for (offset, window) in data.windows(table.block_size()).enumerate() {
+      let checksum = adler2::adler32_slice(window);
+
+      for block in table.get(checksum) {
+        let offset = offset as u64;
+        matches.push(Match { offset, block });
+      }
+    }
+
+    assert!(matches.len() >= 8);
+    matches
+  }
+
+  /// Reader returning at most 4099 bytes per read.
+  struct Trickle<'a>(&'a [u8]);
+
+  impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      let n = buf.len().min(self.0.len()).min(4099);
+
+      buf[..n].copy_from_slice(&self.0[..n]);
+      self.0 = &self.0[n..];
+
+      Ok(n)
+    }
+  }
+}
@@ -0,0 +1,651 @@
+//! zlib stream framing.
+//!
+//! A zlib stream (RFC 1950) is a two byte header, an optional preset dictionary id, raw
+//! deflate data and the big-endian Adler-32 of the uncompressed data. This module builds
+//! and parses the framing around the deflate data, compression itself is left to a raw
+//! deflate implementation. [`StoredEncoder`] writes valid streams without compressing.
+//!
+//! # Example
+//! ```rust
+//! use simd_adler32::zlib::{trailer, verify, Header};
+//!
+//! // "hello" compressed by zlib.
+//! let stream = [
+//!   0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15,
+//! ];
+//!
+//! let (header, len) = Header::parse(&stream).unwrap();
+//! assert_eq!((header, len), (Header::default(), 2));
+//!
+//! let end = [stream[9], stream[10], stream[11], stream[12]];
+//! assert!(verify(end, b"hello"));
+//! assert_eq!(trailer(0x062c_0215), end);
+//! ```
+use crate::Adler32;
+use core::fmt;
+
+/// Compression method of deflate streams.
+pub const DEFLATE: u8 = 8;
+
+/// Compression level hint stored in the header, it is not needed for decompression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+  /// Fastest algorithm.
+  Fastest,
+  /// Fast algorithm.
+  Fast,
+  /// Default algorithm.
+  Default,
+  /// Maximum compression, slowest algorithm.
+  Maximum,
+}
+
+/// zlib stream header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+  window_bits: u8,
+  level: Level,
+  dict_id: Option<u32>,
+}
+
+impl Header {
+  /// Constructs a new `Header` for a deflate stream with a window of `2^window_bits`
+  /// bytes.
+  ///
+  /// # Panics
+  /// Panics if `window_bits` is not within `8..=15`.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::zlib::{Header, Level};
+  ///
+  /// let header = Header::new(15, Level::Maximum);
+  /// let mut buf = [0; 6];
+  ///
+  /// assert_eq!(header.encode(&mut buf), 2);
+  /// assert_eq!(buf[..2], [0x78, 0xda]);
+  /// ```
+  pub fn new(window_bits: u8, level: Level) -> Self {
+    assert!(
+      (8..=15).contains(&window_bits),
+      "Window bits must be within 8..=15"
+    );
+
+    Self {
+      window_bits,
+      level,
+      dict_id: None,
+    }
+  }
+
+  /// Sets the FDICT flag with the id of the preset `dictionary`.
+  pub fn with_dictionary(self, dictionary: &[u8]) -> Self {
+    self.with_dict_id(dict_id(dictionary))
+  }
+
+  /// Sets the FDICT flag with preset dictionary id `dict_id`.
+  pub fn with_dict_id(mut self, dict_id: u32) -> Self {
+    self.dict_id = Some(dict_id);
+    self
+  }
+
+  /// Returns the base two logarithm of the window size.
+  pub fn window_bits(&self) -> u8 {
+    self.window_bits
+  }
+
+  /// Returns the compression level hint.
+  pub fn level(&self) -> Level {
+    self.level
+  }
+
+  /// Returns the preset dictionary id if the FDICT flag is set.
+  pub fn dict_id(&self) -> Option<u32> {
+    self.dict_id
+  }
+
+  /// Returns the length of the encoded header, six bytes with a dictionary id and two
+  /// bytes without.
+  pub fn encoded_len(&self) -> usize {
+    if self.dict_id.is_some() {
+      6
+    } else {
+      2
+    }
+  }
+
+  /// Encodes the header into the start of `out` and returns its length.
+  ///
+  /// # Panics
+  /// Panics if `out` is shorter than [`encoded_len`](Self::encoded_len).
+  pub fn encode(&self, out: &mut [u8]) -> usize {
+    let len = self.encoded_len();
+    assert!(out.len() >= len, "Output is shorter than the header");
+
+    let cmf = ((self.window_bits - 8) << 4) | DEFLATE;
+    let level = match self.level {
+      Level::Fastest => 0,
+      Level::Fast => 1,
+      Level::Default => 2,
+      Level::Maximum => 3,
+    };
+
+    let flg = (level << 6) | (u8::from(self.dict_id.is_some()) << 5);
+    let check = 31 - ((u16::from(cmf) << 8) | u16::from(flg)) % 31;
+
+    out[0] = cmf;
+    out[1] = flg | (check % 31) as u8;
+
+    if let Some(dict_id) = self.dict_id {
+      out[2..6].copy_from_slice(&dict_id.to_be_bytes());
+    }
+
+    len
+  }
+
+  /// Parses the header at the start of `data` and returns it with its length.
+  ///
+  /// # Examples
+  /// ```rust
+  /// use simd_adler32::zlib::{Header, HeaderError};
+  ///
+  /// let (header, len) = Header::parse(&[0x78, 0x01]).unwrap();
+  /// assert_eq!((header.window_bits(), len), (15, 2));
+  ///
+  /// assert_eq!(Header::parse(&[0x78, 0x02]), Err(HeaderError::Check));
+  /// ```
+  pub fn parse(data: &[u8]) -> Result<(Self, usize), HeaderError> {
+    if data.len() < 2 {
+      return Err(HeaderError::Truncated);
+    }
+
+    let (cmf, flg) = (data[0], data[1]);
+    if ((u16::from(cmf) << 8) | u16::from(flg)) % 31 != 0 {
+      return Err(HeaderError::Check);
+    }
+    if cmf & 0x0f != DEFLATE {
+      return Err(HeaderError::Method(cmf & 0x0f));
+    }
+    if cmf >> 4 > 7 {
+      return Err(HeaderError::WindowSize(cmf >> 4));
+    }
+
+    let level = match flg >> 6 {
+      0 => Level::Fastest,
+      1 => Level::Fast,
+      2 => Level::Default,
+      _ => Level::Maximum,
+    };
+
+    let mut header = Self::new((cmf >> 4) + 8, level);
+    if flg & 0x20 != 0 {
+      if data.len() < 6 {
+        return Err(HeaderError::Truncated);
+      }
+
+      header.dict_id = Some(u32::from_be_bytes([data[2], data[3], data[4], data[5]]));
+    }
+
+    Ok((header, header.encoded_len()))
+  }
+
+  /// Writes the header into `writer`.
+  #[cfg(feature = "std")]
+  pub fn write_to<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+    let mut buf = [0; 6];
+    let len = self.encode(&mut buf);
+
+    writer.write_all(&buf[..len])
+  }
+
+  /// Reads a header from `reader`, invalid headers are reported as
+  /// [`InvalidData`](std::io::ErrorKind::InvalidData) errors.
+  #[cfg(feature = "std")]
+  pub fn read_from<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+    let mut buf = [0; 6];
+    reader.read_exact(&mut buf[..2])?;
+
+    let invalid = |err| std::io::Error::new(std::io::ErrorKind::InvalidData, err);
+    match Self::parse(&buf[..2]) {
+      Ok((header, _)) => Ok(header),
+      Err(HeaderError::Truncated) => {
+        reader.read_exact(&mut buf[2..])?;
+        Self::parse(&buf).map(|(header, _)| header).map_err(invalid)
+      }
+      Err(err) => Err(invalid(err)),
+    }
+  }
+}
+
+impl Default for Header {
+  /// Returns the header zlib writes by default, a 32 KiB window and the default level.
+  fn default() -> Self {
+    Self::new(15, Level::Default)
+  }
+}
+
+/// Error returned when parsing an invalid zlib header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+  /// The data ends before the header.
+  Truncated,
+  /// The FCHECK bits do not match.
+  Check,
+  /// The compression method is not deflate.
+  Method(u8),
+  /// The window size exceeds 32 KiB.
+  WindowSize(u8),
+}
+
+impl fmt::Display for HeaderError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HeaderError::Truncated => write!(f, "truncated zlib header"),
+      HeaderError::Check => write!(f, "invalid zlib header check bits"),
+      HeaderError::Method(method) => {
+        write!(f, "unsupported compression method {}", method)
+      }
+      HeaderError::WindowSize(info) => write!(f, "invalid window size {}", info),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HeaderError {}
+
+/// Compute the DICTID of a preset dictionary, the Adler-32 of `dictionary`.
+pub fn dict_id(dictionary: &[u8]) -> u32 {
+  let mut hash = Adler32::new();
+
+  hash.write(dictionary);
+  hash.finish()
+}
+
+/// Returns the trailer of a stream whose uncompressed data has Adler-32 `checksum`.
+pub fn trailer(checksum: u32) -> [u8; 4] {
+  checksum.to_be_bytes()
+}
+
+/// Writes the trailer of a stream whose uncompressed data has Adler-32 `checksum`.
+#[cfg(feature = "std")]
+pub fn write_trailer<W: std::io::Write>(
+  writer: &mut W,
+  checksum: u32,
+) -> std::io::Result<()> {
+  writer.write_all(&trailer(checksum))
+}
+
+/// Returns `true` if `trailer` matches the uncompressed `data`.
+pub fn verify(trailer: [u8; 4], data: &[u8]) -> bool {
+  verify_chunks(trailer, Some(data))
+}
+
+/// Returns `true` if `trailer` matches the uncompressed data split into `chunks`.
+///
+/// # Examples
+/// ```rust
+/// use simd_adler32::zlib::{trailer, verify_chunks};
+///
+/// let checksum = simd_adler32::adler32(&&b"hello"[..]);
+/// assert!(verify_chunks(trailer(checksum), vec![&b"he"[..], b"llo"]));
+/// ```
+pub fn verify_chunks<'a, I: IntoIterator<Item = &'a [u8]>>(
+  trailer: [u8; 4],
+  chunks: I,
+) -> bool {
+  let mut hash = Adler32::new();
+
+  for chunk in chunks {
+    hash.write(chunk);
+  }
+
+  hash.finish() == u32::from_be_bytes(trailer)
+}
+
+/// Returns `true` if `trailer` matches the uncompressed data read from `reader`.
+#[cfg(feature = "std")]
+pub fn verify_reader<R: std::io::Read>(
+  trailer: [u8; 4],
+  reader: &mut R,
+) -> std::io::Result<bool> {
+  crate::read::adler32(reader).map(|checksum| checksum == u32::from_be_bytes(trailer))
+}
+
+/// Largest stored block length.
+#[cfg(feature = "std")]
+const STORED_MAX: usize = 65535;
+
+/// Writer encoding data as a zlib stream of stored deflate blocks.
+///
+/// Data is not compressed, every 65535 bytes become a stored block with a five byte
+/// block header. The stream is completed by [`finish`](Self::finish), or when the
+/// encoder is dropped with errors ignored.
+///
+/// # Examples
+/// ```rust
+/// use std::io::Write;
+/// use simd_adler32::zlib::StoredEncoder;
+///
+/// let mut encoder = StoredEncoder::new(Vec::new());
+/// encoder.write_all(b"hello").unwrap();
+///
+/// let stream = encoder.finish().unwrap();
+/// assert_eq!(
+///   stream,
+///   [0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x06, 0x2c, 0x02, 0x15]
+/// );
+/// ```
+#[cfg(feature = "std")]
+pub struct StoredEncoder<W: std::io::Write> {
+  writer: Option<W>,
+  header: Header,
+  header_written: bool,
+  finished: bool,
+  buf: Vec<u8>,
+  hash: Adler32,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> StoredEncoder<W> {
+  /// Constructs a new `StoredEncoder` writing the stream into `writer`.
+  pub fn new(writer: W) -> Self {
+    Self {
+      writer: Some(writer),
+      header: Header::new(15, Level::Fastest),
+      header_written: false,
+      finished: false,
+      buf: Vec::with_capacity(STORED_MAX),
+      hash: Adler32::new(),
+    }
+  }
+
+  /// Returns a reference to the underlying writer.
+  pub fn get_ref(&self) -> &W {
+    self.writer.as_ref().unwrap()
+  }
+
+  /// Writes the buffered data as the final block followed by the trailer, and returns
+  /// the underlying writer.
+  pub fn finish(mut self) -> std::io::Result<W> {
+    // Set first so a failed write isn't repeated on drop.
+    self.finished = true;
+    self.write_block(true)?;
+
+    let mut writer = self.writer.take().unwrap();
+    write_trailer(&mut writer, self.hash.finish())?;
+
+    Ok(writer)
+  }
+
+  /// Writes the buffered data as a stored block.
+  fn write_block(&mut self, last: bool) -> std::io::Result<()> {
+    let writer = self.writer.as_mut().unwrap();
+
+    if !self.header_written {
+      self.header.write_to(writer)?;
+      self.header_written = true;
+    }
+
+    let len = (self.buf.len() as u16).to_le_bytes();
+    let nlen = (!(self.buf.len() as u16)).to_le_bytes();
+
+    writer.write_all(&[u8::from(last), len[0], len[1], nlen[0], nlen[1]])?;
+    writer.write_all(&self.buf)?;
+
+    self.buf.clear();
+    Ok(())
+  }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> std::io::Write for StoredEncoder<W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    if self.buf.len() == STORED_MAX {
+      self.write_block(false)?;
+    }
+
+    let len = buf.len().min(STORED_MAX - self.buf.len());
+    self.buf.extend_from_slice(&buf[..len]);
+    self.hash.write(&buf[..len]);
+
+    Ok(len)
+  }
+
+  /// Writes the buffered data as a non-final block and flushes the underlying writer.
+  fn flush(&mut self) -> std::io::Result<()> {
+    if !self.buf.is_empty() {
+      self.write_block(false)?;
+    }
+
+    self.writer.as_mut().unwrap().flush()
+  }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Drop for StoredEncoder<W> {
+  fn drop(&mut self) {
+    if !self.finished {
+      let _ = self.write_block(true);
+      let checksum = self.hash.finish();
+      let _ = write_trailer(self.writer.as_mut().unwrap(), checksum);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{dict_id, trailer, verify, verify_chunks, Header, HeaderError, Level};
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+  #[cfg(feature = "std")]
+  use {super::StoredEncoder, std::io::Write};
+
+  #[test]
+  fn known_headers() {
+    for &(level, flg) in &[
+      (Level::Fastest, 0x01),
+      (Level::Fast, 0x5e),
+      (Level::Default, 0x9c),
+      (Level::Maximum, 0xda),
+    ] {
+      let mut buf = [0; 6];
+
+      assert_eq!(Header::new(15, level).encode(&mut buf), 2);
+      assert_eq!(buf[..2], [0x78, flg]);
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let levels = [Level::Fastest, Level::Fast, Level::Default, Level::Maximum];
+
+    for window_bits in 8..=15 {
+      for &level in &levels {
+        for &dict_id in &[None, Some(0xdead_beef)] {
+          let mut header = Header::new(window_bits, level);
+          if let Some(dict_id) = dict_id {
+            header = header.with_dict_id(dict_id);
+          }
+
+          let mut buf = [0; 6];
+          let len = header.encode(&mut buf);
+
+          assert_eq!(((u16::from(buf[0]) << 8) | u16::from(buf[1])) % 31, 0);
+          assert_eq!(Header::parse(&buf[..len]), Ok((header, len)));
+          #[cfg(feature = "std")]
+          assert_eq!(Header::read_from(&mut &buf[..len]).unwrap(), header);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn invalid_headers() {
+    assert_eq!(Header::parse(&[]), Err(HeaderError::Truncated));
+    assert_eq!(Header::parse(&[0x78]), Err(HeaderError::Truncated));
+    assert_eq!(Header::parse(&[0x78, 0xbb, 0]), Err(HeaderError::Truncated));
+    assert_eq!(Header::parse(&[0x78, 0x9d]), Err(HeaderError::Check));
+    assert_eq!(Header::parse(&[0x79, 0x18]), Err(HeaderError::Method(9)));
+    assert_eq!(
+      Header::parse(&[0x88, 0x98]),
+      Err(HeaderError::WindowSize(8))
+    );
+
+    #[cfg(feature = "std")]
+    {
+      let err = Header::read_from(&mut &[0x78, 0x9d][..]).unwrap_err();
+      assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+  }
+
+  #[test]
+  fn dictionary() {
+    let header = Header::default().with_dictionary(b"dictionary");
+    let mut buf = [0; 6];
+
+    assert_eq!(header.encode(&mut buf), 6);
+    assert_eq!(buf[..2], [0x78, 0xbb]);
+    assert_eq!(buf[2..], adler2::adler32_slice(b"dictionary").to_be_bytes());
+    assert_eq!(dict_id(b"dictionary"), adler2::adler32_slice(b"dictionary"));
+  }
+
+  #[test]
+  fn trailers() {
+    let mut random = [0; 1024 * 100];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let end = trailer(adler2::adler32_slice(&random));
+    let mut corrupted = random;
+    corrupted[5000] ^= 1;
+
+    assert!(verify(end, &random));
+    assert!(!verify(end, &corrupted));
+    assert!(verify_chunks(end, random.chunks(777)));
+
+    #[cfg(feature = "std")]
+    {
+      assert!(super::verify_reader(end, &mut &random[..]).unwrap());
+      assert!(!super::verify_reader(end, &mut &corrupted[..]).unwrap());
+    }
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn stored() {
+    let mut random = vec![0; 1024 * 300];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    for &len in &[0, 1, 65535, 65536, 1024 * 300] {
+      let mut encoder = StoredEncoder::new(Vec::new());
+      encoder.write_all(&random[..len]).unwrap();
+
+      let stream = encoder.finish().unwrap();
+      assert_eq!(inflate_stored(&stream), &random[..len], "len({})", len);
+
+      // Header, trailer and five bytes per block.
+      let blocks = random[..len].chunks(65535).len().max(1);
+      assert_eq!(stream.len(), 2 + len + 5 * blocks + 4, "len({})", len);
+    }
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn stored_writes() {
+    let mut random = vec![0; 1024 * 200];
+    SmallRng::from_entropy().fill(&mut random[..]);
+
+    let mut encoder = StoredEncoder::new(Vec::new());
+    for (i, chunk) in random.chunks(1000).enumerate() {
+      encoder.write_all(chunk).unwrap();
+
+      if i % 50 == 0 {
+        encoder.flush().unwrap();
+      }
+    }
+
+    assert_eq!(inflate_stored(&encoder.finish().unwrap()), random);
+
+    let mut stream = Vec::new();
+    StoredEncoder::new(&mut stream)
+      .write_all(b"dropped")
+      .unwrap();
+    assert_eq!(inflate_stored(&stream), b"dropped");
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn stored_finish_error() {
+    let mut stream = Vec::new();
+    let writer = FailOnce {
+      out: &mut stream,
+      calls: 0,
+      fail: 3,
+    };
+
+    let mut encoder = StoredEncoder::new(writer);
+    encoder.write_all(b"hello").unwrap();
+    assert!(encoder.finish().is_err());
+
+    // The header and block header went through, drop doesn't write the block again.
+    assert_eq!(stream.len(), 2 + 5);
+  }
+
+  /// Writer failing its `fail`th write call.
+  #[cfg(feature = "std")]
+  struct FailOnce<'a> {
+    out: &'a mut Vec<u8>,
+    calls: usize,
+    fail: usize,
+  }
+
+  #[cfg(feature = "std")]
+  impl std::io::Write for FailOnce<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.calls += 1;
+      if self.calls == self.fail {
+        return Err(std::io::ErrorKind::Other.into());
+      }
+
+      self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  /// Decodes a zlib stream of stored blocks, panicking on anything else.
+  #[cfg(feature = "std")]
+  fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+    let (header, mut pos) = Header::parse(stream).unwrap();
+    assert_eq!(header.dict_id(), None);
+
+    let mut data = Vec::new();
+    loop {
+      let block = &stream[pos..pos + 5];
+      assert_eq!(block[0] & !1, 0, "Not a stored block");
+
+      let len = u16::from_le_bytes([block[1], block[2]]);
+      let nlen = u16::from_le_bytes([block[3], block[4]]);
+      assert_eq!(len, !nlen);
+
+      pos += 5;
+      data.extend_from_slice(&stream[pos..pos + len as usize]);
+      pos += len as usize;
+
+      if block[0] & 1 == 1 {
+        break;
+      }
+    }
+
+    let end = [
+      stream[pos],
+      stream[pos + 1],
+      stream[pos + 2],
+      stream[pos + 3],
+    ];
+    assert_eq!(stream.len(), pos + 4);
+    assert!(verify(end, &data));
+
+    data
+  }
+}